@@ -87,6 +87,11 @@ fn main() -> anyhow::Result<()> {
 
     let out_dir = env::var_os("OUT_DIR").unwrap();
     let dest_path = Path::new(&out_dir).join("robot_secret.rs");
+    let location_secrets: Vec<&str> = cloud_cfg
+        .location_secrets
+        .iter()
+        .map(|s| s.secret.as_str())
+        .collect();
     let robot_decl = vec![
         const_declaration!(
             #[allow(clippy::redundant_static_lifetimes, dead_code)]
@@ -108,6 +113,10 @@ fn main() -> anyhow::Result<()> {
             #[allow(clippy::redundant_static_lifetimes, dead_code)]
             ROBOT_NAME = robot_name
         ),
+        format!(
+            "#[allow(dead_code)]\nconst LOCATION_SECRETS: &[&str] = &{:?};",
+            location_secrets
+        ),
     ]
     .join("\n");
     fs::write(&dest_path, robot_decl).unwrap();
@@ -29,6 +29,7 @@ use micro_rdk::common::robot::LocalRobot;
 use micro_rdk::common::robot::ResourceType;
 use micro_rdk::esp32::server::{CloudConfig, Esp32Server};
 use micro_rdk::esp32::tls::Esp32TlsServerConfig;
+use micro_rdk::proto::app::v1::LocationSecret;
 use micro_rdk::proto::common::v1::ResourceName;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -48,26 +49,34 @@ fn main() -> anyhow::Result<()> {
     let robot = {
         use esp_idf_hal::adc::config::Config;
         use esp_idf_hal::adc::{self, AdcChannelDriver, AdcDriver, Atten11dB};
-        use esp_idf_hal::gpio::PinDriver;
+        use esp_idf_hal::gpio::{IOPin, PinDriver, Pull};
         use esp_idf_hal::ledc;
         use esp_idf_hal::ledc::config::TimerConfig;
         use esp_idf_hal::units::FromValueType;
+        use micro_rdk::common::base::WheeledBaseGeometry;
+        use micro_rdk::common::servo::ServoPulseConfig;
         use micro_rdk::esp32::analog::Esp32AnalogReader;
         use micro_rdk::esp32::base::Esp32WheelBase;
         use micro_rdk::esp32::board::EspBoard;
         use micro_rdk::esp32::motor::MotorEsp32;
+        use micro_rdk::esp32::pin::Esp32GpioPin;
+        use micro_rdk::esp32::pwm::Esp32PwmPin;
+        use micro_rdk::esp32::servo::Esp32Servo;
         #[cfg(feature = "camera")]
         let camera = {
             Esp32Camera::new();
             camera.setup()?;
             Arc::new(Mutex::new(camera))
         };
-        // // let mut encoder = Esp32Encoder::new(
-        // //     periph.pins.gpio15.into_input()?.degrade(),
-        // //     periph.pins.gpio14.into_input()?.degrade(),
-        // // );
-        // // encoder.setup_pcnt()?;
-        // // encoder.start()?;
+        // An encoder is its own resource, motors and odometry share it:
+        // let encoder: Arc<Mutex<dyn Encoder>> =
+        //     Arc::new(Mutex::new(Esp32QuadratureEncoder::new(
+        //         esp_idf_sys::pcnt_unit_t_PCNT_UNIT_0,
+        //         PinDriver::input(periph.pins.gpio26)?,
+        //         PinDriver::input(periph.pins.gpio27)?,
+        //         100,
+        //     )?));
+        // let m1 = MotorEncodedEsp32::new(a, b, chan, encoder.clone(), 100.0, 400);
         let tconf = TimerConfig::default().frequency(10.kHz().into());
         let timer = Arc::new(ledc::LedcTimerDriver::new(periph.ledc.timer0, &tconf).unwrap());
         let chan = ledc::LedcDriver::new(
@@ -80,6 +89,7 @@ fn main() -> anyhow::Result<()> {
             PinDriver::output(periph.pins.gpio33)?,
             PinDriver::output(periph.pins.gpio32)?,
             chan,
+            100.0,
         );
         let chan2 = ledc::LedcDriver::new(
             periph.ledc.channel2,
@@ -91,9 +101,14 @@ fn main() -> anyhow::Result<()> {
             PinDriver::output(periph.pins.gpio13)?,
             PinDriver::output(periph.pins.gpio12)?,
             chan2,
+            100.0,
         );
 
-        let pins = vec![PinDriver::output(periph.pins.gpio15)?];
+        // gpio0 is wired to the boot button, pressing it pulls the pin low
+        let pins = vec![
+            Esp32GpioPin::output(periph.pins.gpio15.downgrade())?.with_alias("led"),
+            Esp32GpioPin::input(periph.pins.gpio0.downgrade(), Pull::Up)?.with_alias("button"),
+        ];
         let adc1 = Rc::new(RefCell::new(AdcDriver::new(
             periph.adc1,
             &Config::new().calibration(true),
@@ -104,14 +119,55 @@ fn main() -> anyhow::Result<()> {
         let chan: AdcChannelDriver<_, Atten11dB<adc::ADC1>> =
             AdcChannelDriver::new(periph.pins.gpio35)?;
         let r2 = Esp32AnalogReader::new("A2".to_string(), chan, adc1.clone());
+        // LEDC timer 1 is used by the camera
+        let pwm_conf = TimerConfig::default().frequency(1.kHz().into());
+        let pwm_timer = Arc::new(ledc::LedcTimerDriver::new(periph.ledc.timer2, &pwm_conf)?);
+        let pwm = Esp32PwmPin::new(
+            17,
+            ledc::LedcDriver::new(
+                periph.ledc.channel4,
+                pwm_timer,
+                periph.pins.gpio17,
+                &pwm_conf,
+            )?,
+            esp_idf_sys::ledc_timer_t_LEDC_TIMER_2,
+            1000,
+        );
+        // servos expect 50Hz, the 14 bits resolution gives 1.2us steps
+        let servo_conf = ServoPulseConfig::default();
+        let servo_timer_conf = TimerConfig::default()
+            .frequency(servo_conf.frequency_hz().Hz().into())
+            .resolution(ledc::Resolution::Bits14);
+        let servo_timer = Arc::new(ledc::LedcTimerDriver::new(
+            periph.ledc.timer3,
+            &servo_timer_conf,
+        )?);
+        let servo = Esp32Servo::new(
+            ledc::LedcDriver::new(
+                periph.ledc.channel5,
+                servo_timer,
+                periph.pins.gpio16,
+                &servo_timer_conf,
+            )?,
+            servo_conf,
+        );
         let b = EspBoard::new(
             pins,
             vec![Rc::new(RefCell::new(r)), Rc::new(RefCell::new(r2))],
+            vec![pwm],
         );
+        // the first motor drives gpio32 and gpio33, the analog readers use gpio34 and gpio35
+        let analog_discovery = b.analog_discovery(&[32, 33, 34, 35]);
         let motor = Arc::new(Mutex::new(m1));
         let m2 = Arc::new(Mutex::new(m2));
         let board = Arc::new(Mutex::new(b));
-        let base = Arc::new(Mutex::new(Esp32WheelBase::new(motor.clone(), m2.clone())));
+        // 70mm wheels 260mm apart, the motors spin at 100rpm at full power
+        let geometry = WheeledBaseGeometry::new(220.0, 260.0, 100.0)?;
+        let base = Arc::new(Mutex::new(Esp32WheelBase::new(
+            motor.clone(),
+            m2.clone(),
+            geometry,
+        )));
 
         let mut res: micro_rdk::common::robot::ResourceMap = HashMap::with_capacity(5);
         res.insert(
@@ -150,6 +206,15 @@ fn main() -> anyhow::Result<()> {
             },
             ResourceType::Base(base),
         );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "servo".to_string(),
+                name: "servo".to_string(),
+            },
+            ResourceType::Servo(Arc::new(Mutex::new(servo))),
+        );
         #[cfg(feature = "camera")]
         res.insert(
             ResourceName {
@@ -160,7 +225,11 @@ fn main() -> anyhow::Result<()> {
             },
             ResourceType::Camera(camera),
         );
-        LocalRobot::new(res)
+        let mut robot = LocalRobot::new(res);
+        robot.add_discovery(Box::new(analog_discovery));
+        #[cfg(feature = "camera")]
+        robot.add_discovery(Box::new(micro_rdk::esp32::camera::Esp32CameraDiscovery));
+        robot
     };
 
     #[cfg(feature = "qemu")]
@@ -261,7 +330,24 @@ fn main() -> anyhow::Result<()> {
 
     let mut cloud_cfg = CloudConfig::new(ROBOT_NAME, LOCAL_FQDN, FQDN, ROBOT_ID, ROBOT_SECRET);
     cloud_cfg.set_tls_config(cfg);
-    let esp32_srv = Esp32Server::new(robot, cloud_cfg);
+    cloud_cfg.set_location_secrets(
+        LOCATION_SECRETS
+            .iter()
+            .map(|secret| LocationSecret {
+                secret: secret.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+    );
+    let mut esp32_srv = Esp32Server::new(robot, cloud_cfg);
+    esp32_srv.set_method_timeout(
+        "/viam.component.board.v1.BoardService/",
+        Duration::from_secs(1),
+    );
+    esp32_srv.set_method_timeout(
+        "/viam.component.camera.v1.CameraService/",
+        Duration::from_secs(5),
+    );
     esp32_srv.start(ip)?;
     Ok(())
 }
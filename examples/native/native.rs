@@ -6,12 +6,14 @@ use micro_rdk::common::robot::LocalRobot;
 use micro_rdk::common::robot::ResourceType;
 use micro_rdk::native::server::{CloudConfig, NativeServer};
 use micro_rdk::native::tls::NativeTlsServerConfig;
+use micro_rdk::proto::app::v1::LocationSecret;
 use micro_rdk::proto::common::v1::ResourceName;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 fn main() -> anyhow::Result<()> {
     simple_logger::SimpleLogger::new()
@@ -29,9 +31,19 @@ fn main() -> anyhow::Result<()> {
         use micro_rdk::common::board::FakeBoard;
         #[cfg(feature = "camera")]
         use micro_rdk::common::camera::FakeCamera;
+        use micro_rdk::common::encoder::FakeEncoder;
+        use micro_rdk::common::gantry::FakeGantry;
+        use micro_rdk::common::gripper::FakeGripper;
         use micro_rdk::common::motor::FakeMotor;
+        use micro_rdk::common::movement_sensor::FakeMovementSensor;
+        use micro_rdk::common::servo::FakeServo;
         let motor = Arc::new(Mutex::new(FakeMotor::new()));
         let base = Arc::new(Mutex::new(FakeBase::new()));
+        let servo = Arc::new(Mutex::new(FakeServo::new()));
+        let movement_sensor = Arc::new(Mutex::new(FakeMovementSensor::default()));
+        let encoder = Arc::new(Mutex::new(FakeEncoder::new(100)));
+        let gripper = Arc::new(Mutex::new(FakeGripper::new()));
+        let gantry = Arc::new(Mutex::new(FakeGantry::new(300.0)));
         let board = Arc::new(Mutex::new(FakeBoard::new(vec![
             Rc::new(RefCell::new(FakeAnalogReader::new("A1".to_string(), 10))),
             Rc::new(RefCell::new(FakeAnalogReader::new("A2".to_string(), 20))),
@@ -66,6 +78,51 @@ fn main() -> anyhow::Result<()> {
             },
             ResourceType::Base(base),
         );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "servo".to_string(),
+                name: "servo".to_string(),
+            },
+            ResourceType::Servo(servo),
+        );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "movement_sensor".to_string(),
+                name: "ms".to_string(),
+            },
+            ResourceType::MovementSensor(movement_sensor),
+        );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "encoder".to_string(),
+                name: "encoder".to_string(),
+            },
+            ResourceType::Encoder(encoder),
+        );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "gripper".to_string(),
+                name: "gripper".to_string(),
+            },
+            ResourceType::Gripper(gripper),
+        );
+        res.insert(
+            ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: "gantry".to_string(),
+                name: "gantry".to_string(),
+            },
+            ResourceType::Gantry(gantry),
+        );
         #[cfg(feature = "camera")]
         res.insert(
             ResourceName {
@@ -91,7 +148,20 @@ fn main() -> anyhow::Result<()> {
     };
     let mut cloud_cfg = CloudConfig::new(ROBOT_NAME, LOCAL_FQDN, FQDN, ROBOT_ID, ROBOT_SECRET);
     cloud_cfg.set_tls_config(cfg);
-    let esp32_srv = NativeServer::new(robot, cloud_cfg);
+    cloud_cfg.set_location_secrets(
+        LOCATION_SECRETS
+            .iter()
+            .map(|secret| LocationSecret {
+                secret: secret.to_string(),
+                ..Default::default()
+            })
+            .collect(),
+    );
+    let mut esp32_srv = NativeServer::new(robot, cloud_cfg);
+    esp32_srv.set_method_timeout(
+        "/viam.component.board.v1.BoardService/",
+        Duration::from_secs(1),
+    );
     esp32_srv.start(ip)?;
     Ok(())
 }
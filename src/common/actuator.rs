@@ -0,0 +1,53 @@
+use std::sync::{Arc, Mutex};
+
+/// Common interface of every resource that can move, used to stop the whole robot at once
+pub trait Actuator {
+    fn is_moving(&self) -> anyhow::Result<bool>;
+    /// Polled while waiting for a motion started by a command to be over. Unlike `is_moving`
+    /// it may act on the hardware, e.g. to stop the parts of the actuator that are done.
+    fn poll_moving(&mut self) -> anyhow::Result<bool> {
+        self.is_moving()
+    }
+    fn stop(&mut self) -> anyhow::Result<()>;
+    /// Stop with the `extra` parameters sent by the client, implementations not taking
+    /// parameters just stop
+    fn stop_with_extra(&mut self, _extra: Option<&prost_types::Struct>) -> anyhow::Result<()> {
+        self.stop()
+    }
+}
+
+impl<L> Actuator for Mutex<L>
+where
+    L: ?Sized + Actuator,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        self.lock().unwrap().is_moving()
+    }
+    fn poll_moving(&mut self) -> anyhow::Result<bool> {
+        self.get_mut().unwrap().poll_moving()
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.get_mut().unwrap().stop()
+    }
+    fn stop_with_extra(&mut self, extra: Option<&prost_types::Struct>) -> anyhow::Result<()> {
+        self.get_mut().unwrap().stop_with_extra(extra)
+    }
+}
+
+impl<A> Actuator for Arc<Mutex<A>>
+where
+    A: ?Sized + Actuator,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        self.lock().unwrap().is_moving()
+    }
+    fn poll_moving(&mut self) -> anyhow::Result<bool> {
+        self.lock().unwrap().poll_moving()
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.lock().unwrap().stop()
+    }
+    fn stop_with_extra(&mut self, extra: Option<&prost_types::Struct>) -> anyhow::Result<()> {
+        self.lock().unwrap().stop_with_extra(extra)
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{common::error::GrpcError, proto::rpc::v1::Credentials};
+use hmac::{Hmac, Mac};
+use hyper::http::HeaderValue;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Credentials type used by clients authenticating with the robot part secret
+pub static CREDENTIALS_TYPE_ROBOT_SECRET: &str = "robot-secret";
+/// Credentials type used by clients authenticating with one of the location secrets
+pub static CREDENTIALS_TYPE_ROBOT_LOCATION_SECRET: &str = "robot-location-secret";
+
+/// Default validity of the tokens handed out by `Authenticator::authenticate`
+static TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// Header of every token, they are all HS256 JWTs
+static JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// entity the token was issued to
+    sub: String,
+    /// issuance time in seconds since the UNIX epoch
+    iat: u64,
+    /// expiration time in seconds since the UNIX epoch
+    exp: u64,
+}
+
+/// Checks the credentials of clients calling AuthService/Authenticate against the robot secret
+/// and the location secrets, and the bearer tokens it issued to them on every other RPC.
+/// Tokens are signed with the robot secret so they don't survive a change of secret.
+pub struct Authenticator {
+    robot_secret: String,
+    location_secrets: Vec<String>,
+    token_lifetime: Duration,
+}
+
+impl Authenticator {
+    pub fn new(robot_secret: &str, location_secrets: Vec<String>) -> Self {
+        Authenticator {
+            robot_secret: robot_secret.to_owned(),
+            location_secrets,
+            token_lifetime: TOKEN_LIFETIME,
+        }
+    }
+
+    /// Set how long a token stays valid after being issued
+    pub fn set_token_lifetime(&mut self, lifetime: Duration) {
+        self.token_lifetime = lifetime
+    }
+
+    /// Issue a token to `entity` if `creds` match one of the secrets
+    pub fn authenticate(&self, entity: &str, creds: &Credentials) -> Result<String, GrpcError> {
+        let valid = if creds.r#type == CREDENTIALS_TYPE_ROBOT_SECRET {
+            secret_matches(&self.robot_secret, &creds.payload)
+        } else if creds.r#type == CREDENTIALS_TYPE_ROBOT_LOCATION_SECRET {
+            self.location_secrets
+                .iter()
+                .any(|secret| secret_matches(secret, &creds.payload))
+        } else {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "unsupported credentials type {}",
+                creds.r#type
+            )));
+        };
+        if !valid {
+            return Err(GrpcError::RpcUnauthenticated(
+                "invalid credentials".to_string(),
+            ));
+        }
+        let iat = now()?;
+        let claims = Claims {
+            sub: entity.to_owned(),
+            iat,
+            exp: iat + self.token_lifetime.as_secs(),
+        };
+        let claims = serde_json::to_vec(&claims)
+            .map_err(|e| GrpcError::RpcInternal(format!("cannot encode claims: {}", e)))?;
+        let mut token = encode_segment(JWT_HEADER.as_bytes());
+        token.push('.');
+        token.push_str(&encode_segment(&claims));
+        let signature = self.mac(token.as_bytes()).finalize().into_bytes();
+        token.push('.');
+        token.push_str(&encode_segment(&signature));
+        Ok(token)
+    }
+
+    /// Check the `authorization` header of a request carries a valid bearer token
+    pub fn verify(&self, authorization: Option<&HeaderValue>) -> Result<(), GrpcError> {
+        let token = authorization
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| GrpcError::RpcUnauthenticated("missing bearer token".to_string()))?;
+        let invalid = || GrpcError::RpcUnauthenticated("invalid token".to_string());
+        let (signed, signature) = token.rsplit_once('.').ok_or_else(invalid)?;
+        let (header, claims) = signed.split_once('.').ok_or_else(invalid)?;
+        let signature = decode_segment(signature).ok_or_else(invalid)?;
+        self.mac(signed.as_bytes())
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+        // the signature is only ever produced by `authenticate`, it is still worth making sure
+        // nobody handed us a token using another algorithm with the same key
+        if decode_segment(header).as_deref() != Some(JWT_HEADER.as_bytes()) {
+            return Err(invalid());
+        }
+        let claims: Claims = decode_segment(claims)
+            .and_then(|claims| serde_json::from_slice(&claims).ok())
+            .ok_or_else(invalid)?;
+        if claims.exp <= now()? {
+            return Err(GrpcError::RpcUnauthenticated("token expired".to_string()));
+        }
+        Ok(())
+    }
+
+    fn mac(&self, data: &[u8]) -> HmacSha256 {
+        // HMAC accepts keys of any length
+        let mut mac = HmacSha256::new_from_slice(self.robot_secret.as_bytes()).unwrap();
+        mac.update(data);
+        mac
+    }
+}
+
+/// Compare a secret with what a client sent without leaking where they differ, empty secrets
+/// never match
+fn secret_matches(secret: &str, payload: &str) -> bool {
+    if secret.is_empty() || secret.len() != payload.len() {
+        return false;
+    }
+    secret
+        .bytes()
+        .zip(payload.bytes())
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+fn encode_segment(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_segment(segment: &str) -> Option<Vec<u8>> {
+    base64::decode_config(segment, base64::URL_SAFE_NO_PAD).ok()
+}
+
+fn now() -> Result<u64, GrpcError> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .map_err(|_| GrpcError::RpcInternal("system time is before the UNIX epoch".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials(r#type: &str, payload: &str) -> Credentials {
+        Credentials {
+            r#type: r#type.to_string(),
+            payload: payload.to_string(),
+        }
+    }
+
+    fn bearer(token: &str) -> HeaderValue {
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap()
+    }
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new("robot secret", vec!["location secret".to_string()])
+    }
+
+    #[test]
+    fn issued_tokens_are_valid() {
+        let auth = authenticator();
+        for creds in [
+            credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "robot secret"),
+            credentials(CREDENTIALS_TYPE_ROBOT_LOCATION_SECRET, "location secret"),
+        ] {
+            let token = auth.authenticate("client", &creds).unwrap();
+            assert_eq!(auth.verify(Some(&bearer(&token))), Ok(()));
+        }
+    }
+
+    #[test]
+    fn wrong_credentials_are_rejected() {
+        let auth = authenticator();
+        assert!(matches!(
+            auth.authenticate(
+                "client",
+                &credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "location secret")
+            ),
+            Err(GrpcError::RpcUnauthenticated(_))
+        ));
+        assert!(matches!(
+            auth.authenticate("client", &credentials("api-key", "robot secret")),
+            Err(GrpcError::RpcInvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn missing_or_malformed_tokens_are_rejected() {
+        let auth = authenticator();
+        let token = auth
+            .authenticate(
+                "client",
+                &credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "robot secret"),
+            )
+            .unwrap();
+        for authorization in [
+            None,
+            Some(HeaderValue::from_str(&token).unwrap()),
+            Some(bearer("not a token")),
+            Some(bearer(token.rsplit_once('.').unwrap().0)),
+        ] {
+            assert!(matches!(
+                auth.verify(authorization.as_ref()),
+                Err(GrpcError::RpcUnauthenticated(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let other = Authenticator::new("other secret", vec![]);
+        let token = other
+            .authenticate(
+                "client",
+                &credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "other secret"),
+            )
+            .unwrap();
+        assert_eq!(
+            authenticator().verify(Some(&bearer(&token))),
+            Err(GrpcError::RpcUnauthenticated("invalid token".to_string()))
+        );
+    }
+
+    #[test]
+    fn tampered_claims_are_rejected() {
+        let auth = authenticator();
+        let token = auth
+            .authenticate(
+                "client",
+                &credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "robot secret"),
+            )
+            .unwrap();
+        let mut segments: Vec<_> = token.split('.').map(str::to_string).collect();
+        let claims = Claims {
+            sub: "client".to_string(),
+            iat: 0,
+            exp: u64::MAX,
+        };
+        segments[1] = encode_segment(&serde_json::to_vec(&claims).unwrap());
+        assert_eq!(
+            auth.verify(Some(&bearer(&segments.join(".")))),
+            Err(GrpcError::RpcUnauthenticated("invalid token".to_string()))
+        );
+    }
+
+    #[test]
+    fn tokens_with_another_header_are_rejected() {
+        let auth = authenticator();
+        let claims = Claims {
+            sub: "client".to_string(),
+            iat: 0,
+            exp: u64::MAX,
+        };
+        let mut token = encode_segment(br#"{"alg":"none"}"#);
+        token.push('.');
+        token.push_str(&encode_segment(&serde_json::to_vec(&claims).unwrap()));
+        let signature = auth.mac(token.as_bytes()).finalize().into_bytes();
+        token.push('.');
+        token.push_str(&encode_segment(&signature));
+        assert_eq!(
+            auth.verify(Some(&bearer(&token))),
+            Err(GrpcError::RpcUnauthenticated("invalid token".to_string()))
+        );
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let mut auth = authenticator();
+        auth.set_token_lifetime(Duration::ZERO);
+        let token = auth
+            .authenticate(
+                "client",
+                &credentials(CREDENTIALS_TYPE_ROBOT_SECRET, "robot secret"),
+            )
+            .unwrap();
+        assert_eq!(
+            auth.verify(Some(&bearer(&token))),
+            Err(GrpcError::RpcUnauthenticated("token expired".to_string()))
+        );
+    }
+}
@@ -1,20 +1,132 @@
 #![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
 use crate::common::status::Status;
 use crate::proto::common::v1::Vector3;
 use log::*;
 use std::collections::BTreeMap;
 use std::sync::Mutex;
+use std::time::Duration;
 
-pub trait Base: Status {
+pub trait Base: Status + Actuator + DoCommand {
     fn set_power(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()>;
-    fn stop(&mut self) -> anyhow::Result<()>;
+    /// Drive `distance_mm` straight at `mm_per_sec`, backward if one of them is negative.
+    /// Returns the duration after which the base should be stopped if it cannot tell when it
+    /// got there, otherwise it stops moving by itself.
+    fn move_straight(
+        &mut self,
+        distance_mm: i64,
+        mm_per_sec: f64,
+    ) -> anyhow::Result<Option<Duration>>;
+    /// Turn in place by `angle_deg` (counterclockwise when positive) at `degs_per_sec`. Returns
+    /// the duration after which the base should be stopped if it cannot tell when it got there.
+    fn spin(&mut self, angle_deg: f64, degs_per_sec: f64) -> anyhow::Result<Option<Duration>>;
+    /// Move at `lin.y` mm/sec while turning at `ang.z` deg/sec until stopped
+    fn set_velocity(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()>;
 }
 
-pub struct FakeBase {}
+/// Dimensions of a base with a motor on its left and right wheels, turns linear and angular
+/// speeds into wheel rpm and revolutions
+#[derive(Clone, Copy, Debug)]
+pub struct WheeledBaseGeometry {
+    wheel_circumference_mm: f64,
+    width_mm: f64,
+    max_rpm: f64,
+}
+
+impl WheeledBaseGeometry {
+    /// `width_mm` is the distance between the left and right wheels, `max_rpm` the speed of
+    /// the slowest motor at full power
+    pub fn new(wheel_circumference_mm: f64, width_mm: f64, max_rpm: f64) -> anyhow::Result<Self> {
+        if wheel_circumference_mm <= 0.0 || width_mm <= 0.0 || max_rpm <= 0.0 {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "wheel circumference, width and max rpm should be strictly positive".to_string(),
+            )
+            .into());
+        }
+        Ok(WheeledBaseGeometry {
+            wheel_circumference_mm,
+            width_mm,
+            max_rpm,
+        })
+    }
+    pub fn wheel_circumference_mm(&self) -> f64 {
+        self.wheel_circumference_mm
+    }
+    pub fn width_mm(&self) -> f64 {
+        self.width_mm
+    }
+    /// Rpm and revolutions of both wheels driving `distance_mm` at `mm_per_sec`
+    pub fn straight(&self, distance_mm: i64, mm_per_sec: f64) -> anyhow::Result<(f64, f64)> {
+        if mm_per_sec == 0.0 {
+            return Err(
+                GrpcError::RpcInvalidArgument("mm_per_sec cannot be zero".to_string()).into(),
+            );
+        }
+        Ok((
+            self.clamp_rpm(self.rpm(mm_per_sec)),
+            distance_mm as f64 / self.wheel_circumference_mm,
+        ))
+    }
+    /// Rpm and revolutions of the left and right wheels spinning the base counterclockwise by
+    /// `angle_deg` at `degs_per_sec`, the left wheel goes backward while the right one goes
+    /// forward
+    pub fn spin(
+        &self,
+        angle_deg: f64,
+        degs_per_sec: f64,
+    ) -> anyhow::Result<((f64, f64), (f64, f64))> {
+        if degs_per_sec == 0.0 {
+            return Err(
+                GrpcError::RpcInvalidArgument("degs_per_sec cannot be zero".to_string()).into(),
+            );
+        }
+        let rpm = self.clamp_rpm(self.rpm(self.wheel_arc_mm(degs_per_sec)));
+        let revolutions = self.wheel_arc_mm(angle_deg) / self.wheel_circumference_mm;
+        Ok(((rpm, -revolutions), (rpm, revolutions)))
+    }
+    /// Rpm of the left and right wheels moving at `mm_per_sec` while turning at
+    /// `degs_per_sec`, both are scaled down when one of them would exceed the max rpm so the
+    /// base still follows the same arc
+    pub fn velocity(&self, mm_per_sec: f64, degs_per_sec: f64) -> (f64, f64) {
+        let turn = self.wheel_arc_mm(degs_per_sec);
+        let left = self.rpm(mm_per_sec - turn);
+        let right = self.rpm(mm_per_sec + turn);
+        let fastest = left.abs().max(right.abs());
+        if fastest > self.max_rpm {
+            let scale = self.max_rpm / fastest;
+            return (left * scale, right * scale);
+        }
+        (left, right)
+    }
+    /// Distance covered by a wheel when the base spins by `angle_deg`
+    fn wheel_arc_mm(&self, angle_deg: f64) -> f64 {
+        angle_deg.to_radians() * self.width_mm / 2.0
+    }
+    fn rpm(&self, mm_per_sec: f64) -> f64 {
+        mm_per_sec / self.wheel_circumference_mm * 60.0
+    }
+    fn clamp_rpm(&self, rpm: f64) -> f64 {
+        rpm.clamp(-self.max_rpm, self.max_rpm)
+    }
+}
+
+/// Time taken to cover `distance` at `speed`, used by bases without feedback
+fn travel_time(distance: f64, speed: f64) -> anyhow::Result<Duration> {
+    if speed == 0.0 {
+        return Err(GrpcError::RpcInvalidArgument("speed cannot be zero".to_string()).into());
+    }
+    Ok(Duration::from_secs_f64((distance / speed).abs()))
+}
+
+pub struct FakeBase {
+    moving: bool,
+}
 
 impl FakeBase {
     pub fn new() -> Self {
-        FakeBase {}
+        FakeBase { moving: false }
     }
 }
 impl Default for FakeBase {
@@ -30,8 +142,20 @@ where
     fn set_power(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()> {
         self.get_mut().unwrap().set_power(lin, ang)
     }
-    fn stop(&mut self) -> anyhow::Result<()> {
-        self.get_mut().unwrap().stop()
+    fn move_straight(
+        &mut self,
+        distance_mm: i64,
+        mm_per_sec: f64,
+    ) -> anyhow::Result<Option<Duration>> {
+        self.get_mut()
+            .unwrap()
+            .move_straight(distance_mm, mm_per_sec)
+    }
+    fn spin(&mut self, angle_deg: f64, degs_per_sec: f64) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().spin(angle_deg, degs_per_sec)
+    }
+    fn set_velocity(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()> {
+        self.get_mut().unwrap().set_velocity(lin, ang)
     }
 }
 
@@ -41,23 +165,184 @@ impl Base for FakeBase {
             "Setting power following lin vec {:?} and ang {:?}",
             lin, ang
         );
+        self.moving = lin.y != 0.0 || ang.z != 0.0;
         Ok(())
     }
+    fn move_straight(
+        &mut self,
+        distance_mm: i64,
+        mm_per_sec: f64,
+    ) -> anyhow::Result<Option<Duration>> {
+        info!("Moving straight {}mm at {}mm/sec", distance_mm, mm_per_sec);
+        let dur = travel_time(distance_mm as f64, mm_per_sec)?;
+        self.moving = true;
+        Ok(Some(dur))
+    }
+    fn spin(&mut self, angle_deg: f64, degs_per_sec: f64) -> anyhow::Result<Option<Duration>> {
+        info!("Spinning {}deg at {}deg/sec", angle_deg, degs_per_sec);
+        let dur = travel_time(angle_deg, degs_per_sec)?;
+        self.moving = true;
+        Ok(Some(dur))
+    }
+    fn set_velocity(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()> {
+        info!(
+            "Setting velocity following lin vec {:?} and ang {:?}",
+            lin, ang
+        );
+        self.moving = lin.y != 0.0 || ang.z != 0.0;
+        Ok(())
+    }
+}
+
+impl Actuator for FakeBase {
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(self.moving)
+    }
     fn stop(&mut self) -> anyhow::Result<()> {
         info!("Stopping base");
+        self.moving = false;
         Ok(())
     }
 }
 
+impl DoCommand for FakeBase {}
+
 impl Status for FakeBase {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         let mut bt = BTreeMap::new();
         bt.insert(
             "is_moving".to_string(),
             prost_types::Value {
-                kind: Some(prost_types::value::Kind::BoolValue(false)),
+                kind: Some(prost_types::value::Kind::BoolValue(self.moving)),
             },
         );
         Ok(Some(prost_types::Struct { fields: bt }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::motor::go_for_math;
+
+    // a base spinning by one degree moves each wheel by one mm
+    const WIDTH_MM: f64 = 360.0 / std::f64::consts::PI;
+
+    fn geometry() -> WheeledBaseGeometry {
+        WheeledBaseGeometry::new(200.0, WIDTH_MM, 100.0).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_wheel(actual: (f64, f64), rpm: f64, revolutions: f64) {
+        assert_close(actual.0, rpm);
+        assert_close(actual.1, revolutions);
+    }
+
+    fn is_code(err: anyhow::Error, code: fn(&GrpcError) -> bool) -> bool {
+        err.downcast_ref::<GrpcError>().is_some_and(code)
+    }
+
+    #[test]
+    fn geometry_should_be_strictly_positive() {
+        for (circumference, width, max_rpm) in [(0.0, 1.0, 1.0), (1.0, -1.0, 1.0), (1.0, 1.0, 0.0)]
+        {
+            let err = WheeledBaseGeometry::new(circumference, width, max_rpm).unwrap_err();
+            assert!(is_code(err, |e| matches!(
+                e,
+                GrpcError::RpcFailedPrecondition(_)
+            )));
+        }
+    }
+
+    #[test]
+    fn straight_turns_the_distance_into_revolutions() {
+        let (rpm, revolutions) = geometry().straight(1000, 100.0).unwrap();
+        assert_close(rpm, 30.0);
+        assert_close(revolutions, 5.0);
+        // both wheels run at 30% of the max rpm for the time taken to drive 1m at 10cm/s
+        let (pct, dur) = go_for_math(100.0, rpm, revolutions).unwrap();
+        assert_close(pct, 0.3);
+        assert_close(dur.unwrap().as_secs_f64(), 10.0);
+
+        let (_, revolutions) = geometry().straight(-400, 100.0).unwrap();
+        assert_close(revolutions, -2.0);
+    }
+
+    #[test]
+    fn straight_clamps_to_the_max_rpm() {
+        assert_close(geometry().straight(1000, 1000.0).unwrap().0, 100.0);
+        assert_close(geometry().straight(1000, -1000.0).unwrap().0, -100.0);
+    }
+
+    #[test]
+    fn zero_speeds_are_rejected() {
+        let invalid = |e: &GrpcError| matches!(e, GrpcError::RpcInvalidArgument(_));
+        assert!(is_code(
+            geometry().straight(1000, 0.0).unwrap_err(),
+            invalid
+        ));
+        assert!(is_code(geometry().spin(90.0, 0.0).unwrap_err(), invalid));
+    }
+
+    #[test]
+    fn spin_turns_the_angle_into_wheel_revolutions() {
+        let (left, right) = geometry().spin(90.0, 45.0).unwrap();
+        assert_wheel(left, 13.5, -0.45);
+        assert_wheel(right, 13.5, 0.45);
+    }
+
+    #[test]
+    fn spin_drives_the_wheels_in_opposite_directions() {
+        // counterclockwise, the left wheel goes backward
+        let (left, right) = geometry().spin(90.0, 45.0).unwrap();
+        assert!(go_for_math(100.0, left.0, left.1).unwrap().0 < 0.0);
+        assert!(go_for_math(100.0, right.0, right.1).unwrap().0 > 0.0);
+        // clockwise, the right wheel goes backward
+        let (left, right) = geometry().spin(-90.0, 45.0).unwrap();
+        assert!(go_for_math(100.0, left.0, left.1).unwrap().0 > 0.0);
+        assert!(go_for_math(100.0, right.0, right.1).unwrap().0 < 0.0);
+        // so does a negative speed
+        let (left, right) = geometry().spin(90.0, -45.0).unwrap();
+        assert!(go_for_math(100.0, left.0, left.1).unwrap().0 > 0.0);
+        assert!(go_for_math(100.0, right.0, right.1).unwrap().0 < 0.0);
+    }
+
+    #[test]
+    fn spin_clamps_to_the_max_rpm() {
+        let (left, right) = geometry().spin(90.0, 10000.0).unwrap();
+        assert_wheel(left, 100.0, -0.45);
+        assert_wheel(right, 100.0, 0.45);
+    }
+
+    #[test]
+    fn velocity_turns_by_speeding_up_the_outer_wheel() {
+        let (left, right) = geometry().velocity(100.0, 0.0);
+        assert_close(left, 30.0);
+        assert_close(right, 30.0);
+        let (left, right) = geometry().velocity(0.0, 45.0);
+        assert_close(left, -13.5);
+        assert_close(right, 13.5);
+        let (left, right) = geometry().velocity(100.0, -20.0);
+        assert_close(left, 36.0);
+        assert_close(right, 24.0);
+    }
+
+    #[test]
+    fn velocity_scales_both_wheels_down_to_the_max_rpm() {
+        let (left, right) = geometry().velocity(1000.0, 0.0);
+        assert_close(left, 100.0);
+        assert_close(right, 100.0);
+        // 60 and 180 rpm, the arc is kept
+        let (left, right) = geometry().velocity(400.0, 200.0);
+        assert_close(left, 100.0 / 3.0);
+        assert_close(right, 100.0);
+    }
+}
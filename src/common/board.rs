@@ -1,18 +1,226 @@
 #![allow(dead_code)]
 use crate::common::analog::AnalogReader;
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
 use crate::common::status::Status;
 use crate::proto::common;
 use core::cell::RefCell;
 use log::*;
 use std::collections::{BTreeMap, HashMap};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
+/// Edge seen by a digital interrupt
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tick {
+    /// whether the edge is rising
+    pub high: bool,
+    /// time of the edge in nanoseconds since boot, 0 unless the interrupt records timestamps
+    pub nanos: u64,
+}
+
+/// Called on every edge. Callbacks never run in the interrupt handler, which only queues the
+/// edges, they are called later on from a task by `BasicDigitalInterrupt::dispatch`.
+pub type DigitalInterruptCallback = Box<dyn Fn(Tick) + Send + Sync>;
+
+pub trait DigitalInterrupt {
+    fn name(&self) -> String;
+    /// Number of rising edges seen so far
+    fn value(&self) -> anyhow::Result<i64>;
+    fn add_callback(&mut self, callback: DigitalInterruptCallback) -> anyhow::Result<()>;
+}
+
+/// Number of edges queued for the callbacks, edges coming faster than they are dispatched are
+/// still counted but not passed to the callbacks once the queue is full
+const TICK_QUEUE_SIZE: usize = 64;
+
+/// Queue of edges with one producer (the interrupt handler) and one consumer, it only uses
+/// atomics so it never blocks nor allocates
+struct TickQueue {
+    // edge times shifted left by one, the lowest bit is the level
+    slots: [AtomicU64; TICK_QUEUE_SIZE],
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+impl TickQueue {
+    fn new() -> Self {
+        TickQueue {
+            slots: std::array::from_fn(|_| AtomicU64::new(0)),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+    /// Returns false when the queue is full
+    fn push(&self, tick: Tick) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) == TICK_QUEUE_SIZE {
+            return false;
+        }
+        self.slots[head % TICK_QUEUE_SIZE]
+            .store(tick.nanos << 1 | tick.high as u64, Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+    fn pop(&self) -> Option<Tick> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail == self.head.load(Ordering::Acquire) {
+            return None;
+        }
+        let slot = self.slots[tail % TICK_QUEUE_SIZE].load(Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(Tick {
+            high: slot & 1 == 1,
+            nanos: slot >> 1,
+        })
+    }
+}
+
+/// Counting logic shared by digital interrupts. `tick` is safe to call from an interrupt
+/// handler, the edges it records are passed to the callbacks by `dispatch`, which has to be
+/// called from a task.
+pub struct BasicDigitalInterrupt {
+    count: AtomicI64,
+    high: AtomicBool,
+    last_tick: AtomicU64,
+    timestamps: bool,
+    queue: TickQueue,
+    callbacks: Mutex<Vec<DigitalInterruptCallback>>,
+}
+
+impl BasicDigitalInterrupt {
+    /// With `timestamps` every tick records the time of the edge
+    pub fn new(timestamps: bool) -> Self {
+        BasicDigitalInterrupt {
+            count: AtomicI64::new(0),
+            high: AtomicBool::new(false),
+            last_tick: AtomicU64::new(0),
+            timestamps,
+            queue: TickQueue::new(),
+            callbacks: Mutex::new(Vec::new()),
+        }
+    }
+    pub fn records_timestamps(&self) -> bool {
+        self.timestamps
+    }
+    /// Record an edge, returns false if the callbacks will miss it because too many edges are
+    /// waiting to be dispatched
+    pub fn tick(&self, high: bool, nanos: u64) -> bool {
+        if high {
+            self.count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.high.store(high, Ordering::Relaxed);
+        let nanos = if self.timestamps { nanos } else { 0 };
+        self.last_tick.store(nanos, Ordering::Relaxed);
+        self.queue.push(Tick { high, nanos })
+    }
+    /// Pass the edges recorded since the last call to the callbacks
+    pub fn dispatch(&self) {
+        let callbacks = self.callbacks.lock().unwrap();
+        while let Some(tick) = self.queue.pop() {
+            for callback in callbacks.iter() {
+                callback(tick);
+            }
+        }
+    }
+    pub fn value(&self) -> i64 {
+        self.count.load(Ordering::Relaxed)
+    }
+    /// Level after the last edge
+    pub fn is_high(&self) -> bool {
+        self.high.load(Ordering::Relaxed)
+    }
+    /// Time of the last edge, 0 unless timestamps are recorded
+    pub fn last_tick(&self) -> u64 {
+        self.last_tick.load(Ordering::Relaxed)
+    }
+    pub fn add_callback(&self, callback: DigitalInterruptCallback) {
+        self.callbacks.lock().unwrap().push(callback);
+    }
+}
+
+/// Digital interrupt driven by calling `tick`, for testing
+pub struct FakeDigitalInterrupt {
+    name: String,
+    inner: BasicDigitalInterrupt,
+}
+
+impl FakeDigitalInterrupt {
+    pub fn new(name: String, timestamps: bool) -> Self {
+        FakeDigitalInterrupt {
+            name,
+            inner: BasicDigitalInterrupt::new(timestamps),
+        }
+    }
+    /// Record an edge and call the callbacks right away
+    pub fn tick(&self, high: bool, nanos: u64) {
+        self.inner.tick(high, nanos);
+        self.inner.dispatch();
+    }
+}
+
+impl DigitalInterrupt for FakeDigitalInterrupt {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn value(&self) -> anyhow::Result<i64> {
+        Ok(self.inner.value())
+    }
+    fn add_callback(&mut self, callback: DigitalInterruptCallback) -> anyhow::Result<()> {
+        self.inner.add_callback(callback);
+        Ok(())
+    }
+}
+
+/// Value of every interrupt as reported in the status of a board
+pub(crate) fn digital_interrupts_status(
+    interrupts: &[Rc<RefCell<dyn DigitalInterrupt>>],
+) -> BTreeMap<String, prost_types::Value> {
+    interrupts
+        .iter()
+        .map(|i| {
+            let borrowed = i.borrow();
+            (
+                borrowed.name(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+                        fields: BTreeMap::from([(
+                            "value".to_string(),
+                            prost_types::Value {
+                                kind: Some(prost_types::value::Kind::NumberValue(
+                                    borrowed.value().unwrap_or(0) as f64,
+                                )),
+                            },
+                        )]),
+                    })),
+                },
+            )
+        })
+        .collect()
+}
+
 pub struct FakeBoard {
     analogs: Vec<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>>,
+    interrupts: Vec<Rc<RefCell<dyn DigitalInterrupt>>>,
+    /// duty cycle and frequency last set on every PWM pin
+    pwms: HashMap<i32, (f64, u64)>,
+    aliases: HashMap<String, i32>,
+}
+
+/// Parse a pin sent as a string holding its number
+pub fn parse_pin(pin: &str) -> anyhow::Result<i32> {
+    pin.parse::<i32>()
+        .map_err(|_| GrpcError::RpcInvalidArgument(format!("invalid pin {}", pin)).into())
 }
-pub trait Board: Status {
+
+pub trait Board: Status + DoCommand {
+    /// Number of the pin called `pin` by a request, either its number or one of its aliases
+    /// (e.g. "led")
+    fn resolve_pin(&self, pin: &str) -> anyhow::Result<i32> {
+        parse_pin(pin)
+    }
     fn set_gpio_pin_level(&mut self, pin: i32, is_high: bool) -> anyhow::Result<()>;
     fn get_board_status(&self) -> anyhow::Result<common::v1::BoardStatus>;
     fn get_gpio_level(&self, pin: i32) -> anyhow::Result<bool>;
@@ -20,15 +228,41 @@ pub trait Board: Status {
         &self,
         name: String,
     ) -> anyhow::Result<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>>;
+    fn get_digital_interrupt_by_name(
+        &self,
+        name: String,
+    ) -> anyhow::Result<Rc<RefCell<dyn DigitalInterrupt>>>;
+    /// Duty cycle (between 0 and 1) of the PWM signal on `pin`
+    fn get_pwm_duty(&self, pin: i32) -> anyhow::Result<f64>;
+    fn set_pwm_duty(&mut self, pin: i32, duty_cycle_pct: f64) -> anyhow::Result<()>;
+    fn get_pwm_frequency(&self, pin: i32) -> anyhow::Result<u64>;
+    fn set_pwm_frequency(&mut self, pin: i32, frequency_hz: u64) -> anyhow::Result<()>;
 }
 
 impl FakeBoard {
     pub fn new(analogs: Vec<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>>) -> Self {
-        FakeBoard { analogs }
+        FakeBoard {
+            analogs,
+            interrupts: Vec::new(),
+            pwms: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+    pub fn add_digital_interrupt(&mut self, interrupt: Rc<RefCell<dyn DigitalInterrupt>>) {
+        self.interrupts.push(interrupt)
+    }
+    pub fn add_pin_alias(&mut self, alias: String, pin: i32) {
+        self.aliases.insert(alias, pin);
     }
 }
 
 impl Board for FakeBoard {
+    fn resolve_pin(&self, pin: &str) -> anyhow::Result<i32> {
+        match self.aliases.get(pin) {
+            Some(pin) => Ok(*pin),
+            None => parse_pin(pin),
+        }
+    }
     fn set_gpio_pin_level(&mut self, pin: i32, is_high: bool) -> anyhow::Result<()> {
         info!("set pin {} to {}", pin, is_high);
         Ok(())
@@ -47,6 +281,15 @@ impl Board for FakeBoard {
                 },
             );
         });
+        self.interrupts.iter().for_each(|i| {
+            let borrowed = i.borrow();
+            b.digital_interrupts.insert(
+                borrowed.name(),
+                common::v1::DigitalInterruptStatus {
+                    value: borrowed.value().unwrap_or(0),
+                },
+            );
+        });
         Ok(b) //component::board::v1::StatusResponse { status: Some(b) }
     }
     fn get_gpio_level(&self, pin: i32) -> anyhow::Result<bool> {
@@ -59,11 +302,44 @@ impl Board for FakeBoard {
     ) -> anyhow::Result<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>> {
         match self.analogs.iter().find(|a| a.borrow().name() == name) {
             Some(reader) => Ok(reader.clone()),
-            None => Err(anyhow::anyhow!("couldn't find analog reader {}", name)),
+            None => {
+                Err(GrpcError::RpcNotFound(format!("couldn't find analog reader {}", name)).into())
+            }
+        }
+    }
+    fn get_digital_interrupt_by_name(
+        &self,
+        name: String,
+    ) -> anyhow::Result<Rc<RefCell<dyn DigitalInterrupt>>> {
+        match self.interrupts.iter().find(|i| i.borrow().name() == name) {
+            Some(interrupt) => Ok(interrupt.clone()),
+            None => Err(GrpcError::RpcNotFound(format!(
+                "couldn't find digital interrupt {}",
+                name
+            ))
+            .into()),
         }
     }
+    fn get_pwm_duty(&self, pin: i32) -> anyhow::Result<f64> {
+        Ok(self.pwms.get(&pin).map_or(0.0, |pwm| pwm.0))
+    }
+    fn set_pwm_duty(&mut self, pin: i32, duty_cycle_pct: f64) -> anyhow::Result<()> {
+        info!("set pin {} duty cycle to {}", pin, duty_cycle_pct);
+        self.pwms.entry(pin).or_insert((0.0, 0)).0 = duty_cycle_pct;
+        Ok(())
+    }
+    fn get_pwm_frequency(&self, pin: i32) -> anyhow::Result<u64> {
+        Ok(self.pwms.get(&pin).map_or(0, |pwm| pwm.1))
+    }
+    fn set_pwm_frequency(&mut self, pin: i32, frequency_hz: u64) -> anyhow::Result<()> {
+        info!("set pin {} frequency to {}Hz", pin, frequency_hz);
+        self.pwms.entry(pin).or_insert((0.0, 0)).1 = frequency_hz;
+        Ok(())
+    }
 }
 
+impl DoCommand for FakeBoard {}
+
 impl Status for FakeBoard {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         let mut bt = BTreeMap::new();
@@ -96,6 +372,17 @@ impl Status for FakeBoard {
                 },
             );
         }
+        let interrupts = digital_interrupts_status(&self.interrupts);
+        if !interrupts.is_empty() {
+            bt.insert(
+                "digital_interrupts".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+                        fields: interrupts,
+                    })),
+                },
+            );
+        }
         Ok(Some(prost_types::Struct { fields: bt }))
     }
 }
@@ -104,6 +391,9 @@ impl<A> Board for Arc<Mutex<A>>
 where
     A: ?Sized + Board,
 {
+    fn resolve_pin(&self, pin: &str) -> anyhow::Result<i32> {
+        self.lock().unwrap().resolve_pin(pin)
+    }
     fn get_board_status(&self) -> anyhow::Result<common::v1::BoardStatus> {
         self.lock().unwrap().get_board_status()
     }
@@ -119,4 +409,177 @@ where
     ) -> anyhow::Result<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>> {
         self.lock().unwrap().get_analog_reader_by_name(name)
     }
+    fn get_digital_interrupt_by_name(
+        &self,
+        name: String,
+    ) -> anyhow::Result<Rc<RefCell<dyn DigitalInterrupt>>> {
+        self.lock().unwrap().get_digital_interrupt_by_name(name)
+    }
+    fn get_pwm_duty(&self, pin: i32) -> anyhow::Result<f64> {
+        self.lock().unwrap().get_pwm_duty(pin)
+    }
+    fn set_pwm_duty(&mut self, pin: i32, duty_cycle_pct: f64) -> anyhow::Result<()> {
+        self.lock().unwrap().set_pwm_duty(pin, duty_cycle_pct)
+    }
+    fn get_pwm_frequency(&self, pin: i32) -> anyhow::Result<u64> {
+        self.lock().unwrap().get_pwm_frequency(pin)
+    }
+    fn set_pwm_frequency(&mut self, pin: i32, frequency_hz: u64) -> anyhow::Result<()> {
+        self.lock().unwrap().set_pwm_frequency(pin, frequency_hz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn digital_interrupts_count_rising_edges() {
+        let interrupt = FakeDigitalInterrupt::new("i1".to_string(), false);
+        for high in [true, false, true, true, false] {
+            interrupt.tick(high, 1000);
+        }
+        assert_eq!(interrupt.value().unwrap(), 3);
+        assert!(!interrupt.inner.is_high());
+    }
+
+    #[test]
+    fn digital_interrupts_record_timestamps_when_asked() {
+        let interrupt = BasicDigitalInterrupt::new(false);
+        interrupt.tick(true, 1000);
+        assert_eq!(interrupt.last_tick(), 0);
+        let interrupt = BasicDigitalInterrupt::new(true);
+        interrupt.tick(true, 1000);
+        assert_eq!(interrupt.last_tick(), 1000);
+    }
+
+    #[test]
+    fn digital_interrupts_call_back_on_every_edge() {
+        let mut interrupt = FakeDigitalInterrupt::new("i1".to_string(), true);
+        let rising = Arc::new(AtomicUsize::new(0));
+        let counted = rising.clone();
+        interrupt
+            .add_callback(Box::new(move |tick| {
+                if tick.high {
+                    counted.fetch_add(1, Ordering::Relaxed);
+                }
+            }))
+            .unwrap();
+        interrupt.tick(true, 1);
+        interrupt.tick(false, 2);
+        interrupt.tick(true, 3);
+        assert_eq!(rising.load(Ordering::Relaxed), 2);
+    }
+
+    type Recorded = Arc<Mutex<Vec<Tick>>>;
+
+    fn recording(interrupt: &BasicDigitalInterrupt) -> Recorded {
+        let ticks: Recorded = Arc::new(Mutex::new(Vec::new()));
+        let recorded = ticks.clone();
+        interrupt.add_callback(Box::new(move |tick| recorded.lock().unwrap().push(tick)));
+        ticks
+    }
+
+    #[test]
+    fn digital_interrupts_call_back_once_dispatched() {
+        let interrupt = BasicDigitalInterrupt::new(true);
+        let ticks = recording(&interrupt);
+        assert!(interrupt.tick(true, 10));
+        assert!(interrupt.tick(false, 20));
+        assert!(ticks.lock().unwrap().is_empty());
+        interrupt.dispatch();
+        assert_eq!(
+            *ticks.lock().unwrap(),
+            [
+                Tick {
+                    high: true,
+                    nanos: 10
+                },
+                Tick {
+                    high: false,
+                    nanos: 20
+                }
+            ]
+        );
+        interrupt.dispatch();
+        assert_eq!(ticks.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn digital_interrupts_count_the_edges_the_callbacks_miss() {
+        let interrupt = BasicDigitalInterrupt::new(true);
+        let ticks = recording(&interrupt);
+        for nanos in 0..TICK_QUEUE_SIZE as u64 {
+            assert!(interrupt.tick(true, nanos));
+        }
+        assert!(!interrupt.tick(true, 1000));
+        assert_eq!(interrupt.value(), TICK_QUEUE_SIZE as i64 + 1);
+        assert_eq!(interrupt.last_tick(), 1000);
+        interrupt.dispatch();
+        let nanos: Vec<u64> = ticks.lock().unwrap().iter().map(|t| t.nanos).collect();
+        assert_eq!(nanos, (0..TICK_QUEUE_SIZE as u64).collect::<Vec<_>>());
+        // the queue has room again
+        assert!(interrupt.tick(false, 2000));
+    }
+
+    #[test]
+    fn digital_interrupts_queue_wraps_around() {
+        let interrupt = BasicDigitalInterrupt::new(true);
+        let ticks = recording(&interrupt);
+        for nanos in 0..(3 * TICK_QUEUE_SIZE as u64) {
+            assert!(interrupt.tick(nanos % 2 == 0, nanos));
+            if nanos % 5 == 0 {
+                interrupt.dispatch();
+            }
+        }
+        interrupt.dispatch();
+        let ticks = ticks.lock().unwrap();
+        assert_eq!(ticks.len(), 3 * TICK_QUEUE_SIZE);
+        for (nanos, tick) in ticks.iter().enumerate() {
+            assert_eq!(tick.nanos, nanos as u64);
+            assert_eq!(tick.high, nanos % 2 == 0);
+        }
+    }
+
+    #[test]
+    fn fake_board_records_pwm_settings_per_pin() {
+        let mut board = FakeBoard::new(Vec::new());
+        assert_eq!(board.get_pwm_duty(4).unwrap(), 0.0);
+        assert_eq!(board.get_pwm_frequency(4).unwrap(), 0);
+        board.set_pwm_duty(4, 0.25).unwrap();
+        board.set_pwm_frequency(4, 1000).unwrap();
+        board.set_pwm_frequency(5, 50).unwrap();
+        // setting one doesn't reset the other
+        board.set_pwm_duty(4, 0.75).unwrap();
+        assert_eq!(board.get_pwm_duty(4).unwrap(), 0.75);
+        assert_eq!(board.get_pwm_frequency(4).unwrap(), 1000);
+        assert_eq!(board.get_pwm_duty(5).unwrap(), 0.0);
+        assert_eq!(board.get_pwm_frequency(5).unwrap(), 50);
+    }
+
+    #[test]
+    fn fake_board_finds_digital_interrupts_by_name() {
+        let mut board = FakeBoard::new(Vec::new());
+        let interrupt = Rc::new(RefCell::new(FakeDigitalInterrupt::new(
+            "i1".to_string(),
+            false,
+        )));
+        board.add_digital_interrupt(interrupt.clone());
+        interrupt.borrow().tick(true, 0);
+        let found = board
+            .get_digital_interrupt_by_name("i1".to_string())
+            .unwrap();
+        assert_eq!(found.borrow().value().unwrap(), 1);
+        let err = board
+            .get_digital_interrupt_by_name("i2".to_string())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<GrpcError>(),
+            Some(GrpcError::RpcNotFound(_))
+        ));
+        let status = board.get_board_status().unwrap();
+        assert_eq!(status.digital_interrupts["i1"].value, 1);
+    }
 }
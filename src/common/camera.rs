@@ -1,26 +1,28 @@
 #![allow(dead_code)]
 use std::sync::Mutex;
 
+use crate::common::generic::DoCommand;
+use crate::common::grpc::ChunkedBuffer;
 use crate::proto::component::camera;
-use bytes::{Bytes, BytesMut};
-use prost::Message;
+use bytes::Bytes;
 
-pub trait Camera {
-    fn get_frame(&mut self, buffer: BytesMut) -> anyhow::Result<BytesMut>;
+pub trait Camera: DoCommand {
+    /// Encode a `GetImageResponse` holding the current frame into `buffer`
+    fn get_frame(&mut self, buffer: &mut ChunkedBuffer) -> anyhow::Result<()>;
 }
 
 pub struct FakeCamera {}
 
+impl DoCommand for FakeCamera {}
+
 impl Camera for FakeCamera {
-    fn get_frame(&mut self, mut buffer: BytesMut) -> anyhow::Result<BytesMut> {
+    fn get_frame(&mut self, buffer: &mut ChunkedBuffer) -> anyhow::Result<()> {
         let msg = camera::v1::GetImageResponse {
             mime_type: "image/jpeg".to_string(),
             image: Bytes::new(),
         };
-
-        msg.encode(&mut buffer).unwrap();
-
-        Ok(buffer)
+        buffer.encode_message(&msg)?;
+        Ok(())
     }
 }
 
@@ -40,7 +42,7 @@ impl<L> Camera for Mutex<L>
 where
     L: ?Sized + Camera,
 {
-    fn get_frame(&mut self, buffer: BytesMut) -> anyhow::Result<BytesMut> {
+    fn get_frame(&mut self, buffer: &mut ChunkedBuffer) -> anyhow::Result<()> {
         self.get_mut().unwrap().get_frame(buffer)
     }
 }
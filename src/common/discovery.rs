@@ -0,0 +1,317 @@
+#![allow(dead_code)]
+use core::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::proto::robot::v1::{Discovery, DiscoveryQuery};
+use log::*;
+use prost_types::{value::Kind, ListValue, Struct, Value};
+
+/// Hook looking for components of one model (e.g. scanning an I2C bus for known addresses),
+/// implemented by drivers and registered on the robot with `LocalRobot::add_discovery`
+pub trait Discover {
+    /// Subtype (e.g. "sensor") of the components found
+    fn subtype(&self) -> &str;
+    /// Model of the components found
+    fn model(&self) -> &str;
+    /// Attributes of the configuration suggested for every component found
+    fn discover(&mut self) -> anyhow::Result<Vec<Struct>>;
+}
+
+/// Run the hooks matching `queries` (all of them if there are none) and turn what they found
+/// into suggested component configurations. A failing hook is logged and skipped so the
+/// others still report what they found.
+pub fn discover_components(
+    hooks: &mut [Box<dyn Discover>],
+    queries: &[DiscoveryQuery],
+) -> Vec<Discovery> {
+    let mut discoveries = Vec::new();
+    for hook in hooks.iter_mut() {
+        if !queries.is_empty()
+            && !queries
+                .iter()
+                .any(|q| q.subtype == hook.subtype() && q.model == hook.model())
+        {
+            continue;
+        }
+        let found = match hook.discover() {
+            Ok(found) => found,
+            Err(e) => {
+                warn!(
+                    "discovery of {} {} failed: {:?}",
+                    hook.subtype(),
+                    hook.model(),
+                    e
+                );
+                continue;
+            }
+        };
+        let components = found
+            .into_iter()
+            .map(|attributes| {
+                struct_value(BTreeMap::from([
+                    ("type".to_string(), string_value(hook.subtype())),
+                    ("model".to_string(), string_value(hook.model())),
+                    ("attributes".to_string(), struct_value(attributes.fields)),
+                ]))
+            })
+            .collect();
+        discoveries.push(Discovery {
+            query: Some(DiscoveryQuery {
+                subtype: hook.subtype().to_string(),
+                model: hook.model().to_string(),
+            }),
+            results: Some(Struct {
+                fields: BTreeMap::from([(
+                    "components".to_string(),
+                    Value {
+                        kind: Some(Kind::ListValue(ListValue { values: components })),
+                    },
+                )]),
+            }),
+        });
+    }
+    discoveries
+}
+
+fn string_value(s: &str) -> Value {
+    Value {
+        kind: Some(Kind::StringValue(s.to_string())),
+    }
+}
+
+fn struct_value(fields: BTreeMap<String, Value>) -> Value {
+    Value {
+        kind: Some(Kind::StructValue(Struct { fields })),
+    }
+}
+
+/// Probes a single I2C address, returns whether a device acknowledged it
+pub trait I2cProbe {
+    fn probe(&mut self, address: u8) -> anyhow::Result<bool>;
+}
+
+/// Several hooks can probe the same bus
+impl<P> I2cProbe for Rc<RefCell<P>>
+where
+    P: ?Sized + I2cProbe,
+{
+    fn probe(&mut self, address: u8) -> anyhow::Result<bool> {
+        self.borrow_mut().probe(address)
+    }
+}
+
+/// Scan an I2C bus for devices of one model, each address acknowledging is suggested as a
+/// component with its `i2c_bus` and `i2c_address`
+pub struct I2cDiscovery<P> {
+    probe: P,
+    bus: String,
+    subtype: &'static str,
+    model: &'static str,
+    addresses: Vec<u8>,
+}
+
+impl<P> I2cDiscovery<P>
+where
+    P: I2cProbe,
+{
+    /// `addresses` are the ones the model can be configured to answer on
+    pub fn new(
+        probe: P,
+        bus: String,
+        subtype: &'static str,
+        model: &'static str,
+        addresses: Vec<u8>,
+    ) -> Self {
+        I2cDiscovery {
+            probe,
+            bus,
+            subtype,
+            model,
+            addresses,
+        }
+    }
+}
+
+impl<P> Discover for I2cDiscovery<P>
+where
+    P: I2cProbe,
+{
+    fn subtype(&self) -> &str {
+        self.subtype
+    }
+    fn model(&self) -> &str {
+        self.model
+    }
+    fn discover(&mut self) -> anyhow::Result<Vec<Struct>> {
+        let mut found = Vec::new();
+        for address in self.addresses.iter() {
+            if !self.probe.probe(*address)? {
+                continue;
+            }
+            found.push(Struct {
+                fields: BTreeMap::from([
+                    ("i2c_bus".to_string(), string_value(&self.bus)),
+                    (
+                        "i2c_address".to_string(),
+                        Value {
+                            kind: Some(Kind::NumberValue(*address as f64)),
+                        },
+                    ),
+                ]),
+            });
+        }
+        Ok(found)
+    }
+}
+
+/// Always finds the same components, for testing clients
+pub struct FakeDiscovery {
+    subtype: String,
+    model: String,
+    found: Vec<Struct>,
+}
+
+impl FakeDiscovery {
+    pub fn new(subtype: String, model: String, found: Vec<Struct>) -> Self {
+        FakeDiscovery {
+            subtype,
+            model,
+            found,
+        }
+    }
+}
+
+impl Discover for FakeDiscovery {
+    fn subtype(&self) -> &str {
+        &self.subtype
+    }
+    fn model(&self) -> &str {
+        &self.model
+    }
+    fn discover(&mut self) -> anyhow::Result<Vec<Struct>> {
+        Ok(self.found.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingDiscovery;
+
+    impl Discover for FailingDiscovery {
+        fn subtype(&self) -> &str {
+            "sensor"
+        }
+        fn model(&self) -> &str {
+            "broken"
+        }
+        fn discover(&mut self) -> anyhow::Result<Vec<Struct>> {
+            Err(anyhow::anyhow!("bus error"))
+        }
+    }
+
+    /// Acknowledges the addresses it was given
+    struct FakeProbe(Vec<u8>);
+
+    impl I2cProbe for FakeProbe {
+        fn probe(&mut self, address: u8) -> anyhow::Result<bool> {
+            Ok(self.0.contains(&address))
+        }
+    }
+
+    fn attributes(name: &str) -> Struct {
+        Struct {
+            fields: BTreeMap::from([("name".to_string(), string_value(name))]),
+        }
+    }
+
+    fn fake(subtype: &str, model: &str, found: Vec<Struct>) -> Box<dyn Discover> {
+        Box::new(FakeDiscovery::new(
+            subtype.to_string(),
+            model.to_string(),
+            found,
+        ))
+    }
+
+    fn components(discovery: &Discovery) -> Vec<Value> {
+        match &discovery.results.as_ref().unwrap().fields["components"].kind {
+            Some(Kind::ListValue(list)) => list.values.clone(),
+            other => panic!("unexpected components {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fake_discovery_suggests_its_components() {
+        let mut hooks = vec![fake(
+            "motor",
+            "fake",
+            vec![attributes("a"), attributes("b")],
+        )];
+        let discoveries = discover_components(&mut hooks, &[]);
+        assert_eq!(discoveries.len(), 1);
+        let query = discoveries[0].query.as_ref().unwrap();
+        assert_eq!(
+            (query.subtype.as_str(), query.model.as_str()),
+            ("motor", "fake")
+        );
+        let components = components(&discoveries[0]);
+        assert_eq!(components.len(), 2);
+        assert_eq!(
+            components[1],
+            struct_value(BTreeMap::from([
+                ("type".to_string(), string_value("motor")),
+                ("model".to_string(), string_value("fake")),
+                (
+                    "attributes".to_string(),
+                    struct_value(attributes("b").fields)
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn only_queried_hooks_run() {
+        let mut hooks = vec![
+            fake("motor", "fake", vec![attributes("m")]),
+            fake("sensor", "fake", vec![attributes("s")]),
+        ];
+        let queries = [DiscoveryQuery {
+            subtype: "sensor".to_string(),
+            model: "fake".to_string(),
+        }];
+        let discoveries = discover_components(&mut hooks, &queries);
+        assert_eq!(discoveries.len(), 1);
+        assert_eq!(discoveries[0].query.as_ref(), Some(&queries[0]));
+    }
+
+    #[test]
+    fn failing_hooks_are_skipped() {
+        let mut hooks = vec![
+            Box::new(FailingDiscovery) as Box<dyn Discover>,
+            fake("motor", "fake", vec![attributes("m")]),
+        ];
+        let discoveries = discover_components(&mut hooks, &[]);
+        assert_eq!(discoveries.len(), 1);
+        assert_eq!(discoveries[0].query.as_ref().unwrap().model, "fake");
+    }
+
+    #[test]
+    fn i2c_discovery_suggests_acknowledged_addresses() {
+        let mut discovery = I2cDiscovery::new(
+            FakeProbe(vec![0x40, 0x48]),
+            "i2c0".to_string(),
+            "sensor",
+            "ina219",
+            vec![0x40, 0x41, 0x44, 0x45],
+        );
+        let found = discovery.discover().unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].fields["i2c_bus"], string_value("i2c0"));
+        assert_eq!(
+            found[0].fields["i2c_address"].kind,
+            Some(Kind::NumberValue(64.0))
+        );
+    }
+}
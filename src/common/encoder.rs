@@ -0,0 +1,190 @@
+#![allow(dead_code)]
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::status::Status;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Unit an encoder position is expressed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderPositionType {
+    /// Ticks counted since the position was last reset, reported by incremental encoders
+    Ticks,
+    /// Angle of the shaft within a turn, reported by absolute encoders
+    Degrees,
+}
+
+/// Describes the positions an encoder is able to report, returned by `Encoder::get_properties`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncoderSupportedProperties {
+    pub ticks_count_supported: bool,
+    pub angle_degrees_supported: bool,
+}
+
+/// Error returned when asking an encoder for a position type it doesn't support
+pub fn position_type_not_supported(position_type: EncoderPositionType) -> GrpcError {
+    GrpcError::RpcUnimplemented(format!(
+        "encoder doesn't support {:?} positions",
+        position_type
+    ))
+}
+
+pub trait Encoder: Status + DoCommand {
+    fn get_properties(&self) -> EncoderSupportedProperties;
+    /// Position of the encoder in `position_type` units
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64>;
+    /// Set the current position as the new zero position
+    fn reset_position(&mut self) -> anyhow::Result<()>;
+    /// Encoders unable to sense which way they turn (e.g. single phase ones) count in the
+    /// direction last set, motors set it whenever they are powered
+    fn set_direction(&mut self, _forwards: bool) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<L> Encoder for Mutex<L>
+where
+    L: ?Sized + Encoder,
+{
+    fn get_properties(&self) -> EncoderSupportedProperties {
+        self.lock().unwrap().get_properties()
+    }
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+        self.lock().unwrap().get_position(position_type)
+    }
+    fn reset_position(&mut self) -> anyhow::Result<()> {
+        self.get_mut().unwrap().reset_position()
+    }
+    fn set_direction(&mut self, forwards: bool) -> anyhow::Result<()> {
+        self.get_mut().unwrap().set_direction(forwards)
+    }
+}
+
+impl<A> Encoder for Arc<Mutex<A>>
+where
+    A: ?Sized + Encoder,
+{
+    fn get_properties(&self) -> EncoderSupportedProperties {
+        self.lock().unwrap().get_properties()
+    }
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+        self.lock().unwrap().get_position(position_type)
+    }
+    fn reset_position(&mut self) -> anyhow::Result<()> {
+        self.lock().unwrap().reset_position()
+    }
+    fn set_direction(&mut self, forwards: bool) -> anyhow::Result<()> {
+        self.lock().unwrap().set_direction(forwards)
+    }
+}
+
+/// Status of an encoder counting ticks
+pub(crate) fn encoder_status(ticks: f64) -> prost_types::Struct {
+    prost_types::Struct {
+        fields: BTreeMap::from([(
+            "position".to_string(),
+            prost_types::Value {
+                kind: Some(prost_types::value::Kind::NumberValue(ticks)),
+            },
+        )]),
+    }
+}
+
+/// Encoder whose ticks are set by hand, with a non zero `ticks_per_rotation` it also reports
+/// the angle of its shaft
+pub struct FakeEncoder {
+    ticks: i64,
+    ticks_per_rotation: u32,
+}
+
+impl FakeEncoder {
+    pub fn new(ticks_per_rotation: u32) -> Self {
+        FakeEncoder {
+            ticks: 0,
+            ticks_per_rotation,
+        }
+    }
+    pub fn set_ticks(&mut self, ticks: i64) {
+        self.ticks = ticks;
+    }
+}
+
+impl Encoder for FakeEncoder {
+    fn get_properties(&self) -> EncoderSupportedProperties {
+        EncoderSupportedProperties {
+            ticks_count_supported: true,
+            angle_degrees_supported: self.ticks_per_rotation != 0,
+        }
+    }
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+        match position_type {
+            EncoderPositionType::Ticks => Ok(self.ticks as f64),
+            EncoderPositionType::Degrees if self.ticks_per_rotation != 0 => {
+                let tpr = self.ticks_per_rotation as i64;
+                Ok(self.ticks.rem_euclid(tpr) as f64 * 360.0 / tpr as f64)
+            }
+            _ => Err(position_type_not_supported(position_type).into()),
+        }
+    }
+    fn reset_position(&mut self) -> anyhow::Result<()> {
+        self.ticks = 0;
+        Ok(())
+    }
+}
+
+impl DoCommand for FakeEncoder {}
+
+impl Status for FakeEncoder {
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(encoder_status(self.ticks as f64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fake_encoder_counts_ticks() {
+        let mut encoder = FakeEncoder::new(0);
+        encoder.set_ticks(-42);
+        assert_eq!(
+            encoder.get_position(EncoderPositionType::Ticks).unwrap(),
+            -42.0
+        );
+        assert_eq!(encoder.get_status().unwrap(), Some(encoder_status(-42.0)));
+        encoder.reset_position().unwrap();
+        assert_eq!(
+            encoder.get_position(EncoderPositionType::Ticks).unwrap(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn fake_encoder_without_ticks_per_rotation_has_no_angle() {
+        let encoder = FakeEncoder::new(0);
+        assert!(!encoder.get_properties().angle_degrees_supported);
+        let err = encoder
+            .get_position(EncoderPositionType::Degrees)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<GrpcError>(),
+            Some(&position_type_not_supported(EncoderPositionType::Degrees))
+        );
+    }
+
+    #[test]
+    fn fake_encoder_degrees_wrap_around() {
+        let mut encoder = FakeEncoder::new(100);
+        assert!(encoder.get_properties().angle_degrees_supported);
+        for (ticks, degrees) in [(0, 0.0), (25, 90.0), (100, 0.0), (350, 180.0), (-25, 270.0)] {
+            encoder.set_ticks(ticks);
+            assert_eq!(
+                encoder.get_position(EncoderPositionType::Degrees).unwrap(),
+                degrees,
+                "{} ticks",
+                ticks
+            );
+        }
+    }
+}
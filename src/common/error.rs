@@ -0,0 +1,190 @@
+use crate::proto::google::rpc::Code;
+
+/// Errors returned by RPC handlers and components, each variant maps to a gRPC status code
+/// (see https://grpc.github.io/grpc/core/md_doc_statuscodes.html). Components returning an
+/// `anyhow::Error` can wrap a `GrpcError` in it to control the status code sent to the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GrpcError {
+    RpcCancelled,
+    RpcInvalidArgument(String),
+    RpcDeadlineExceeded,
+    RpcNotFound(String),
+    RpcAlreadyExists(String),
+    RpcPermissionDenied(String),
+    RpcResourceExhausted(String),
+    RpcFailedPrecondition(String),
+    RpcAborted(String),
+    RpcOutOfRange(String),
+    RpcUnimplemented(String),
+    RpcInternal(String),
+    RpcUnavailable(String),
+    RpcUnauthenticated(String),
+}
+
+impl GrpcError {
+    pub fn code(&self) -> Code {
+        match self {
+            GrpcError::RpcCancelled => Code::Cancelled,
+            GrpcError::RpcInvalidArgument(_) => Code::InvalidArgument,
+            GrpcError::RpcDeadlineExceeded => Code::DeadlineExceeded,
+            GrpcError::RpcNotFound(_) => Code::NotFound,
+            GrpcError::RpcAlreadyExists(_) => Code::AlreadyExists,
+            GrpcError::RpcPermissionDenied(_) => Code::PermissionDenied,
+            GrpcError::RpcResourceExhausted(_) => Code::ResourceExhausted,
+            GrpcError::RpcFailedPrecondition(_) => Code::FailedPrecondition,
+            GrpcError::RpcAborted(_) => Code::Aborted,
+            GrpcError::RpcOutOfRange(_) => Code::OutOfRange,
+            GrpcError::RpcUnimplemented(_) => Code::Unimplemented,
+            GrpcError::RpcInternal(_) => Code::Internal,
+            GrpcError::RpcUnavailable(_) => Code::Unavailable,
+            GrpcError::RpcUnauthenticated(_) => Code::Unauthenticated,
+        }
+    }
+
+    /// Build the `google.rpc.Status` message describing this error, sent to the client
+    /// in the `grpc-status-details-bin` trailer
+    pub fn to_status(&self) -> crate::proto::google::rpc::Status {
+        crate::proto::google::rpc::Status {
+            code: self.code() as i32,
+            message: self.to_string(),
+            details: vec![],
+        }
+    }
+}
+
+impl std::error::Error for GrpcError {}
+
+impl std::fmt::Display for GrpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GrpcError::RpcCancelled => write!(f, "request cancelled"),
+            GrpcError::RpcDeadlineExceeded => write!(f, "deadline exceeded"),
+            GrpcError::RpcInvalidArgument(msg)
+            | GrpcError::RpcNotFound(msg)
+            | GrpcError::RpcAlreadyExists(msg)
+            | GrpcError::RpcPermissionDenied(msg)
+            | GrpcError::RpcResourceExhausted(msg)
+            | GrpcError::RpcFailedPrecondition(msg)
+            | GrpcError::RpcAborted(msg)
+            | GrpcError::RpcOutOfRange(msg)
+            | GrpcError::RpcUnimplemented(msg)
+            | GrpcError::RpcInternal(msg)
+            | GrpcError::RpcUnavailable(msg)
+            | GrpcError::RpcUnauthenticated(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<prost::DecodeError> for GrpcError {
+    fn from(err: prost::DecodeError) -> Self {
+        GrpcError::RpcInvalidArgument(format!("cannot decode request: {}", err))
+    }
+}
+
+impl From<prost::EncodeError> for GrpcError {
+    fn from(err: prost::EncodeError) -> Self {
+        GrpcError::RpcResourceExhausted(format!("cannot encode response: {}", err))
+    }
+}
+
+/// Errors coming from components keep their status code if they wrap a `GrpcError`,
+/// otherwise they are reported as INTERNAL
+impl From<anyhow::Error> for GrpcError {
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<GrpcError>() {
+            Ok(err) => err,
+            Err(err) => GrpcError::RpcInternal(format!("{:#}", err)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+
+    #[test]
+    fn every_error_has_its_grpc_status() {
+        let msg = || "msg".to_string();
+        let table = [
+            (GrpcError::RpcCancelled, 1),
+            (GrpcError::RpcInvalidArgument(msg()), 3),
+            (GrpcError::RpcDeadlineExceeded, 4),
+            (GrpcError::RpcNotFound(msg()), 5),
+            (GrpcError::RpcAlreadyExists(msg()), 6),
+            (GrpcError::RpcPermissionDenied(msg()), 7),
+            (GrpcError::RpcResourceExhausted(msg()), 8),
+            (GrpcError::RpcFailedPrecondition(msg()), 9),
+            (GrpcError::RpcAborted(msg()), 10),
+            (GrpcError::RpcOutOfRange(msg()), 11),
+            (GrpcError::RpcUnimplemented(msg()), 12),
+            (GrpcError::RpcInternal(msg()), 13),
+            (GrpcError::RpcUnavailable(msg()), 14),
+            (GrpcError::RpcUnauthenticated(msg()), 16),
+        ];
+        for (err, status) in table {
+            assert_eq!(err.code() as i32, status, "{:?}", err);
+            let details = err.to_status();
+            assert_eq!(details.code, status);
+            assert_eq!(details.message, err.to_string());
+        }
+    }
+
+    #[test]
+    fn errors_display_their_message() {
+        assert_eq!(GrpcError::RpcCancelled.to_string(), "request cancelled");
+        assert_eq!(
+            GrpcError::RpcDeadlineExceeded.to_string(),
+            "deadline exceeded"
+        );
+        assert_eq!(
+            GrpcError::RpcNotFound("no motor m1".to_string()).to_string(),
+            "no motor m1"
+        );
+    }
+
+    #[test]
+    fn decode_errors_are_invalid_arguments() {
+        let err: GrpcError = prost_types::Value::decode(&[0xff_u8][..])
+            .unwrap_err()
+            .into();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.to_string().starts_with("cannot decode request: "));
+    }
+
+    #[test]
+    fn encode_errors_exhaust_resources() {
+        let value = prost_types::Value {
+            kind: Some(prost_types::value::Kind::StringValue(
+                "too long".to_string(),
+            )),
+        };
+        let mut buf = [0_u8; 4];
+        let err: GrpcError = value.encode(&mut &mut buf[..]).unwrap_err().into();
+        assert_eq!(err.code(), Code::ResourceExhausted);
+        assert!(err.to_string().starts_with("cannot encode response: "));
+    }
+
+    #[test]
+    fn anyhow_errors_are_internal_unless_they_wrap_a_grpc_error() {
+        let err: GrpcError = anyhow::anyhow!("motor on fire").into();
+        assert_eq!(err, GrpcError::RpcInternal("motor on fire".to_string()));
+
+        let wrapped = GrpcError::RpcOutOfRange("position out of range".to_string());
+        let err: GrpcError = anyhow::Error::from(wrapped.clone()).into();
+        assert_eq!(err, wrapped);
+        // context added on the way up doesn't hide the code
+        let err: GrpcError = anyhow::Error::from(wrapped.clone())
+            .context("cannot move the gantry")
+            .into();
+        assert_eq!(err, wrapped);
+        // the whole chain of an internal error is reported
+        let err: GrpcError = anyhow::anyhow!("i2c timeout")
+            .context("cannot read the sensor")
+            .into();
+        assert_eq!(
+            err,
+            GrpcError::RpcInternal("cannot read the sensor: i2c timeout".to_string())
+        );
+    }
+}
@@ -0,0 +1,745 @@
+use std::collections::HashMap;
+
+use crate::{
+    common::error::GrpcError,
+    proto::{
+        app::v1::{orientation, Frame, Orientation},
+        common::v1::{Pose as ProtoPose, PoseInFrame, Transform},
+        robot::v1::FrameSystemConfig,
+    },
+};
+
+/// Name of the root of every frame system
+pub static WORLD_FRAME: &str = "world";
+
+// below this an orientation vector is considered to point at a pole
+const ANGLE_EPSILON: f64 = 1e-4;
+
+/// Unit quaternion representing a rotation, every orientation form converts to and from it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quaternion {
+    pub fn identity() -> Self {
+        Quaternion {
+            w: 1.0,
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    /// Rotation of `angle` radians around `axis`, which doesn't need to be normalized
+    pub fn from_axis_angle(axis: [f64; 3], angle: f64) -> Self {
+        let norm = (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2]).sqrt();
+        if norm == 0.0 {
+            return Self::identity();
+        }
+        let s = (angle / 2.0).sin() / norm;
+        Quaternion {
+            w: (angle / 2.0).cos(),
+            x: axis[0] * s,
+            y: axis[1] * s,
+            z: axis[2] * s,
+        }
+    }
+
+    /// Orientation vector (in radians): the local Z axis points at (ox, oy, oz) and the frame
+    /// is then rotated by theta around it
+    pub fn from_orientation_vector(ox: f64, oy: f64, oz: f64, theta: f64) -> Self {
+        let norm = (ox * ox + oy * oy + oz * oz).sqrt();
+        let (ox, oy, oz) = if norm == 0.0 {
+            (0.0, 0.0, 1.0)
+        } else {
+            (ox / norm, oy / norm, oz / norm)
+        };
+        let lat = oz.clamp(-1.0, 1.0).acos();
+        let lon = if 1.0 - oz.abs() > ANGLE_EPSILON {
+            oy.atan2(ox)
+        } else {
+            0.0
+        };
+        Self::from_axis_angle([0.0, 0.0, 1.0], lon)
+            * Self::from_axis_angle([0.0, 1.0, 0.0], lat)
+            * Self::from_axis_angle([0.0, 0.0, 1.0], theta)
+    }
+
+    /// Tait-Bryan angles (in radians) applied in the z-y'-x'' sequence
+    pub fn from_euler_angles(roll: f64, pitch: f64, yaw: f64) -> Self {
+        Self::from_axis_angle([0.0, 0.0, 1.0], yaw)
+            * Self::from_axis_angle([0.0, 1.0, 0.0], pitch)
+            * Self::from_axis_angle([1.0, 0.0, 0.0], roll)
+    }
+
+    pub fn from_orientation(orientation: &Orientation) -> Self {
+        match orientation.r#type.as_ref() {
+            None | Some(orientation::Type::NoOrientation(_)) => Self::identity(),
+            Some(orientation::Type::VectorRadians(ov)) => {
+                Self::from_orientation_vector(ov.x, ov.y, ov.z, ov.theta)
+            }
+            Some(orientation::Type::VectorDegrees(ov)) => {
+                Self::from_orientation_vector(ov.x, ov.y, ov.z, ov.theta.to_radians())
+            }
+            Some(orientation::Type::EulerAngles(e)) => {
+                Self::from_euler_angles(e.roll, e.pitch, e.yaw)
+            }
+            Some(orientation::Type::AxisAngles(a)) => {
+                Self::from_axis_angle([a.x, a.y, a.z], a.theta)
+            }
+            Some(orientation::Type::Quaternion(q)) => Quaternion {
+                w: q.w,
+                x: q.x,
+                y: q.y,
+                z: q.z,
+            }
+            .normalize(),
+        }
+    }
+
+    pub fn normalize(self) -> Self {
+        let norm = (self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if norm == 0.0 {
+            return Self::identity();
+        }
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    pub fn conjugate(self) -> Self {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn rotate(&self, v: [f64; 3]) -> [f64; 3] {
+        let p = Quaternion {
+            w: 0.0,
+            x: v[0],
+            y: v[1],
+            z: v[2],
+        };
+        let r = *self * p * self.conjugate();
+        [r.x, r.y, r.z]
+    }
+
+    /// Rotation matrix, row major
+    fn matrix(&self) -> [[f64; 3]; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Orientation vector with theta in radians
+    pub fn to_vector_radians(&self) -> orientation::OrientationVectorRadians {
+        let m = self.matrix();
+        // the local Z axis is the third column, theta undoes the lon/lat rotations of
+        // `from_orientation_vector`
+        let (x, y, z) = (m[0][2], m[1][2], m[2][2]);
+        let theta = if 1.0 - z.abs() > ANGLE_EPSILON {
+            m[2][1].atan2(-m[2][0])
+        } else if z > 0.0 {
+            m[1][0].atan2(m[0][0])
+        } else {
+            m[1][0].atan2(m[1][1])
+        };
+        orientation::OrientationVectorRadians { theta, x, y, z }
+    }
+
+    pub fn to_vector_degrees(&self) -> orientation::OrientationVectorDegrees {
+        let ov = self.to_vector_radians();
+        orientation::OrientationVectorDegrees {
+            theta: ov.theta.to_degrees(),
+            x: ov.x,
+            y: ov.y,
+            z: ov.z,
+        }
+    }
+
+    pub fn to_euler_angles(&self) -> orientation::EulerAngles {
+        let Quaternion { w, x, y, z } = *self;
+        let roll = (2.0 * (w * x + y * z)).atan2(1.0 - 2.0 * (x * x + y * y));
+        let pitch = (2.0 * (w * y - z * x)).clamp(-1.0, 1.0).asin();
+        let yaw = (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+        orientation::EulerAngles { roll, pitch, yaw }
+    }
+
+    pub fn to_axis_angles(&self) -> orientation::AxisAngles {
+        // pick the shortest rotation
+        let q = if self.w < 0.0 {
+            Quaternion {
+                w: -self.w,
+                x: -self.x,
+                y: -self.y,
+                z: -self.z,
+            }
+        } else {
+            *self
+        };
+        let sin = (1.0 - q.w * q.w).max(0.0).sqrt();
+        if sin < f64::EPSILON {
+            return orientation::AxisAngles {
+                theta: 0.0,
+                x: 0.0,
+                y: 0.0,
+                z: 1.0,
+            };
+        }
+        orientation::AxisAngles {
+            theta: 2.0 * q.w.clamp(-1.0, 1.0).acos(),
+            x: q.x / sin,
+            y: q.y / sin,
+            z: q.z / sin,
+        }
+    }
+
+    /// Convert to the same orientation form as `like`
+    pub fn to_orientation(&self, like: &Orientation) -> Orientation {
+        let r#type = match like.r#type.as_ref() {
+            None => None,
+            Some(orientation::Type::NoOrientation(_)) => Some(orientation::Type::NoOrientation(
+                orientation::NoOrientation {},
+            )),
+            Some(orientation::Type::VectorRadians(_)) => {
+                Some(orientation::Type::VectorRadians(self.to_vector_radians()))
+            }
+            Some(orientation::Type::VectorDegrees(_)) => {
+                Some(orientation::Type::VectorDegrees(self.to_vector_degrees()))
+            }
+            Some(orientation::Type::EulerAngles(_)) => {
+                Some(orientation::Type::EulerAngles(self.to_euler_angles()))
+            }
+            Some(orientation::Type::AxisAngles(_)) => {
+                Some(orientation::Type::AxisAngles(self.to_axis_angles()))
+            }
+            Some(orientation::Type::Quaternion(_)) => {
+                Some(orientation::Type::Quaternion(orientation::Quaternion {
+                    w: self.w,
+                    x: self.x,
+                    y: self.y,
+                    z: self.z,
+                }))
+            }
+        };
+        Orientation { r#type }
+    }
+}
+
+impl std::ops::Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, o: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+/// Position (in millimeters) and orientation of a frame relative to its parent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub translation: [f64; 3],
+    pub rotation: Quaternion,
+}
+
+impl Pose {
+    pub fn identity() -> Self {
+        Pose {
+            translation: [0.0; 3],
+            rotation: Quaternion::identity(),
+        }
+    }
+
+    pub fn from_frame(frame: &Frame) -> Self {
+        let translation = frame
+            .translation
+            .as_ref()
+            .map_or([0.0; 3], |t| [t.x, t.y, t.z]);
+        let rotation = frame
+            .orientation
+            .as_ref()
+            .map_or_else(Quaternion::identity, Quaternion::from_orientation);
+        Pose {
+            translation,
+            rotation,
+        }
+    }
+
+    /// The API poses carry their orientation as an orientation vector in degrees
+    pub fn from_proto(pose: &ProtoPose) -> Self {
+        Pose {
+            translation: [pose.x, pose.y, pose.z],
+            rotation: Quaternion::from_orientation_vector(
+                pose.o_x,
+                pose.o_y,
+                pose.o_z,
+                pose.theta.to_radians(),
+            ),
+        }
+    }
+
+    pub fn to_proto(&self) -> ProtoPose {
+        let ov = self.rotation.to_vector_degrees();
+        ProtoPose {
+            x: self.translation[0],
+            y: self.translation[1],
+            z: self.translation[2],
+            o_x: ov.x,
+            o_y: ov.y,
+            o_z: ov.z,
+            theta: ov.theta,
+        }
+    }
+
+    /// Express `child`, a pose relative to this one, relative to the parent of this pose
+    pub fn compose(&self, child: &Pose) -> Pose {
+        let t = self.rotation.rotate(child.translation);
+        Pose {
+            translation: [
+                self.translation[0] + t[0],
+                self.translation[1] + t[1],
+                self.translation[2] + t[2],
+            ],
+            rotation: (self.rotation * child.rotation).normalize(),
+        }
+    }
+
+    pub fn inverse(&self) -> Pose {
+        let rotation = self.rotation.conjugate();
+        let t = rotation.rotate(self.translation);
+        Pose {
+            translation: [-t[0], -t[1], -t[2]],
+            rotation,
+        }
+    }
+}
+
+/// Tree of the reference frames of a robot, rooted at the world frame
+pub struct FrameSystem {
+    /// parent and pose in the parent of every frame but the world frame
+    frames: HashMap<String, (String, Pose)>,
+}
+
+impl FrameSystem {
+    pub fn new() -> Self {
+        FrameSystem {
+            frames: HashMap::new(),
+        }
+    }
+
+    /// Add (or replace) the frame `name`, frames can be added in any order, parents are only
+    /// looked up when poses are transformed
+    pub fn add_frame(&mut self, name: &str, parent: &str, pose: Pose) -> Result<(), GrpcError> {
+        if name == WORLD_FRAME {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "{} frame can't have a parent",
+                WORLD_FRAME
+            )));
+        }
+        let parent = if parent.is_empty() {
+            WORLD_FRAME
+        } else {
+            parent
+        };
+        if parent == name {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "frame {} can't be its own parent",
+                name
+            )));
+        }
+        self.frames
+            .insert(name.to_owned(), (parent.to_owned(), pose));
+        Ok(())
+    }
+
+    pub fn add_transform(&mut self, transform: &Transform) -> Result<(), GrpcError> {
+        let observer = transform.pose_in_observer_frame.as_ref().ok_or_else(|| {
+            GrpcError::RpcInvalidArgument(format!(
+                "transform of frame {} has no pose",
+                transform.reference_frame
+            ))
+        })?;
+        let pose = observer
+            .pose
+            .as_ref()
+            .map_or_else(Pose::identity, Pose::from_proto);
+        self.add_frame(&transform.reference_frame, &observer.reference_frame, pose)
+    }
+
+    /// Pose of the frame `name` in the world frame
+    fn world_pose(&self, name: &str) -> Result<Pose, GrpcError> {
+        let mut pose = Pose::identity();
+        let mut current = name;
+        // a frame can't have more ancestors than there are frames unless the tree has a cycle
+        for _ in 0..=self.frames.len() {
+            if current == WORLD_FRAME {
+                return Ok(pose);
+            }
+            let (parent, in_parent) = self.frames.get(current).ok_or_else(|| {
+                GrpcError::RpcInvalidArgument(format!("frame {} doesn't exist", current))
+            })?;
+            pose = in_parent.compose(&pose);
+            current = parent;
+        }
+        Err(GrpcError::RpcInvalidArgument(format!(
+            "frame {} is part of a cycle",
+            name
+        )))
+    }
+
+    /// Express `source` in the frame `destination` (the world frame if empty)
+    pub fn transform_pose(
+        &self,
+        source: &PoseInFrame,
+        destination: &str,
+    ) -> Result<PoseInFrame, GrpcError> {
+        let destination = if destination.is_empty() {
+            WORLD_FRAME
+        } else {
+            destination
+        };
+        let pose = source
+            .pose
+            .as_ref()
+            .map_or_else(Pose::identity, Pose::from_proto);
+        let from = if source.reference_frame.is_empty() {
+            WORLD_FRAME
+        } else {
+            &source.reference_frame
+        };
+        let in_world = self.world_pose(from)?.compose(&pose);
+        let pose = self.world_pose(destination)?.inverse().compose(&in_world);
+        Ok(PoseInFrame {
+            reference_frame: destination.to_owned(),
+            pose: Some(pose.to_proto()),
+        })
+    }
+
+    /// Configuration of every frame, sorted by name
+    pub fn configs(&self) -> Vec<FrameSystemConfig> {
+        let mut names: Vec<&String> = self.frames.keys().collect();
+        names.sort();
+        names
+            .into_iter()
+            .map(|name| {
+                let (parent, pose) = &self.frames[name];
+                FrameSystemConfig {
+                    frame: Some(Transform {
+                        reference_frame: name.clone(),
+                        pose_in_observer_frame: Some(PoseInFrame {
+                            reference_frame: parent.clone(),
+                            pose: Some(pose.to_proto()),
+                        }),
+                        physical_object: None,
+                    }),
+                    kinematics: None,
+                }
+            })
+            .collect()
+    }
+}
+
+impl Default for FrameSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    fn assert_close(a: [f64; 3], b: [f64; 3]) {
+        for i in 0..3 {
+            assert!((a[i] - b[i]).abs() < 1e-9, "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// Quaternions `q` and `-q` are the same rotation, compare what they do to the axes
+    fn assert_same_rotation(a: Quaternion, b: Quaternion) {
+        for axis in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] {
+            assert_close(a.rotate(axis), b.rotate(axis));
+        }
+    }
+
+    #[test]
+    fn orientation_vector_points_the_z_axis() {
+        let q = Quaternion::from_orientation_vector(1.0, 0.0, 0.0, 0.0);
+        assert_close(q.rotate([0.0, 0.0, 1.0]), [1.0, 0.0, 0.0]);
+        let q = Quaternion::from_orientation_vector(0.0, 2.0, 0.0, 0.0);
+        assert_close(q.rotate([0.0, 0.0, 1.0]), [0.0, 1.0, 0.0]);
+        let q = Quaternion::from_orientation_vector(0.0, 0.0, 1.0, FRAC_PI_2);
+        assert_close(q.rotate([1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn orientation_vector_round_trips() {
+        for (x, y, z, theta) in [
+            (0.0, 0.0, 1.0, 0.0),
+            (0.0, 0.0, 1.0, 1.0),
+            (0.0, 0.0, -1.0, -2.0),
+            (1.0, 0.0, 0.0, FRAC_PI_2),
+            (0.3, -0.4, 0.5, 3.0),
+            (-1.0, -1.0, -1.0, -0.5),
+        ] {
+            let q = Quaternion::from_orientation_vector(x, y, z, theta);
+            let ov = q.to_vector_radians();
+            let norm = (x * x + y * y + z * z).sqrt();
+            assert_close([ov.x, ov.y, ov.z], [x / norm, y / norm, z / norm]);
+            assert_same_rotation(
+                Quaternion::from_orientation_vector(ov.x, ov.y, ov.z, ov.theta),
+                q,
+            );
+            let ov = q.to_vector_degrees();
+            assert!((ov.theta.to_radians() - theta).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn euler_angles_round_trip() {
+        let q = Quaternion::from_euler_angles(0.1, -0.2, 0.3);
+        let e = q.to_euler_angles();
+        assert_close([e.roll, e.pitch, e.yaw], [0.1, -0.2, 0.3]);
+        let q = Quaternion::from_euler_angles(0.0, 0.0, FRAC_PI_2);
+        assert_close(q.rotate([1.0, 0.0, 0.0]), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn axis_angles_round_trip() {
+        let q = Quaternion::from_axis_angle([0.0, 2.0, 0.0], 1.0);
+        let a = q.to_axis_angles();
+        assert_close([a.x, a.y, a.z], [0.0, 1.0, 0.0]);
+        assert!((a.theta - 1.0).abs() < 1e-9);
+        // the long way around is reported as the short one around the opposite axis
+        let a = Quaternion::from_axis_angle([0.0, 0.0, 1.0], 3.0 * FRAC_PI_2).to_axis_angles();
+        assert_close([a.x, a.y, a.z], [0.0, 0.0, -1.0]);
+        assert!((a.theta - FRAC_PI_2).abs() < 1e-9);
+        let a = Quaternion::identity().to_axis_angles();
+        assert_close([a.x, a.y, a.z], [0.0, 0.0, 1.0]);
+        assert_eq!(a.theta, 0.0);
+    }
+
+    #[test]
+    fn every_orientation_form_converts_back() {
+        let q = Quaternion::from_orientation_vector(0.3, -0.4, 0.5, 3.0);
+        let forms = [
+            orientation::Type::VectorRadians(Default::default()),
+            orientation::Type::VectorDegrees(Default::default()),
+            orientation::Type::EulerAngles(Default::default()),
+            orientation::Type::AxisAngles(Default::default()),
+            orientation::Type::Quaternion(Default::default()),
+        ];
+        for form in forms {
+            let like = Orientation { r#type: Some(form) };
+            let converted = q.to_orientation(&like);
+            assert_same_rotation(Quaternion::from_orientation(&converted), q);
+        }
+    }
+
+    #[test]
+    fn quaternions_are_normalized() {
+        let q = Quaternion::from_orientation(&Orientation {
+            r#type: Some(orientation::Type::Quaternion(orientation::Quaternion {
+                w: 0.0,
+                x: 0.0,
+                y: 0.0,
+                z: 2.0,
+            })),
+        });
+        assert_close(q.rotate([1.0, 0.0, 0.0]), [-1.0, 0.0, 0.0]);
+        assert_same_rotation(q, Quaternion::from_axis_angle([0.0, 0.0, 1.0], PI));
+    }
+
+    fn yaw(angle: f64) -> Quaternion {
+        Quaternion::from_axis_angle([0.0, 0.0, 1.0], angle)
+    }
+
+    fn pose(translation: [f64; 3], rotation: Quaternion) -> Pose {
+        Pose {
+            translation,
+            rotation,
+        }
+    }
+
+    fn point_in(frame: &str, point: [f64; 3]) -> PoseInFrame {
+        PoseInFrame {
+            reference_frame: frame.to_string(),
+            pose: Some(pose(point, Quaternion::identity()).to_proto()),
+        }
+    }
+
+    fn translation(pose: &PoseInFrame) -> [f64; 3] {
+        Pose::from_proto(pose.pose.as_ref().unwrap()).translation
+    }
+
+    /// A base turned left, carrying an arm with a camera at its end
+    fn robot_frames() -> FrameSystem {
+        let mut frames = FrameSystem::new();
+        // children can be added before their parent
+        frames
+            .add_frame("camera", "arm", pose([0.0, 0.0, 20.0], yaw(PI)))
+            .unwrap();
+        frames
+            .add_frame(
+                "arm",
+                "base",
+                pose([0.0, 50.0, 0.0], Quaternion::identity()),
+            )
+            .unwrap();
+        frames
+            .add_frame("base", "", pose([100.0, 0.0, 0.0], yaw(FRAC_PI_2)))
+            .unwrap();
+        frames
+    }
+
+    #[test]
+    fn world_pose_composes_the_ancestors() {
+        let frames = robot_frames();
+        let base = frames.world_pose("base").unwrap();
+        assert_close(base.translation, [100.0, 0.0, 0.0]);
+        let camera = frames.world_pose("camera").unwrap();
+        assert_close(camera.translation, [50.0, 0.0, 20.0]);
+        assert_same_rotation(camera.rotation, yaw(-FRAC_PI_2));
+        assert_eq!(frames.world_pose(WORLD_FRAME).unwrap(), Pose::identity());
+    }
+
+    #[test]
+    fn transform_pose_moves_points_between_frames() {
+        let frames = robot_frames();
+        // the camera looks backward relative to the base, which faces +y
+        let seen = point_in("camera", [10.0, 0.0, 0.0]);
+        let in_world = frames.transform_pose(&seen, "").unwrap();
+        assert_eq!(in_world.reference_frame, WORLD_FRAME);
+        assert_close(translation(&in_world), [50.0, -10.0, 20.0]);
+        let in_base = frames.transform_pose(&seen, "base").unwrap();
+        assert_eq!(in_base.reference_frame, "base");
+        assert_close(translation(&in_base), [-10.0, 50.0, 20.0]);
+
+        let back = frames.transform_pose(&in_world, "camera").unwrap();
+        assert_close(translation(&back), [10.0, 0.0, 0.0]);
+        let back = frames.transform_pose(&in_base, "camera").unwrap();
+        assert_close(translation(&back), [10.0, 0.0, 0.0]);
+        // without a reference frame the pose is in the world frame
+        let unset = frames
+            .transform_pose(&point_in("", [50.0, -10.0, 20.0]), "camera")
+            .unwrap();
+        assert_close(translation(&unset), [10.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn transform_pose_uses_the_transforms_of_the_request() {
+        let mut frames = robot_frames();
+        frames
+            .add_transform(&Transform {
+                reference_frame: "target".to_string(),
+                pose_in_observer_frame: Some(point_in("camera", [30.0, 0.0, 0.0])),
+                physical_object: None,
+            })
+            .unwrap();
+        let in_world = frames
+            .transform_pose(&point_in("target", [0.0; 3]), WORLD_FRAME)
+            .unwrap();
+        assert_close(translation(&in_world), [50.0, -30.0, 20.0]);
+        assert!(matches!(
+            frames.add_transform(&Transform {
+                reference_frame: "nowhere".to_string(),
+                pose_in_observer_frame: None,
+                physical_object: None,
+            }),
+            Err(GrpcError::RpcInvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn inverse_undoes_a_pose() {
+        let p = pose(
+            [1.0, -2.0, 3.0],
+            Quaternion::from_axis_angle([1.0, 1.0, 0.0], 0.7),
+        );
+        for identity in [p.compose(&p.inverse()), p.inverse().compose(&p)] {
+            assert_close(identity.translation, [0.0; 3]);
+            assert_same_rotation(identity.rotation, Quaternion::identity());
+        }
+        let twice = p.inverse().inverse();
+        assert_close(twice.translation, p.translation);
+        assert_same_rotation(twice.rotation, p.rotation);
+    }
+
+    #[test]
+    fn missing_parents_are_reported() {
+        let mut frames = robot_frames();
+        frames
+            .add_frame("gripper", "wrist", Pose::identity())
+            .unwrap();
+        assert_eq!(
+            frames.world_pose("gripper"),
+            Err(GrpcError::RpcInvalidArgument(
+                "frame wrist doesn't exist".to_string()
+            ))
+        );
+        assert!(frames
+            .transform_pose(&point_in("camera", [0.0; 3]), "gripper")
+            .is_err());
+        assert!(frames
+            .transform_pose(&point_in("unknown", [0.0; 3]), "")
+            .is_err());
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let mut frames = robot_frames();
+        frames.add_frame("a", "b", Pose::identity()).unwrap();
+        frames.add_frame("b", "c", Pose::identity()).unwrap();
+        frames.add_frame("c", "a", Pose::identity()).unwrap();
+        assert_eq!(
+            frames.world_pose("a"),
+            Err(GrpcError::RpcInvalidArgument(
+                "frame a is part of a cycle".to_string()
+            ))
+        );
+        assert!(frames
+            .transform_pose(&point_in("camera", [0.0; 3]), "c")
+            .is_err());
+        // the rest of the tree is still usable
+        assert!(frames.world_pose("camera").is_ok());
+    }
+
+    #[test]
+    fn the_world_frame_has_no_parent() {
+        let mut frames = FrameSystem::new();
+        assert!(frames
+            .add_frame(WORLD_FRAME, "base", Pose::identity())
+            .is_err());
+        assert!(frames.add_frame("base", "base", Pose::identity()).is_err());
+        assert!(frames.configs().is_empty());
+    }
+}
@@ -0,0 +1,473 @@
+#![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::board::Board;
+use crate::common::encoder::{Encoder, EncoderPositionType};
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::motor::Motor;
+use crate::common::status::Status;
+use log::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait Gantry: Status + Actuator + DoCommand {
+    /// Position of every axis, in mm from its home
+    fn get_position(&self) -> anyhow::Result<Vec<f64>>;
+    /// Start moving every axis to its position in `positions_mm`. Returns the duration after
+    /// which the gantry should be stopped if it cannot tell by itself when it got there.
+    fn move_to_position(&mut self, positions_mm: &[f64]) -> anyhow::Result<Option<Duration>>;
+    /// Length of every axis in mm
+    fn get_lengths(&self) -> anyhow::Result<Vec<f64>>;
+}
+
+impl<L> Gantry for Mutex<L>
+where
+    L: ?Sized + Gantry,
+{
+    fn get_position(&self) -> anyhow::Result<Vec<f64>> {
+        self.lock().unwrap().get_position()
+    }
+    fn move_to_position(&mut self, positions_mm: &[f64]) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().move_to_position(positions_mm)
+    }
+    fn get_lengths(&self) -> anyhow::Result<Vec<f64>> {
+        self.lock().unwrap().get_lengths()
+    }
+}
+
+impl<A> Gantry for Arc<Mutex<A>>
+where
+    A: ?Sized + Gantry,
+{
+    fn get_position(&self) -> anyhow::Result<Vec<f64>> {
+        self.lock().unwrap().get_position()
+    }
+    fn move_to_position(&mut self, positions_mm: &[f64]) -> anyhow::Result<Option<Duration>> {
+        self.lock().unwrap().move_to_position(positions_mm)
+    }
+    fn get_lengths(&self) -> anyhow::Result<Vec<f64>> {
+        self.lock().unwrap().get_lengths()
+    }
+}
+
+fn list_value(values: &[f64]) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::ListValue(
+            prost_types::ListValue {
+                values: values
+                    .iter()
+                    .map(|v| prost_types::Value {
+                        kind: Some(prost_types::value::Kind::NumberValue(*v)),
+                    })
+                    .collect(),
+            },
+        )),
+    }
+}
+
+/// Status of a gantry as described by `viam.component.gantry.v1.Status`
+pub(crate) fn gantry_status(
+    positions_mm: &[f64],
+    lengths_mm: &[f64],
+    is_moving: bool,
+) -> prost_types::Struct {
+    prost_types::Struct {
+        fields: BTreeMap::from([
+            ("positions_mm".to_string(), list_value(positions_mm)),
+            ("lengths_mm".to_string(), list_value(lengths_mm)),
+            (
+                "is_moving".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::BoolValue(is_moving)),
+                },
+            ),
+        ]),
+    }
+}
+
+/// Homing gives up on reaching a limit switch after that long
+static HOMING_TIMEOUT: Duration = Duration::from_secs(60);
+static HOMING_POLL_PERIOD: Duration = Duration::from_millis(5);
+
+/// Where the axis starts and how far it travels per tick, found by homing
+#[derive(Clone, Copy, Debug)]
+struct AxisCalibration {
+    home_ticks: f64,
+    mm_per_tick: f64,
+}
+
+/// Gantry with a single axis moved by a motor, positive rpm moving away from home. The
+/// position is read from an encoder, which can be shared with the motor, and a limit switch
+/// at each end of the axis reads `pressed_high` on its pin of `board` when pressed. The
+/// gantry has to be homed before it can move or tell its position, moves are kept within
+/// the limit switches.
+pub struct SingleAxisGantry<M, E> {
+    motor: M,
+    encoder: E,
+    board: Arc<Mutex<dyn Board>>,
+    /// pins of the limit switches at home and at the far end
+    limit_pins: (i32, i32),
+    pressed_high: bool,
+    length_mm: f64,
+    rpm: f64,
+    calibration: Option<AxisCalibration>,
+    /// ticks to reach and whether they are away from home
+    target: Option<(f64, bool)>,
+}
+
+impl<M, E> SingleAxisGantry<M, E>
+where
+    M: Motor,
+    E: Encoder,
+{
+    /// `length_mm` is the distance between the limit switches, `rpm` the speed of the motor
+    /// when moving and homing
+    pub fn new(
+        motor: M,
+        encoder: E,
+        board: Arc<Mutex<dyn Board>>,
+        limit_pins: (i32, i32),
+        pressed_high: bool,
+        length_mm: f64,
+        rpm: f64,
+    ) -> anyhow::Result<Self> {
+        if length_mm <= 0.0 || rpm <= 0.0 {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "length and rpm should be strictly positive".to_string(),
+            )
+            .into());
+        }
+        if !encoder.get_properties().ticks_count_supported {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "the encoder of a gantry should count ticks".to_string(),
+            )
+            .into());
+        }
+        Ok(SingleAxisGantry {
+            motor,
+            encoder,
+            board,
+            limit_pins,
+            pressed_high,
+            length_mm,
+            rpm,
+            calibration: None,
+            target: None,
+        })
+    }
+    /// Find both ends of the axis by running into the limit switches, the ticks counted in
+    /// between give the mm per tick. Blocks until done, it is meant to run before serving.
+    pub fn home(&mut self) -> anyhow::Result<()> {
+        self.target = None;
+        let home_ticks = self.run_to_limit(false)?;
+        let end_ticks = self.run_to_limit(true)?;
+        if end_ticks <= home_ticks {
+            return Err(GrpcError::RpcInternal(format!(
+                "encoder counted {} ticks from home to the far end",
+                end_ticks - home_ticks
+            ))
+            .into());
+        }
+        let calibration = AxisCalibration {
+            home_ticks,
+            mm_per_tick: self.length_mm / (end_ticks - home_ticks),
+        };
+        info!("gantry homed: {:?}", calibration);
+        self.calibration = Some(calibration);
+        Ok(())
+    }
+    /// Millimeters travelled per tick of the encoder, known once homed
+    pub fn mm_per_tick(&self) -> Option<f64> {
+        self.calibration.map(|c| c.mm_per_tick)
+    }
+    fn limit_pressed(&self, away: bool) -> anyhow::Result<bool> {
+        let pin = if away {
+            self.limit_pins.1
+        } else {
+            self.limit_pins.0
+        };
+        Ok(self.board.lock().unwrap().get_gpio_level(pin)? == self.pressed_high)
+    }
+    fn ticks(&self) -> anyhow::Result<f64> {
+        self.encoder.get_position(EncoderPositionType::Ticks)
+    }
+    /// Move until the limit switch `away` from home (or at home) is pressed and return the
+    /// ticks counted there
+    fn run_to_limit(&mut self, away: bool) -> anyhow::Result<f64> {
+        let rpm = if away { self.rpm } else { -self.rpm };
+        self.motor.go_for(rpm, 0.0)?;
+        let start = Instant::now();
+        let reached = loop {
+            match self.limit_pressed(away) {
+                Ok(true) => break self.ticks(),
+                Ok(false) if start.elapsed() < HOMING_TIMEOUT => {
+                    std::thread::sleep(HOMING_POLL_PERIOD)
+                }
+                Ok(false) => {
+                    break Err(GrpcError::RpcInternal(format!(
+                        "limit switch not reached after {:?}",
+                        HOMING_TIMEOUT
+                    ))
+                    .into())
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        self.motor.stop()?;
+        reached
+    }
+}
+
+impl<M, E> Gantry for SingleAxisGantry<M, E>
+where
+    M: Motor,
+    E: Encoder,
+{
+    fn get_position(&self) -> anyhow::Result<Vec<f64>> {
+        let calibration = self
+            .calibration
+            .ok_or_else(|| GrpcError::RpcFailedPrecondition("gantry isn't homed".to_string()))?;
+        Ok(vec![
+            (self.ticks()? - calibration.home_ticks) * calibration.mm_per_tick,
+        ])
+    }
+    /// The encoder tells when the gantry got there, it stops moving once it did
+    fn move_to_position(&mut self, positions_mm: &[f64]) -> anyhow::Result<Option<Duration>> {
+        let position_mm = match positions_mm {
+            [position_mm] => *position_mm,
+            _ => {
+                return Err(GrpcError::RpcInvalidArgument(format!(
+                    "expected 1 position, got {}",
+                    positions_mm.len()
+                ))
+                .into())
+            }
+        };
+        if !(0.0..=self.length_mm).contains(&position_mm) {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "position {}mm is outside of the axis [0, {}]",
+                position_mm, self.length_mm
+            ))
+            .into());
+        }
+        let calibration = self
+            .calibration
+            .ok_or_else(|| GrpcError::RpcFailedPrecondition("gantry isn't homed".to_string()))?;
+        let target = calibration.home_ticks + position_mm / calibration.mm_per_tick;
+        let current = self.ticks()?;
+        if target == current {
+            return Ok(None);
+        }
+        let away = target > current;
+        self.motor
+            .go_for(if away { self.rpm } else { -self.rpm }, 0.0)?;
+        self.target = Some((target, away));
+        Ok(None)
+    }
+    fn get_lengths(&self) -> anyhow::Result<Vec<f64>> {
+        Ok(vec![self.length_mm])
+    }
+}
+
+impl<M, E> Actuator for SingleAxisGantry<M, E>
+where
+    M: Motor,
+    E: Encoder,
+{
+    /// Moving stops once the target is reached, or if the limit switch ahead is pressed
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        let (target, away) = match self.target {
+            Some(target) => target,
+            None => return self.motor.is_moving(),
+        };
+        if self.limit_pressed(away)? {
+            return Ok(false);
+        }
+        let ticks = self.ticks()?;
+        Ok(if away { ticks < target } else { ticks > target })
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.target = None;
+        self.motor.stop()
+    }
+}
+
+impl<M, E> DoCommand for SingleAxisGantry<M, E>
+where
+    M: Motor,
+    E: Encoder,
+{
+}
+
+impl<M, E> Status for SingleAxisGantry<M, E>
+where
+    M: Motor,
+    E: Encoder,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        let positions_mm = match self.calibration {
+            Some(_) => self.get_position()?,
+            None => Vec::new(),
+        };
+        Ok(Some(gantry_status(
+            &positions_mm,
+            &self.get_lengths()?,
+            self.is_moving()?,
+        )))
+    }
+}
+
+pub struct FakeGantry {
+    position_mm: f64,
+    length_mm: f64,
+}
+
+impl FakeGantry {
+    pub fn new(length_mm: f64) -> Self {
+        FakeGantry {
+            position_mm: 0.0,
+            length_mm,
+        }
+    }
+}
+
+impl Gantry for FakeGantry {
+    fn get_position(&self) -> anyhow::Result<Vec<f64>> {
+        Ok(vec![self.position_mm])
+    }
+    fn move_to_position(&mut self, positions_mm: &[f64]) -> anyhow::Result<Option<Duration>> {
+        info!("moving gantry to {:?}", positions_mm);
+        if let Some(position_mm) = positions_mm.first() {
+            self.position_mm = position_mm.clamp(0.0, self.length_mm);
+        }
+        Ok(None)
+    }
+    fn get_lengths(&self) -> anyhow::Result<Vec<f64>> {
+        Ok(vec![self.length_mm])
+    }
+}
+
+impl Actuator for FakeGantry {
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        info!("stopping gantry");
+        Ok(())
+    }
+}
+
+impl DoCommand for FakeGantry {}
+
+impl Status for FakeGantry {
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(gantry_status(
+            &[self.position_mm],
+            &[self.length_mm],
+            false,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::board::FakeBoard;
+    use crate::common::encoder::FakeEncoder;
+    use crate::common::motor::FakeMotor;
+
+    type TestGantry = SingleAxisGantry<Arc<Mutex<FakeMotor>>, Arc<Mutex<FakeEncoder>>>;
+
+    /// Homed gantry 100mm long over 1000 ticks, the fake board reads every limit switch high
+    #[allow(clippy::arc_with_non_send_sync)]
+    fn homed_gantry(
+        pressed_high: bool,
+    ) -> (TestGantry, Arc<Mutex<FakeMotor>>, Arc<Mutex<FakeEncoder>>) {
+        let motor = Arc::new(Mutex::new(FakeMotor::new()));
+        let encoder = Arc::new(Mutex::new(FakeEncoder::new(0)));
+        let board = Arc::new(Mutex::new(FakeBoard::new(Vec::new())));
+        let mut gantry = SingleAxisGantry::new(
+            motor.clone(),
+            encoder.clone(),
+            board,
+            (1, 2),
+            pressed_high,
+            100.0,
+            60.0,
+        )
+        .unwrap();
+        gantry.calibration = Some(AxisCalibration {
+            home_ticks: 0.0,
+            mm_per_tick: 0.1,
+        });
+        (gantry, motor, encoder)
+    }
+
+    fn assert_invalid_argument(result: anyhow::Result<Option<Duration>>) {
+        let err = result.unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<GrpcError>(),
+                Some(GrpcError::RpcInvalidArgument(_))
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn fake_gantry_stays_on_its_axis() {
+        let mut gantry = FakeGantry::new(100.0);
+        gantry.move_to_position(&[40.0]).unwrap();
+        assert_eq!(gantry.get_position().unwrap(), vec![40.0]);
+        gantry.move_to_position(&[150.0]).unwrap();
+        assert_eq!(gantry.get_position().unwrap(), vec![100.0]);
+        gantry.move_to_position(&[-1.0]).unwrap();
+        assert_eq!(gantry.get_position().unwrap(), vec![0.0]);
+        assert_eq!(gantry.get_lengths().unwrap(), vec![100.0]);
+    }
+
+    #[test]
+    fn moves_outside_of_the_axis_are_refused() {
+        let (mut gantry, motor, _) = homed_gantry(false);
+        assert_invalid_argument(gantry.move_to_position(&[-0.1]));
+        assert_invalid_argument(gantry.move_to_position(&[100.1]));
+        assert_invalid_argument(gantry.move_to_position(&[]));
+        assert_invalid_argument(gantry.move_to_position(&[10.0, 10.0]));
+        assert!(!motor.is_moving().unwrap());
+        gantry.move_to_position(&[100.0]).unwrap();
+        assert!(motor.is_moving().unwrap());
+    }
+
+    #[test]
+    fn moves_need_homing() {
+        let (mut gantry, motor, _) = homed_gantry(false);
+        gantry.calibration = None;
+        let err = gantry.move_to_position(&[10.0]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GrpcError>(),
+            Some(GrpcError::RpcFailedPrecondition(_))
+        ));
+        assert!(!motor.is_moving().unwrap());
+    }
+
+    #[test]
+    fn moves_stop_at_the_target() {
+        let (mut gantry, motor, encoder) = homed_gantry(false);
+        encoder.lock().unwrap().set_ticks(500);
+        gantry.move_to_position(&[20.0]).unwrap();
+        assert_eq!(motor.is_powered().unwrap(), (true, -0.6));
+        assert!(gantry.is_moving().unwrap());
+        encoder.lock().unwrap().set_ticks(199);
+        assert!(!gantry.is_moving().unwrap());
+        assert!((gantry.get_position().unwrap()[0] - 19.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moves_stop_at_the_limit_switch_ahead() {
+        let (mut gantry, _, _) = homed_gantry(true);
+        gantry.move_to_position(&[20.0]).unwrap();
+        assert!(!gantry.is_moving().unwrap());
+    }
+}
@@ -0,0 +1,60 @@
+#![allow(dead_code)]
+use std::sync::{Arc, Mutex};
+
+use crate::common::error::GrpcError;
+
+/// Escape hatch for driver specific operations (e.g. taring a scale) the component APIs don't
+/// cover, every resource implements it and most keep the unimplemented default
+pub trait DoCommand {
+    fn do_command(&mut self, _command: prost_types::Struct) -> anyhow::Result<prost_types::Struct> {
+        Err(GrpcError::RpcUnimplemented("do_command is not implemented".to_string()).into())
+    }
+}
+
+impl<L> DoCommand for Mutex<L>
+where
+    L: ?Sized + DoCommand,
+{
+    fn do_command(&mut self, command: prost_types::Struct) -> anyhow::Result<prost_types::Struct> {
+        self.get_mut().unwrap().do_command(command)
+    }
+}
+
+impl<A> DoCommand for Arc<Mutex<A>>
+where
+    A: ?Sized + DoCommand,
+{
+    fn do_command(&mut self, command: prost_types::Struct) -> anyhow::Result<prost_types::Struct> {
+        self.lock().unwrap().do_command(command)
+    }
+}
+
+/// Resource which only speaks DoCommand
+pub trait GenericComponent: DoCommand {}
+
+impl<L> GenericComponent for Mutex<L> where L: ?Sized + GenericComponent {}
+
+impl<A> GenericComponent for Arc<Mutex<A>> where A: ?Sized + GenericComponent {}
+
+/// Sends the commands it receives back
+pub struct FakeGenericComponent {}
+
+impl FakeGenericComponent {
+    pub fn new() -> Self {
+        FakeGenericComponent {}
+    }
+}
+
+impl Default for FakeGenericComponent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DoCommand for FakeGenericComponent {
+    fn do_command(&mut self, command: prost_types::Struct) -> anyhow::Result<prost_types::Struct> {
+        Ok(command)
+    }
+}
+
+impl GenericComponent for FakeGenericComponent {}
@@ -0,0 +1,437 @@
+#![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::analog::AnalogReader;
+use crate::common::board::Board;
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::motor::Motor;
+use crate::common::servo::Servo;
+use crate::common::status::Status;
+use log::*;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait Gripper: Status + Actuator + DoCommand {
+    /// Start opening the gripper. Returns the duration after which the gripper should be
+    /// stopped if it cannot tell by itself when it is open.
+    fn open(&mut self) -> anyhow::Result<Option<Duration>>;
+    /// Start closing the gripper. Returns the duration after which the gripper should be
+    /// stopped if it cannot tell by itself when it is closed.
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>>;
+    /// Whether the last grab closed the gripper on something, known once it stopped
+    fn is_holding(&self) -> anyhow::Result<bool>;
+}
+
+impl<L> Gripper for Mutex<L>
+where
+    L: ?Sized + Gripper,
+{
+    fn open(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().open()
+    }
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().grab()
+    }
+    fn is_holding(&self) -> anyhow::Result<bool> {
+        self.lock().unwrap().is_holding()
+    }
+}
+
+impl<A> Gripper for Arc<Mutex<A>>
+where
+    A: ?Sized + Gripper,
+{
+    fn open(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.lock().unwrap().open()
+    }
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.lock().unwrap().grab()
+    }
+    fn is_holding(&self) -> anyhow::Result<bool> {
+        self.lock().unwrap().is_holding()
+    }
+}
+
+/// Status of a gripper as described by `viam.component.gripper.v1.Status`
+pub(crate) fn gripper_status(is_moving: bool) -> prost_types::Struct {
+    prost_types::Struct {
+        fields: BTreeMap::from([(
+            "is_moving".to_string(),
+            prost_types::Value {
+                kind: Some(prost_types::value::Kind::BoolValue(is_moving)),
+            },
+        )]),
+    }
+}
+
+/// Gripper whose jaws are moved by a servo between two angles. The servo can't tell whether
+/// something is between the jaws, the gripper is assumed to hold something after a grab.
+pub struct ServoGripper<S> {
+    servo: S,
+    open_angle_deg: u32,
+    closed_angle_deg: u32,
+    closed: bool,
+}
+
+impl<S> ServoGripper<S>
+where
+    S: Servo,
+{
+    pub fn new(servo: S, open_angle_deg: u32, closed_angle_deg: u32) -> anyhow::Result<Self> {
+        if open_angle_deg == closed_angle_deg {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "open and closed angles should be different".to_string(),
+            )
+            .into());
+        }
+        Ok(ServoGripper {
+            servo,
+            open_angle_deg,
+            closed_angle_deg,
+            closed: false,
+        })
+    }
+}
+
+impl<S> Gripper for ServoGripper<S>
+where
+    S: Servo,
+{
+    fn open(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.servo.move_to(self.open_angle_deg)?;
+        self.closed = false;
+        Ok(None)
+    }
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.servo.move_to(self.closed_angle_deg)?;
+        self.closed = true;
+        Ok(None)
+    }
+    fn is_holding(&self) -> anyhow::Result<bool> {
+        Ok(self.closed)
+    }
+}
+
+impl<S> Actuator for ServoGripper<S>
+where
+    S: Servo,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        self.servo.is_moving()
+    }
+    /// A hobby servo can't stop halfway without letting go of what the jaws hold, the servo
+    /// keeps going to the angle it was last sent to
+    fn stop(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S> DoCommand for ServoGripper<S> where S: Servo {}
+
+impl<S> Status for ServoGripper<S>
+where
+    S: Servo,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(gripper_status(self.is_moving()?)))
+    }
+}
+
+/// What tells a motor gripper its jaws closed on something
+pub enum GripTrigger {
+    /// The current drawn by the motor, read by an analog reader, reaches `threshold` when the
+    /// jaws squeeze something
+    Current {
+        reader: Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>,
+        threshold: u16,
+    },
+    /// A switch on the jaws, pressed by the object being grabbed, reads `pressed_high` on `pin`
+    /// of `board` when pressed
+    Switch {
+        board: Arc<Mutex<dyn Board>>,
+        pin: i32,
+        pressed_high: bool,
+    },
+}
+
+impl GripTrigger {
+    fn tripped(&self) -> anyhow::Result<bool> {
+        match self {
+            GripTrigger::Current { reader, threshold } => {
+                Ok(reader.borrow_mut().read()? >= *threshold)
+            }
+            GripTrigger::Switch {
+                board,
+                pin,
+                pressed_high,
+            } => Ok(board.lock().unwrap().get_gpio_level(*pin)? == *pressed_high),
+        }
+    }
+}
+
+/// Motors draw a surge of current when they start, the trigger is ignored for that long after
+/// the gripper starts closing
+static INRUSH_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+enum MotorGripperState {
+    Idle,
+    Opening,
+    Closing(Instant),
+}
+
+/// Gripper whose jaws are moved by a motor, positive rpm closing them. Grabbing closes the
+/// jaws until the trigger trips, meaning they hold something, or until `close_timeout`
+/// elapsed, meaning they closed on nothing. Opening turns the motor back by
+/// `open_revolutions`.
+pub struct MotorGripper<M> {
+    motor: M,
+    trigger: GripTrigger,
+    rpm: f64,
+    open_revolutions: f64,
+    close_timeout: Duration,
+    state: MotorGripperState,
+    /// latched as soon as the trigger trips, a reading falling back doesn't release the grab
+    tripped: Cell<bool>,
+    holding: bool,
+}
+
+impl<M> MotorGripper<M>
+where
+    M: Motor,
+{
+    pub fn new(
+        motor: M,
+        trigger: GripTrigger,
+        rpm: f64,
+        open_revolutions: f64,
+        close_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        if rpm <= 0.0 || open_revolutions <= 0.0 {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "rpm and open revolutions should be strictly positive".to_string(),
+            )
+            .into());
+        }
+        if close_timeout <= INRUSH_PERIOD {
+            return Err(GrpcError::RpcFailedPrecondition(format!(
+                "close timeout should be longer than {:?}",
+                INRUSH_PERIOD
+            ))
+            .into());
+        }
+        Ok(MotorGripper {
+            motor,
+            trigger,
+            rpm,
+            open_revolutions,
+            close_timeout,
+            state: MotorGripperState::Idle,
+            tripped: Cell::new(false),
+            holding: false,
+        })
+    }
+}
+
+impl<M> Gripper for MotorGripper<M>
+where
+    M: Motor,
+{
+    fn open(&mut self) -> anyhow::Result<Option<Duration>> {
+        let dur = self.motor.go_for(-self.rpm, self.open_revolutions)?;
+        self.state = MotorGripperState::Opening;
+        self.holding = false;
+        Ok(dur)
+    }
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>> {
+        self.motor.go_for(self.rpm, 0.0)?;
+        self.state = MotorGripperState::Closing(Instant::now());
+        self.tripped.set(false);
+        self.holding = false;
+        Ok(None)
+    }
+    fn is_holding(&self) -> anyhow::Result<bool> {
+        Ok(self.holding)
+    }
+}
+
+impl<M> Actuator for MotorGripper<M>
+where
+    M: Motor,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        match self.state {
+            MotorGripperState::Idle => Ok(false),
+            MotorGripperState::Opening => self.motor.is_moving(),
+            MotorGripperState::Closing(since) => {
+                let elapsed = since.elapsed();
+                if elapsed >= INRUSH_PERIOD && !self.tripped.get() && self.trigger.tripped()? {
+                    self.tripped.set(true);
+                }
+                Ok(!self.tripped.get() && elapsed < self.close_timeout)
+            }
+        }
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        if let MotorGripperState::Closing(_) = self.state {
+            self.holding = self.tripped.get();
+        }
+        self.state = MotorGripperState::Idle;
+        self.motor.stop()
+    }
+}
+
+impl<M> DoCommand for MotorGripper<M> where M: Motor {}
+
+impl<M> Status for MotorGripper<M>
+where
+    M: Motor,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(gripper_status(self.is_moving()?)))
+    }
+}
+
+pub struct FakeGripper {
+    holding: bool,
+}
+
+impl FakeGripper {
+    pub fn new() -> Self {
+        FakeGripper { holding: false }
+    }
+}
+
+impl Default for FakeGripper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gripper for FakeGripper {
+    fn open(&mut self) -> anyhow::Result<Option<Duration>> {
+        info!("opening gripper");
+        self.holding = false;
+        Ok(None)
+    }
+    fn grab(&mut self) -> anyhow::Result<Option<Duration>> {
+        info!("grabbing");
+        self.holding = true;
+        Ok(None)
+    }
+    fn is_holding(&self) -> anyhow::Result<bool> {
+        Ok(self.holding)
+    }
+}
+
+impl Actuator for FakeGripper {
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        info!("stopping gripper");
+        Ok(())
+    }
+}
+
+impl DoCommand for FakeGripper {}
+
+impl Status for FakeGripper {
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(gripper_status(false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::motor::FakeMotor;
+
+    /// Reads whatever the test sets
+    struct TestReader(Rc<Cell<u16>>);
+
+    impl AnalogReader<u16> for TestReader {
+        type Error = anyhow::Error;
+        fn read(&mut self) -> anyhow::Result<u16> {
+            Ok(self.0.get())
+        }
+        fn name(&self) -> String {
+            "current".to_string()
+        }
+    }
+
+    type TestGripper = MotorGripper<Arc<Mutex<FakeMotor>>>;
+
+    fn motor_gripper(
+        close_timeout: Duration,
+    ) -> (TestGripper, Arc<Mutex<FakeMotor>>, Rc<Cell<u16>>) {
+        let motor = Arc::new(Mutex::new(FakeMotor::new()));
+        let current = Rc::new(Cell::new(0));
+        let trigger = GripTrigger::Current {
+            reader: Rc::new(RefCell::new(TestReader(current.clone()))),
+            threshold: 500,
+        };
+        let gripper = MotorGripper::new(motor.clone(), trigger, 50.0, 2.0, close_timeout).unwrap();
+        (gripper, motor, current)
+    }
+
+    #[test]
+    fn fake_gripper_holds_after_a_grab() {
+        let mut gripper = FakeGripper::new();
+        assert!(!gripper.is_holding().unwrap());
+        gripper.grab().unwrap();
+        assert!(gripper.is_holding().unwrap());
+        gripper.open().unwrap();
+        assert!(!gripper.is_holding().unwrap());
+    }
+
+    #[test]
+    fn motor_gripper_ignores_the_inrush_current() {
+        let (mut gripper, motor, current) = motor_gripper(Duration::from_secs(10));
+        gripper.grab().unwrap();
+        assert!(motor.is_moving().unwrap());
+        current.set(1000);
+        assert!(gripper.is_moving().unwrap());
+    }
+
+    #[test]
+    fn motor_gripper_trip_is_latched() {
+        let (mut gripper, motor, current) = motor_gripper(Duration::from_secs(10));
+        gripper.grab().unwrap();
+        std::thread::sleep(INRUSH_PERIOD);
+        assert!(gripper.is_moving().unwrap());
+        current.set(1000);
+        assert!(!gripper.is_moving().unwrap());
+        // the jaws stalling on the object makes the current drop, the grab is still done
+        current.set(0);
+        assert!(!gripper.is_moving().unwrap());
+        gripper.stop().unwrap();
+        assert!(!motor.is_moving().unwrap());
+        assert!(gripper.is_holding().unwrap());
+        // a new grab forgets the previous trip
+        gripper.grab().unwrap();
+        assert!(!gripper.is_holding().unwrap());
+        assert!(gripper.is_moving().unwrap());
+    }
+
+    #[test]
+    fn motor_gripper_closing_on_nothing_times_out() {
+        let (mut gripper, _, _) = motor_gripper(INRUSH_PERIOD * 2);
+        gripper.grab().unwrap();
+        std::thread::sleep(INRUSH_PERIOD * 2);
+        assert!(!gripper.is_moving().unwrap());
+        gripper.stop().unwrap();
+        assert!(!gripper.is_holding().unwrap());
+    }
+
+    #[test]
+    fn motor_gripper_opens_by_turning_back() {
+        let (mut gripper, motor, _) = motor_gripper(Duration::from_secs(10));
+        let dur = gripper.open().unwrap();
+        assert_eq!(dur, Some(Duration::from_millis(2400)));
+        assert_eq!(motor.is_powered().unwrap(), (true, -0.5));
+    }
+}
@@ -1,12 +1,27 @@
-use std::{fmt::Debug, marker::PhantomData, sync::Arc, sync::Mutex, time::Duration};
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    sync::Arc,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use crate::{
+    common::actuator::Actuator,
+    common::auth::Authenticator,
     common::board::Board,
+    common::error::GrpcError,
+    common::movement_sensor::{
+        reading_not_supported, MovementSensor, MovementSensorSupportedProperties,
+    },
+    common::operation::{CancellationToken, OPID_METADATA_KEY},
+    common::reflection::{self, Reflection, REFLECTION_METHOD, REFLECTION_SERVICE},
     common::robot::LocalRobot,
-    proto::{self, component, robot},
+    common::session::SESSION_METADATA_KEY,
+    proto::{self, common::v1::ResourceName, component, robot},
 };
-use bytes::{BufMut, BytesMut};
-use futures_lite::{future, Future};
+use bytes::{buf::UninitSlice, BufMut, BytesMut};
+use futures_lite::{future, Future, Stream};
 use hyper::{
     body::{self, Bytes, HttpBody},
     http::HeaderValue,
@@ -15,8 +30,10 @@ use hyper::{
 };
 use log::*;
 use prost::Message;
+use smol::channel::{bounded, Receiver, Sender};
 use smol_timeout::TimeoutExt;
 use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
 use std::pin::Pin;
 use std::rc::Rc;
@@ -27,10 +44,157 @@ static GRPC_BUFFER_SIZE: usize = 10240;
 #[cfg(not(feature = "camera"))]
 static GRPC_BUFFER_SIZE: usize = 4096;
 
-#[derive(Clone)]
+/// Responses larger than this are split in several DATA frames, it is also the largest single
+/// allocation made while encoding a response. Matches the default HTTP/2 SETTINGS_MAX_FRAME_SIZE.
+static GRPC_CHUNK_SIZE: usize = 16384;
+
+/// Default upper bound on the size of an encoded response, see `GrpcServer::set_max_response_size`
+#[cfg(feature = "camera")]
+static GRPC_MAX_RESPONSE_SIZE: usize = 262144;
+#[cfg(not(feature = "camera"))]
+static GRPC_MAX_RESPONSE_SIZE: usize = 65536;
+
+/// Polling period of StreamStatus when only changes are streamed
+static STREAM_STATUS_CHANGES_PERIOD: Duration = Duration::from_millis(200);
+
+/// How often a component stopping by itself is asked whether it is still moving
+static MOVING_POLL_PERIOD: Duration = Duration::from_millis(20);
+
+/// RPCs of this service are served without authorization, they are used to obtain a token
+static AUTH_SERVICE_PREFIX: &str = "/proto.rpc.v1.AuthService/";
+
+/// Number of messages a streaming handler can queue before being suspended until the client
+/// (and HTTP/2 flow control) lets us send more
+static GRPC_STREAM_QUEUE_SIZE: usize = 1;
+
+type GrpcStreamProducer = Pin<Box<dyn Future<Output = Result<(), GrpcError>>>>;
+
+/// Server side of a streaming response, the producer future is driven by the body when hyper
+/// polls for more data so a handler only makes progress when the client is able to receive.
+struct GrpcStream {
+    producer: Option<GrpcStreamProducer>,
+    receiver: Receiver<Bytes>,
+}
+
+/// Handle given to streaming handlers to push messages to the client
+pub struct GrpcStreamSender {
+    sender: Sender<Bytes>,
+}
+
+impl GrpcStreamSender {
+    /// Encode and queue a message, waits until there is room in the queue. Returns
+    /// `GrpcError::RpcCancelled` when the client went away.
+    pub async fn send_message<M: Message>(&self, m: M) -> Result<(), GrpcError> {
+        let size = 5 + m.encoded_len();
+        let mut buffer = ChunkedBuffer::new(
+            BytesMut::with_capacity(size.min(GRPC_CHUNK_SIZE)),
+            GRPC_MAX_RESPONSE_SIZE,
+        );
+        buffer.encode_message(&m)?;
+        for chunk in buffer.into_chunks() {
+            self.sender
+                .send(chunk)
+                .await
+                .map_err(|_| GrpcError::RpcCancelled)?;
+        }
+        Ok(())
+    }
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+/// Buffer holding one or more length prefixed messages as a list of chunks of at most
+/// GRPC_CHUNK_SIZE bytes, so large responses don't need a single contiguous allocation.
+/// Writing more than `max_size` bytes fails.
+pub struct ChunkedBuffer {
+    chunks: VecDeque<Bytes>,
+    current: BytesMut,
+    len: usize,
+    max_size: usize,
+}
+
+impl ChunkedBuffer {
+    /// Create a buffer which first writes into `first`
+    pub fn new(first: BytesMut, max_size: usize) -> Self {
+        ChunkedBuffer {
+            chunks: VecDeque::new(),
+            current: first,
+            len: 0,
+            max_size,
+        }
+    }
+
+    /// Append `m` prefixed by the 5-byte gRPC header, fails with RESOURCE_EXHAUSTED if the
+    /// message doesn't fit
+    pub fn encode_message<M: Message>(&mut self, m: &M) -> Result<(), GrpcError> {
+        // The header is a null byte, then 4 bytes containing the big-endian length of the
+        // data (*not* including this 5-byte header)
+        let len = m.encoded_len();
+        if 5 + len > self.remaining_mut() {
+            return Err(GrpcError::RpcResourceExhausted(format!(
+                "response of {} bytes exceeds the limit of {} bytes",
+                5 + len,
+                self.max_size
+            )));
+        }
+        self.put_u8(0);
+        self.put_u32(len.try_into().unwrap());
+        m.encode(self)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn into_chunks(mut self) -> VecDeque<Bytes> {
+        if !self.current.is_empty() {
+            let current = std::mem::take(&mut self.current);
+            self.chunks.push_back(current.freeze());
+        }
+        std::mem::take(&mut self.chunks)
+    }
+}
+
+unsafe impl BufMut for ChunkedBuffer {
+    fn remaining_mut(&self) -> usize {
+        self.max_size - self.len
+    }
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.current.advance_mut(cnt);
+        self.len += cnt;
+    }
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        // BufMut requires an empty slice exactly when nothing can be written anymore
+        let remaining = self.remaining_mut();
+        if remaining == 0 {
+            return UninitSlice::new(&mut []);
+        }
+        // the first chunk may have been allocated larger than GRPC_CHUNK_SIZE
+        if self.current.len() == self.current.capacity() || self.current.len() >= GRPC_CHUNK_SIZE {
+            let size = remaining.min(GRPC_CHUNK_SIZE);
+            let full = std::mem::replace(&mut self.current, BytesMut::with_capacity(size));
+            if !full.is_empty() {
+                self.chunks.push_back(full.freeze());
+            }
+        }
+        let room = remaining.min(GRPC_CHUNK_SIZE - self.current.len());
+        let chunk = self.current.chunk_mut();
+        let len = chunk.len().min(room);
+        &mut chunk[..len]
+    }
+}
+
 pub struct GrpcBody {
     _marker: PhantomData<*const ()>,
-    data: Option<Bytes>,
+    data: VecDeque<Bytes>,
+    stream: Option<GrpcStream>,
+    deadline: Option<smol::Timer>,
     trailers: Option<HeaderMap<HeaderValue>>,
 }
 
@@ -39,11 +203,109 @@ impl GrpcBody {
         let mut trailers = HeaderMap::new();
         trailers.insert("grpc-status", "0".parse().unwrap());
         GrpcBody {
-            data: None,
+            data: VecDeque::new(),
+            stream: None,
+            deadline: None,
             trailers: Some(trailers),
             _marker: PhantomData,
         }
     }
+
+    /// Create a body for a server streaming RPC. `handler` is given a `GrpcStreamSender` to
+    /// push messages with, the stream ends (and trailers are sent) once the future it returns
+    /// completes. The future is dropped if the client cancels the RPC.
+    pub fn new_streaming<F, Fut>(handler: F) -> Self
+    where
+        F: FnOnce(GrpcStreamSender) -> Fut,
+        Fut: Future<Output = Result<(), GrpcError>> + 'static,
+    {
+        let (sender, receiver) = bounded(GRPC_STREAM_QUEUE_SIZE);
+        let mut body = Self::new();
+        body.stream = Some(GrpcStream {
+            producer: Some(Box::pin(handler(GrpcStreamSender { sender }))),
+            receiver,
+        });
+        body
+    }
+
+    /// Abort a streaming response with DEADLINE_EXCEEDED if it is still running at `deadline`,
+    /// the handler's future is then dropped
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        if self.stream.is_some() {
+            self.deadline = Some(smol::Timer::at(deadline));
+        }
+    }
+
+    /// Keep `guard` alive until a streaming response is done being produced, a non streaming
+    /// body drops it right away
+    fn hold_while_streaming<G: 'static>(&mut self, guard: G) {
+        if let Some(producer) = self.stream.as_mut().and_then(|s| s.producer.take()) {
+            self.stream.as_mut().unwrap().producer = Some(Box::pin(async move {
+                let _guard = guard;
+                producer.await
+            }));
+        }
+    }
+
+    /// Replace the trailers with the status code and message of `err`
+    pub fn set_error(&mut self, err: &GrpcError) {
+        let trailers = self.trailers.get_or_insert_with(HeaderMap::new);
+        trailers.insert("grpc-status", HeaderValue::from(err.code() as i32));
+        if let Ok(msg) = HeaderValue::from_str(&percent_encode(&err.to_string())) {
+            trailers.insert("grpc-message", msg);
+        }
+        let details =
+            base64::encode_config(err.to_status().encode_to_vec(), base64::STANDARD_NO_PAD);
+        if let Ok(details) = HeaderValue::from_str(&details) {
+            trailers.insert("grpc-status-details-bin", details);
+        }
+    }
+}
+
+/// grpc-message is percent encoded, every byte outside of the printable ASCII range as well as
+/// '%' has to be escaped
+fn percent_encode(msg: &str) -> String {
+    let mut encoded = String::with_capacity(msg.len());
+    for b in msg.bytes() {
+        if (0x20..=0x7e).contains(&b) && b != b'%' {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn string_value(s: &str) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::StringValue(s.to_owned())),
+    }
+}
+
+fn number_value(n: f64) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::NumberValue(n)),
+    }
+}
+
+fn numbers_value(ns: &[f64]) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::ListValue(
+            prost_types::ListValue {
+                values: ns.iter().map(|n| number_value(*n)).collect(),
+            },
+        )),
+    }
+}
+
+/// Arguments of an operation as reported by GetOperations
+fn motion_arguments(fields: &[(&str, prost_types::Value)]) -> prost_types::Struct {
+    prost_types::Struct {
+        fields: fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect(),
+    }
 }
 
 impl Default for GrpcBody {
@@ -64,9 +326,41 @@ impl HttpBody for GrpcBody {
 
     fn poll_data(
         self: Pin<&mut Self>,
-        _: &mut Context<'_>,
+        cx: &mut Context<'_>,
     ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
-        Poll::Ready(self.get_mut().data.take().map(Ok))
+        let body = self.get_mut();
+        if let Some(data) = body.data.pop_front() {
+            return Poll::Ready(Some(Ok(data)));
+        }
+        let stream = match body.stream.as_mut() {
+            Some(stream) => stream,
+            None => return Poll::Ready(None),
+        };
+        if let Some(deadline) = body.deadline.as_mut() {
+            if Pin::new(deadline).poll(cx).is_ready() {
+                body.stream = None;
+                body.deadline = None;
+                body.set_error(&GrpcError::RpcDeadlineExceeded);
+                return Poll::Ready(None);
+            }
+        }
+        let mut failed = None;
+        if let Some(producer) = stream.producer.as_mut() {
+            if let Poll::Ready(ret) = producer.as_mut().poll(cx) {
+                // dropping the producer drops its sender, closing the channel once drained
+                stream.producer = None;
+                failed = ret.err();
+            }
+        }
+        let next = Pin::new(&mut stream.receiver).poll_next(cx);
+        if let Some(e) = failed {
+            body.set_error(&e);
+        }
+        match next {
+            Poll::Ready(Some(data)) => Poll::Ready(Some(Ok(data))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
     }
     fn poll_trailers(
         self: Pin<&mut Self>,
@@ -76,11 +370,49 @@ impl HttpBody for GrpcBody {
     }
 }
 
-#[derive(Clone)]
+/// Stops the motor it holds when dropped, e.g. when the RPC driving the motor is cancelled
+struct StopOnDrop<A: Actuator>(Option<A>);
+
+impl<A: Actuator> Drop for StopOnDrop<A> {
+    fn drop(&mut self) {
+        if let Some(mut actuator) = self.0.take() {
+            if let Err(e) = actuator.stop() {
+                error!("couldn't stop component {:?}", e);
+            }
+        }
+    }
+}
+
+/// Marks the operation of an RPC as finished when dropped
+struct FinishOperationOnDrop(Arc<Mutex<LocalRobot>>, String);
+
+impl Drop for FinishOperationOnDrop {
+    fn drop(&mut self) {
+        self.0.lock().unwrap().operations_mut().finish(&self.1);
+    }
+}
+
+/// An RPC handler, given the server processing the request and the protobuf encoded request.
+/// The response is written with `GrpcServer::encode_message` or `GrpcServer::set_response`.
+pub type GrpcHandler = Rc<dyn Fn(&mut GrpcServer, &[u8]) -> Result<(), GrpcError>>;
+
+type BuiltinHandler = fn(&mut GrpcServer, &[u8]) -> Result<(), GrpcError>;
+
+/// A clone of the server serves every connection, connections are spawned on the executor
+/// driving the accept loop and requests of different clients are interleaved at await points.
+/// Handlers never hold the robot lock across an await so they always observe a consistent
+/// LocalRobot.
 pub struct GrpcServer {
     response: GrpcBody,
     buffer: Rc<RefCell<BytesMut>>,
     robot: Arc<Mutex<LocalRobot>>,
+    handlers: Rc<HashMap<String, GrpcHandler>>,
+    max_response_size: usize,
+    auth: Option<Rc<Authenticator>>,
+    /// session of the request being processed
+    session: Option<String>,
+    /// id and cancellation token of the operation of the request being processed
+    operation: Option<(String, CancellationToken)>,
 }
 
 impl GrpcServer {
@@ -91,155 +423,727 @@ impl GrpcServer {
             response: body,
             buffer: Rc::new(RefCell::new(BytesMut::with_capacity(GRPC_BUFFER_SIZE))),
             robot,
+            handlers: Rc::new(Self::default_handlers()),
+            max_response_size: GRPC_MAX_RESPONSE_SIZE,
+            auth: None,
+            session: None,
+            operation: None,
         }
     }
 
-    fn validate_rpc(message: &Bytes) -> anyhow::Result<&[u8]> {
+    fn validate_rpc(message: &Bytes) -> Result<&[u8], GrpcError> {
         // Per https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md, we're expecting a
         // 5-byte header followed by the actual protocol buffer data. The 5 bytes in the header are
         // 1 null byte (indicating we're not using compression), and 4 bytes of a big-endian
         // integer describing the length of the rest of the data.
-        anyhow::ensure!(message.len() >= 5, "Message too short");
+        if message.len() < 5 {
+            return Err(GrpcError::RpcInvalidArgument(
+                "Message too short".to_string(),
+            ));
+        }
         let (header, rest) = message.split_at(5);
         let (use_compression, expected_len) = header.split_at(1);
-        anyhow::ensure!(use_compression[0] == 0, "Compression not supported");
+        if use_compression[0] != 0 {
+            return Err(GrpcError::RpcUnimplemented(
+                "Compression not supported".to_string(),
+            ));
+        }
         let expected_len = u32::from_be_bytes(expected_len.try_into().unwrap());
-        anyhow::ensure!(expected_len == rest.len() as u32, "Incorrect payload size");
+        if expected_len != rest.len() as u32 {
+            return Err(GrpcError::RpcInvalidArgument(
+                "Incorrect payload size".to_string(),
+            ));
+        }
         Ok(rest)
     }
 
-    fn handle_request(&mut self, path: &str, msg: Bytes) -> anyhow::Result<()> {
-        let payload = Self::validate_rpc(&msg)?;
-        match path {
-            "/viam.component.base.v1.BaseService/SetPower" => self.base_set_power(payload),
-            "/viam.component.base.v1.BaseService/Stop" => self.base_stop(payload),
-            "/viam.component.base.v1.BaseService/MoveStraight" => self.base_move_straight(payload),
-            "/viam.component.base.v1.BaseService/Spin" => self.base_spin(payload),
-            "/viam.component.base.v1.BaseService/SetVelocity" => self.base_set_velocity(payload),
-            "/viam.component.board.v1.BoardService/GetDigitalinterruptValue" => {
-                self.board_get_digital_interrupt_value(payload)
-            }
-            "/viam.component.board.v1.BoardService/GetGPIO" => self.board_get_pin(payload),
-            "/viam.component.board.v1.BoardService/PWM" => self.board_pwm(payload),
-            "/viam.component.board.v1.BoardService/PWMFrequency" => {
-                self.board_pwm_frequency(payload)
-            }
-            "/viam.component.board.v1.BoardService/ReadAnalogReader" => {
-                self.board_read_analog_reader(payload)
-            }
-            "/viam.component.board.v1.BoardService/SetGPIO" => self.board_set_pin(payload),
-            "/viam.component.board.v1.BoardService/SetPWM" => self.board_set_pwm(payload),
-            "/viam.component.board.v1.BoardService/SetPWMFrequency" => {
-                self.board_set_pwm_frequency(payload)
-            }
-            "/viam.component.board.v1.BoardService/Status" => self.board_status(payload),
+    /// Handlers of the services implemented by micro-rdk
+    fn default_handlers() -> HashMap<String, GrpcHandler> {
+        let builtins: &[(&str, BuiltinHandler)] = &[
+            (
+                "/viam.component.base.v1.BaseService/SetPower",
+                Self::base_set_power,
+            ),
+            ("/viam.component.base.v1.BaseService/Stop", Self::base_stop),
+            (
+                "/viam.component.base.v1.BaseService/DoCommand",
+                Self::base_do_command,
+            ),
+            (
+                "/viam.component.base.v1.BaseService/IsMoving",
+                Self::base_is_moving,
+            ),
+            (
+                "/viam.component.base.v1.BaseService/MoveStraight",
+                Self::base_move_straight,
+            ),
+            ("/viam.component.base.v1.BaseService/Spin", Self::base_spin),
+            (
+                "/viam.component.base.v1.BaseService/SetVelocity",
+                Self::base_set_velocity,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/DoCommand",
+                Self::board_do_command,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/GetDigitalInterruptValue",
+                Self::board_get_digital_interrupt_value,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/GetGPIO",
+                Self::board_get_pin,
+            ),
+            ("/viam.component.board.v1.BoardService/PWM", Self::board_pwm),
+            (
+                "/viam.component.board.v1.BoardService/PWMFrequency",
+                Self::board_pwm_frequency,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/ReadAnalogReader",
+                Self::board_read_analog_reader,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/SetGPIO",
+                Self::board_set_pin,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/SetPWM",
+                Self::board_set_pwm,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/SetPWMFrequency",
+                Self::board_set_pwm_frequency,
+            ),
+            (
+                "/viam.component.board.v1.BoardService/Status",
+                Self::board_status,
+            ),
             #[cfg(feature = "camera")]
-            "/viam.component.camera.v1.CameraService/GetImage" => self.camera_get_frame(payload),
+            (
+                "/viam.component.camera.v1.CameraService/DoCommand",
+                Self::camera_do_command,
+            ),
             #[cfg(feature = "camera")]
-            "/viam.component.camera.v1.CameraService/GetPointCloud" => {
-                self.camera_get_point_cloud(payload)
-            }
+            (
+                "/viam.component.camera.v1.CameraService/GetImage",
+                Self::camera_get_frame,
+            ),
             #[cfg(feature = "camera")]
-            "/viam.component.camera.v1.CameraService/GetProperties" => {
-                self.camera_get_properties(payload)
-            }
+            (
+                "/viam.component.camera.v1.CameraService/GetPointCloud",
+                Self::camera_get_point_cloud,
+            ),
             #[cfg(feature = "camera")]
-            "/viam.component.camera.v1.CameraService/RenderFrame" => {
-                self.camera_render_frame(payload)
-            }
-            "/viam.component.motor.v1.MotorService/GetPosition" => self.motor_get_position(payload),
-            "/viam.component.motor.v1.MotorService/GetProperties" => {
-                self.motor_get_properties(payload)
-            }
-            "/viam.component.motor.v1.MotorService/GoFor" => self.motor_go_for(payload),
-            "/viam.component.motor.v1.MotorService/GoTo" => self.motor_go_to(payload),
-            "/viam.component.motor.v1.MotorService/IsPowered" => self.motor_is_powered(payload),
-            "/viam.component.motor.v1.MotorService/ResetZeroPosition" => {
-                self.motor_reset_zero_position(payload)
+            (
+                "/viam.component.camera.v1.CameraService/GetProperties",
+                Self::camera_get_properties,
+            ),
+            #[cfg(feature = "camera")]
+            (
+                "/viam.component.camera.v1.CameraService/RenderFrame",
+                Self::camera_render_frame,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/DoCommand",
+                Self::gantry_do_command,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/GetLengths",
+                Self::gantry_get_lengths,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/GetPosition",
+                Self::gantry_get_position,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/IsMoving",
+                Self::gantry_is_moving,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/MoveToPosition",
+                Self::gantry_move_to_position,
+            ),
+            (
+                "/viam.component.gantry.v1.GantryService/Stop",
+                Self::gantry_stop,
+            ),
+            (
+                "/viam.component.generic.v1.GenericService/DoCommand",
+                Self::generic_do_command,
+            ),
+            (
+                "/viam.component.gripper.v1.GripperService/DoCommand",
+                Self::gripper_do_command,
+            ),
+            (
+                "/viam.component.gripper.v1.GripperService/Grab",
+                Self::gripper_grab,
+            ),
+            (
+                "/viam.component.gripper.v1.GripperService/IsMoving",
+                Self::gripper_is_moving,
+            ),
+            (
+                "/viam.component.gripper.v1.GripperService/Open",
+                Self::gripper_open,
+            ),
+            (
+                "/viam.component.gripper.v1.GripperService/Stop",
+                Self::gripper_stop,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/DoCommand",
+                Self::motor_do_command,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/GetPosition",
+                Self::motor_get_position,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/GetProperties",
+                Self::motor_get_properties,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/GoFor",
+                Self::motor_go_for,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/GoTo",
+                Self::motor_go_to,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/IsMoving",
+                Self::motor_is_moving,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/IsPowered",
+                Self::motor_is_powered,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/ResetZeroPosition",
+                Self::motor_reset_zero_position,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/SetPower",
+                Self::motor_set_power,
+            ),
+            (
+                "/viam.component.motor.v1.MotorService/Stop",
+                Self::motor_stop,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/DoCommand",
+                Self::movement_sensor_do_command,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetAccuracy",
+                Self::movement_sensor_get_accuracy,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetAngularVelocity",
+                Self::movement_sensor_get_angular_velocity,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetCompassHeading",
+                Self::movement_sensor_get_compass_heading,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetLinearAcceleration",
+                Self::movement_sensor_get_linear_acceleration,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetLinearVelocity",
+                Self::movement_sensor_get_linear_velocity,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetOrientation",
+                Self::movement_sensor_get_orientation,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetPosition",
+                Self::movement_sensor_get_position,
+            ),
+            (
+                "/viam.component.movementsensor.v1.MovementSensorService/GetProperties",
+                Self::movement_sensor_get_properties,
+            ),
+            (
+                "/viam.robot.v1.RobotService/ResourceNames",
+                Self::resource_names,
+            ),
+            (
+                "/viam.robot.v1.RobotService/ResourceRPCSubtypes",
+                Self::resource_rpc_subtypes,
+            ),
+            ("/viam.robot.v1.RobotService/GetStatus", Self::robot_status),
+            (
+                "/viam.robot.v1.RobotService/DiscoverComponents",
+                Self::robot_discover_components,
+            ),
+            (
+                "/viam.robot.v1.RobotService/FrameSystemConfig",
+                Self::robot_frame_system_config,
+            ),
+            (
+                "/viam.robot.v1.RobotService/TransformPose",
+                Self::robot_transform_pose,
+            ),
+            ("/viam.robot.v1.RobotService/StopAll", Self::robot_stop_all),
+            (
+                "/viam.robot.v1.RobotService/StreamStatus",
+                Self::robot_stream_status,
+            ),
+            (
+                "/viam.robot.v1.RobotService/GetOperations",
+                Self::robot_get_operations,
+            ),
+            (
+                "/viam.robot.v1.RobotService/CancelOperation",
+                Self::robot_cancel_operation,
+            ),
+            (
+                "/viam.robot.v1.RobotService/BlockForOperation",
+                Self::robot_block_for_operation,
+            ),
+            (
+                "/viam.robot.v1.RobotService/StartSession",
+                Self::robot_start_session,
+            ),
+            (
+                "/viam.robot.v1.RobotService/SendSessionHeartbeat",
+                Self::robot_send_session_heartbeat,
+            ),
+            (
+                "/viam.robot.v1.RobotService/GetSessions",
+                Self::robot_get_sessions,
+            ),
+            (
+                "/proto.rpc.v1.AuthService/Authenticate",
+                Self::auth_service_authentificate,
+            ),
+            (
+                "/viam.component.sensor.v1.SensorService/GetReadings",
+                Self::sensor_get_readings,
+            ),
+            (
+                "/viam.component.sensor.v1.SensorService/DoCommand",
+                Self::sensor_do_command,
+            ),
+            (
+                "/viam.component.servo.v1.ServoService/DoCommand",
+                Self::servo_do_command,
+            ),
+            (
+                "/viam.component.servo.v1.ServoService/GetPosition",
+                Self::servo_get_position,
+            ),
+            (
+                "/viam.component.servo.v1.ServoService/IsMoving",
+                Self::servo_is_moving,
+            ),
+            (
+                "/viam.component.servo.v1.ServoService/Move",
+                Self::servo_move,
+            ),
+            (
+                "/viam.component.servo.v1.ServoService/Stop",
+                Self::servo_stop,
+            ),
+        ];
+        builtins
+            .iter()
+            .map(|(path, handler)| (path.to_string(), Rc::new(*handler) as GrpcHandler))
+            .collect()
+    }
+
+    /// Register `handler` to serve the RPC at the fully-qualified method `path`
+    /// (e.g. "/my.package.v1.CalibrationService/Calibrate"), replacing any existing handler.
+    pub fn register_handler(&mut self, path: &str, handler: GrpcHandler) {
+        Rc::make_mut(&mut self.handlers).insert(path.to_string(), handler);
+    }
+
+    pub fn robot(&self) -> Arc<Mutex<LocalRobot>> {
+        self.robot.clone()
+    }
+
+    /// Replace the response with `body`, used by handlers of server streaming RPCs
+    pub fn set_response(&mut self, body: GrpcBody) {
+        self.response = body;
+    }
+
+    /// Require every RPC but the ones of the AuthService to carry a bearer token issued by
+    /// `auth`, without an authenticator every RPC is allowed
+    pub fn set_authenticator(&mut self, auth: Authenticator) {
+        self.auth = Some(Rc::new(auth))
+    }
+
+    fn authorize(&self, path: &str, headers: &HeaderMap) -> Result<(), GrpcError> {
+        match self.auth.as_ref() {
+            Some(auth) if !path.starts_with(AUTH_SERVICE_PREFIX) => {
+                auth.verify(headers.get("authorization"))
             }
-            "/viam.component.motor.v1.MotorService/SetPower" => self.motor_set_power(payload),
-            "/viam.component.motor.v1.MotorService/Stop" => self.motor_stop(payload),
-            "/viam.robot.v1.RobotService/ResourceNames" => self.resource_names(payload),
-            "/viam.robot.v1.RobotService/GetStatus" => self.robot_status(payload),
-            "/proto.rpc.v1.AuthService/Authenticate" => self.auth_service_authentificate(payload),
-            "/viam.component.sensor.v1.SensorService/GetReadings" => {
-                self.sensor_get_readings(payload)
+            _ => Ok(()),
+        }
+    }
+
+    /// Any RPC sent as part of a session counts as a heartbeat of that session
+    fn request_session(&self, headers: &HeaderMap) -> Result<Option<String>, GrpcError> {
+        let id = match headers.get(SESSION_METADATA_KEY) {
+            Some(id) => id.to_str().map_err(|_| {
+                GrpcError::RpcInvalidArgument(format!("invalid {}", SESSION_METADATA_KEY))
+            })?,
+            None => return Ok(None),
+        };
+        self.robot.lock().unwrap().sessions_mut().heartbeat(id)?;
+        Ok(Some(id.to_owned()))
+    }
+
+    /// Record that the component `name` of type `subtype` was actuated by the session of the
+    /// request, so it gets stopped if the session expires
+    fn session_actuated(&self, subtype: &str, name: &str) {
+        if let Some(id) = self.session.as_ref() {
+            let name = ResourceName {
+                namespace: "rdk".to_string(),
+                r#type: "component".to_string(),
+                subtype: subtype.to_string(),
+                name: name.to_string(),
+            };
+            self.robot
+                .lock()
+                .unwrap()
+                .sessions_mut()
+                .associate(id, name);
+        }
+    }
+
+    fn handle_request(
+        &mut self,
+        path: &str,
+        headers: &HeaderMap,
+        msg: Bytes,
+    ) -> Result<(), GrpcError> {
+        self.authorize(path, headers)?;
+        self.session = self.request_session(headers)?;
+        let payload = Self::validate_rpc(&msg)?;
+        let handler = match self.handlers.get(path) {
+            Some(handler) => handler.clone(),
+            None => {
+                return Err(GrpcError::RpcUnimplemented(format!(
+                    "unimplemented method {}",
+                    path
+                )))
             }
-            _ => anyhow::bail!("unimplemented method"),
+        };
+        let opid = match headers.get(OPID_METADATA_KEY) {
+            Some(id) => Some(id.to_str().map_err(|_| {
+                GrpcError::RpcInvalidArgument(format!("invalid {}", OPID_METADATA_KEY))
+            })?),
+            None => None,
+        };
+        let (id, token) =
+            self.robot
+                .lock()
+                .unwrap()
+                .operations_mut()
+                .create(opid, path, self.session.clone())?;
+        let guard = FinishOperationOnDrop(self.robot.clone(), id.clone());
+        self.operation = Some((id, token));
+        handler(self, payload)?;
+        self.response.hold_while_streaming(guard);
+        Ok(())
+    }
+
+    fn serve_reflection(&mut self, headers: &HeaderMap, body: Body) {
+        if let Err(e) = self.authorize(REFLECTION_METHOD, headers) {
+            self.response.set_error(&e);
+            return;
         }
+        let mut services: Vec<String> = self
+            .handlers
+            .keys()
+            .filter_map(|path| path.trim_start_matches('/').split('/').next())
+            .map(str::to_string)
+            .collect();
+        services.push(REFLECTION_SERVICE.to_string());
+        services.sort();
+        services.dedup();
+        let reflection = Reflection::new(services);
+        self.set_response(GrpcBody::new_streaming(move |sender| {
+            reflection::serve(body, reflection, sender)
+        }));
     }
 
-    fn process_request(&mut self, path: &str, msg: Bytes) {
-        if self.handle_request(path, msg).is_err() {
-            self.response
-                .trailers
-                .as_mut()
+    /// Record the arguments of the operation of the request, to be returned by GetOperations
+    fn set_operation_arguments(&self, arguments: prost_types::Struct) {
+        if let Some((id, _)) = self.operation.as_ref() {
+            self.robot
+                .lock()
                 .unwrap()
-                .insert("grpc-message", "unimplemented".parse().unwrap());
-            self.response
-                .trailers
-                .as_mut()
+                .operations_mut()
+                .set_arguments(id, arguments);
+        }
+    }
+
+    /// Supersede the other operations actuating the component `name` of type `subtype`
+    fn take_over_component(&self, subtype: &str, name: &str) {
+        if let Some((id, _)) = self.operation.as_ref() {
+            self.robot
+                .lock()
                 .unwrap()
-                .insert("grpc-status", "12".parse().unwrap());
+                .operations_mut()
+                .cancel_others_with_label(id, &format!("{}-{}", subtype, name));
+        }
+    }
+
+    /// Token cancelled when the operation of the request is
+    fn operation_token(&self) -> CancellationToken {
+        match self.operation.as_ref() {
+            Some((_, token)) => token.clone(),
+            None => CancellationToken::new(),
         }
     }
 
-    fn motor_get_position(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_get_position")
+    fn process_request(&mut self, path: &str, headers: &HeaderMap, msg: Bytes) {
+        if let Err(e) = self.handle_request(path, headers, msg) {
+            debug!("{} failed with {:?}", path, e);
+            self.response.set_error(&e);
+        }
     }
 
-    fn motor_get_properties(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_get_properties")
+    fn motor_get_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::GetPositionRequest::decode(message)?;
+        let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let position = motor.lock().unwrap().get_position()?;
+        let resp = component::motor::v1::GetPositionResponse { position };
+        self.encode_message(resp)
     }
 
-    fn motor_go_for(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_go_for")
+    fn motor_get_properties(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::GetPropertiesRequest::decode(message)?;
+        let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let props = motor.lock().unwrap().get_properties();
+        let resp = component::motor::v1::GetPropertiesResponse {
+            position_reporting: props.position_reporting,
+        };
+        self.encode_message(resp)
     }
 
-    fn motor_go_to(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_go_to")
+    fn motor_go_for(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::GoForRequest::decode(message)?;
+        let motor = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_motor_by_name(req.name.clone())
+        {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("motor", &req.name);
+        self.take_over_component("motor", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("rpm", number_value(req.rpm)),
+            ("revolutions", number_value(req.revolutions)),
+        ]));
+        let dur = motor.lock().unwrap().go_for(req.rpm, req.revolutions)?;
+        let resp = component::motor::v1::GoForResponse {};
+        // zero revolutions spins the motor until stopped
+        if dur.is_none() && req.revolutions == 0.0 {
+            return self.encode_message(resp);
+        }
+        self.stop_after(motor, dur, resp);
+        Ok(())
     }
 
-    fn motor_is_powered(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_is_powered")
+    fn motor_go_to(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::GoToRequest::decode(message)?;
+        let motor = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_motor_by_name(req.name.clone())
+        {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("motor", &req.name);
+        self.take_over_component("motor", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("rpm", number_value(req.rpm)),
+            (
+                "position_revolutions",
+                number_value(req.position_revolutions),
+            ),
+        ]));
+        let dur = motor
+            .lock()
+            .unwrap()
+            .go_to(req.rpm, req.position_revolutions)?;
+        let resp = component::motor::v1::GoToResponse {};
+        self.stop_after(motor, dur, resp);
+        Ok(())
     }
 
-    fn motor_reset_zero_position(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_reset_zero_position")
+    /// Respond with `resp` once `actuator` ran for `dur`, or once it stopped moving by itself
+    /// when there is no duration, the actuator is then stopped. The actuator is also stopped if
+    /// the client or CancelOperation cancels the RPC before that, unless a newer command took
+    /// over the component.
+    fn stop_after<A, M>(&mut self, actuator: A, dur: Option<Duration>, resp: M)
+    where
+        A: Actuator + Clone + 'static,
+        M: Message + 'static,
+    {
+        self.stop_after_then(actuator, dur, move |_| Ok(resp))
+    }
+
+    /// Same as `stop_after`, the response is made by `respond` once the actuator stopped
+    fn stop_after_then<A, M, F>(&mut self, actuator: A, dur: Option<Duration>, respond: F)
+    where
+        A: Actuator + Clone + 'static,
+        M: Message + 'static,
+        F: FnOnce(&A) -> Result<M, GrpcError> + 'static,
+    {
+        let token = self.operation_token();
+        self.set_response(GrpcBody::new_streaming(move |sender| async move {
+            let mut guard = StopOnDrop(Some(actuator.clone()));
+            let done = future::or(
+                async {
+                    match dur {
+                        Some(dur) => {
+                            smol::Timer::after(dur).await;
+                        }
+                        None => {
+                            let mut polled = actuator.clone();
+                            while polled.poll_moving()? {
+                                smol::Timer::after(MOVING_POLL_PERIOD).await;
+                            }
+                        }
+                    }
+                    Ok::<bool, GrpcError>(true)
+                },
+                async {
+                    token.cancelled().await;
+                    Ok(false)
+                },
+            )
+            .await?;
+            if !done {
+                if token.is_superseded() {
+                    guard.0 = None;
+                }
+                return Err(GrpcError::RpcCancelled);
+            }
+            if let Some(mut stopped) = guard.0.take() {
+                stopped.stop()?;
+            }
+            sender.send_message(respond(&actuator)?).await
+        }));
     }
 
-    fn auth_service_authentificate(&mut self, message: &[u8]) -> anyhow::Result<()> {
-        let _req = proto::rpc::v1::AuthenticateRequest::decode(message)?;
-        let resp = proto::rpc::v1::AuthenticateResponse {
-            access_token: "esp32".to_string(),
+    fn motor_is_moving(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::IsMovingRequest::decode(message)?;
+        let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
+        let is_moving = motor.lock().unwrap().is_moving()?;
+        let resp = component::motor::v1::IsMovingResponse { is_moving };
         self.encode_message(resp)
     }
 
-    fn motor_set_power(&mut self, message: &[u8]) -> anyhow::Result<()> {
-        let req = component::motor::v1::SetPowerRequest::decode(message)?;
+    fn motor_is_powered(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::IsPoweredRequest::decode(message)?;
+        let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let (is_on, power_pct) = motor.lock().unwrap().is_powered()?;
+        let resp = component::motor::v1::IsPoweredResponse { is_on, power_pct };
+        self.encode_message(resp)
+    }
+
+    fn motor_reset_zero_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::ResetZeroPositionRequest::decode(message)?;
         let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
             Some(m) => m,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        motor.lock().unwrap().reset_zero_position(req.offset)?;
+        let resp = component::motor::v1::ResetZeroPositionResponse {};
+        self.encode_message(resp)
+    }
+
+    fn auth_service_authentificate(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = proto::rpc::v1::AuthenticateRequest::decode(message)?;
+        let access_token = match self.auth.as_ref() {
+            Some(auth) => {
+                let creds = req.credentials.ok_or_else(|| {
+                    GrpcError::RpcInvalidArgument("missing credentials".to_string())
+                })?;
+                auth.authenticate(&req.entity, &creds)?
+            }
+            // every token is accepted when authentication is disabled
+            None => "esp32".to_string(),
+        };
+        let resp = proto::rpc::v1::AuthenticateResponse { access_token };
+        self.encode_message(resp)
+    }
+
+    fn motor_set_power(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::SetPowerRequest::decode(message)?;
+        let motor = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_motor_by_name(req.name.clone())
+        {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
+        self.session_actuated("motor", &req.name);
+        self.take_over_component("motor", &req.name);
         motor.lock().unwrap().set_power(req.power_pct)?;
         let resp = component::motor::v1::SetPowerResponse {};
         self.encode_message(resp)
     }
 
-    fn motor_stop(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: motor_stop")
+    fn motor_stop(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::motor::v1::StopRequest::decode(message)?;
+        let motor = match self.robot.lock().unwrap().get_motor_by_name(req.name) {
+            Some(m) => m,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        motor.lock().unwrap().stop_with_extra(req.extra.as_ref())?;
+        let resp = component::motor::v1::StopResponse {};
+        self.encode_message(resp)
     }
 
-    fn board_get_digital_interrupt_value(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: board_get_digital_interrupt_value")
+    fn board_get_digital_interrupt_value(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::board::v1::GetDigitalInterruptValueRequest::decode(message)?;
+        let board = match self.robot.lock().unwrap().get_board_by_name(req.board_name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let interrupt = board.get_digital_interrupt_by_name(req.digital_interrupt_name)?;
+        let value = interrupt.borrow().value()?;
+        let resp = component::board::v1::GetDigitalInterruptValueResponse { value };
+        self.encode_message(resp)
     }
 
-    fn board_status(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn board_status(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::board::v1::StatusRequest::decode(message)?;
         let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
         let status = board.lock().unwrap().get_board_status()?;
         let status = component::board::v1::StatusResponse {
@@ -248,19 +1152,35 @@ impl GrpcServer {
         self.encode_message(status)
     }
 
-    fn board_pwm(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: board_pwm")
+    fn board_pwm(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::board::v1::PwmRequest::decode(message)?;
+        let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
+        let duty_cycle_pct = board.lock().unwrap().get_pwm_duty(pin)?;
+        let resp = component::board::v1::PwmResponse { duty_cycle_pct };
+        self.encode_message(resp)
     }
 
-    fn board_pwm_frequency(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: board_pwm_frequency")
+    fn board_pwm_frequency(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::board::v1::PwmFrequencyRequest::decode(message)?;
+        let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
+        let frequency_hz = board.lock().unwrap().get_pwm_frequency(pin)?;
+        let resp = component::board::v1::PwmFrequencyResponse { frequency_hz };
+        self.encode_message(resp)
     }
 
-    fn board_read_analog_reader(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn board_read_analog_reader(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::board::v1::ReadAnalogReaderRequest::decode(message)?;
         let board = match self.robot.lock().unwrap().get_board_by_name(req.board_name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
         let reader = board.get_analog_reader_by_name(req.analog_reader_name)?;
         let resp = component::board::v1::ReadAnalogReaderResponse {
@@ -269,46 +1189,393 @@ impl GrpcServer {
         self.encode_message(resp)
     }
 
-    fn board_set_pin(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn board_set_pin(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::board::v1::SetGpioRequest::decode(message)?;
         let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
 
-        let pin: i32 = req.pin.parse::<i32>().unwrap();
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
         let is_high = req.high;
         board.lock().unwrap().set_gpio_pin_level(pin, is_high)?;
         let resp = component::board::v1::SetGpioResponse {};
         self.encode_message(resp)
     }
 
-    fn board_set_pwm(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: board_set_pwm")
+    fn board_set_pwm(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::board::v1::SetPwmRequest::decode(message)?;
+        let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
+        if !(0.0..=1.0).contains(&req.duty_cycle_pct) {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "duty cycle {} is not between 0 and 1",
+                req.duty_cycle_pct
+            )));
+        }
+        board
+            .lock()
+            .unwrap()
+            .set_pwm_duty(pin, req.duty_cycle_pct)?;
+        let resp = component::board::v1::SetPwmResponse {};
+        self.encode_message(resp)
     }
 
-    fn board_set_pwm_frequency(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: board_set_pwm_frequency")
+    fn board_set_pwm_frequency(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::board::v1::SetPwmFrequencyRequest::decode(message)?;
+        let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
+        if req.frequency_hz == 0 {
+            return Err(GrpcError::RpcInvalidArgument(
+                "frequency must be greater than 0".to_string(),
+            ));
+        }
+        board
+            .lock()
+            .unwrap()
+            .set_pwm_frequency(pin, req.frequency_hz)?;
+        let resp = component::board::v1::SetPwmFrequencyResponse {};
+        self.encode_message(resp)
     }
 
-    fn board_get_pin(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn board_get_pin(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::board::v1::GetGpioRequest::decode(message)?;
         let board = match self.robot.lock().unwrap().get_board_by_name(req.name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
 
-        let pin: i32 = req.pin.parse::<i32>().unwrap();
+        let pin = board.lock().unwrap().resolve_pin(&req.pin)?;
         let level = board.lock().unwrap().get_gpio_level(pin)?;
         let resp = component::board::v1::GetGpioResponse { high: level };
         self.encode_message(resp)
     }
 
-    fn sensor_get_readings(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    /// Movement sensor `name`, failing with a not supported error unless its properties list
+    /// `reading` as `supported`
+    fn movement_sensor_supporting(
+        &self,
+        name: String,
+        reading: &str,
+        supported: fn(&MovementSensorSupportedProperties) -> bool,
+    ) -> Result<Arc<Mutex<dyn MovementSensor>>, GrpcError> {
+        let sensor = match self.robot.lock().unwrap().get_movement_sensor_by_name(name) {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        if !supported(&sensor.lock().unwrap().get_properties()) {
+            return Err(reading_not_supported(reading));
+        }
+        Ok(sensor)
+    }
+
+    fn movement_sensor_get_properties(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetPropertiesRequest::decode(message)?;
+        let sensor = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_movement_sensor_by_name(req.name)
+        {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let props = sensor.lock().unwrap().get_properties();
+        let resp: component::movementsensor::v1::GetPropertiesResponse = props.into();
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetPositionRequest::decode(message)?;
+        let sensor =
+            self.movement_sensor_supporting(req.name, "position", |p| p.position_supported)?;
+        let (coordinate, altitude_mm) = sensor.lock().unwrap().get_position()?;
+        let resp = component::movementsensor::v1::GetPositionResponse {
+            coordinate: Some(coordinate),
+            altitude_mm,
+        };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_linear_velocity(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetLinearVelocityRequest::decode(message)?;
+        let sensor = self.movement_sensor_supporting(req.name, "linear velocity", |p| {
+            p.linear_velocity_supported
+        })?;
+        let linear_velocity = sensor.lock().unwrap().get_linear_velocity()?;
+        let resp = component::movementsensor::v1::GetLinearVelocityResponse {
+            linear_velocity: Some(linear_velocity),
+        };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_angular_velocity(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetAngularVelocityRequest::decode(message)?;
+        let sensor = self.movement_sensor_supporting(req.name, "angular velocity", |p| {
+            p.angular_velocity_supported
+        })?;
+        let angular_velocity = sensor.lock().unwrap().get_angular_velocity()?;
+        let resp = component::movementsensor::v1::GetAngularVelocityResponse {
+            angular_velocity: Some(angular_velocity),
+        };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_linear_acceleration(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetLinearAccelerationRequest::decode(message)?;
+        let sensor = self.movement_sensor_supporting(req.name, "linear acceleration", |p| {
+            p.linear_acceleration_supported
+        })?;
+        let linear_acceleration = sensor.lock().unwrap().get_linear_acceleration()?;
+        let resp = component::movementsensor::v1::GetLinearAccelerationResponse {
+            linear_acceleration: Some(linear_acceleration),
+        };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_compass_heading(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetCompassHeadingRequest::decode(message)?;
+        let sensor = self.movement_sensor_supporting(req.name, "compass heading", |p| {
+            p.compass_heading_supported
+        })?;
+        let value = sensor.lock().unwrap().get_compass_heading()?;
+        let resp = component::movementsensor::v1::GetCompassHeadingResponse { value };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_orientation(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetOrientationRequest::decode(message)?;
+        let sensor =
+            self.movement_sensor_supporting(req.name, "orientation", |p| p.orientation_supported)?;
+        let orientation = sensor.lock().unwrap().get_orientation()?;
+        let resp = component::movementsensor::v1::GetOrientationResponse {
+            orientation: Some(orientation),
+        };
+        self.encode_message(resp)
+    }
+
+    fn movement_sensor_get_accuracy(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::movementsensor::v1::GetAccuracyRequest::decode(message)?;
+        let sensor = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_movement_sensor_by_name(req.name)
+        {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let accuracy_mm = sensor.lock().unwrap().get_accuracy()?;
+        let resp = component::movementsensor::v1::GetAccuracyResponse { accuracy_mm };
+        self.encode_message(resp)
+    }
+
+    fn servo_move(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::servo::v1::MoveRequest::decode(message)?;
+        let servo = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_servo_by_name(req.name.clone())
+        {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("servo", &req.name);
+        self.take_over_component("servo", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("angle_deg", number_value(req.angle_deg as f64)),
+        ]));
+        servo.lock().unwrap().move_to(req.angle_deg)?;
+        let resp = component::servo::v1::MoveResponse {};
+        self.encode_message(resp)
+    }
+
+    fn servo_get_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::servo::v1::GetPositionRequest::decode(message)?;
+        let servo = match self.robot.lock().unwrap().get_servo_by_name(req.name) {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let position_deg = servo.lock().unwrap().get_position()?;
+        let resp = component::servo::v1::GetPositionResponse { position_deg };
+        self.encode_message(resp)
+    }
+
+    fn servo_is_moving(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::servo::v1::IsMovingRequest::decode(message)?;
+        let servo = match self.robot.lock().unwrap().get_servo_by_name(req.name) {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let is_moving = servo.lock().unwrap().is_moving()?;
+        let resp = component::servo::v1::IsMovingResponse { is_moving };
+        self.encode_message(resp)
+    }
+
+    fn servo_stop(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::servo::v1::StopRequest::decode(message)?;
+        let servo = match self.robot.lock().unwrap().get_servo_by_name(req.name) {
+            Some(s) => s,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        servo.lock().unwrap().stop_with_extra(req.extra.as_ref())?;
+        let resp = component::servo::v1::StopResponse {};
+        self.encode_message(resp)
+    }
+
+    fn gantry_get_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gantry::v1::GetPositionRequest::decode(message)?;
+        let gantry = match self.robot.lock().unwrap().get_gantry_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let positions_mm = gantry.lock().unwrap().get_position()?;
+        let resp = component::gantry::v1::GetPositionResponse { positions_mm };
+        self.encode_message(resp)
+    }
+
+    fn gantry_move_to_position(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gantry::v1::MoveToPositionRequest::decode(message)?;
+        let gantry = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_gantry_by_name(req.name.clone())
+        {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("gantry", &req.name);
+        self.take_over_component("gantry", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("positions_mm", numbers_value(&req.positions_mm)),
+        ]));
+        let dur = gantry.lock().unwrap().move_to_position(&req.positions_mm)?;
+        self.stop_after(
+            gantry,
+            dur,
+            component::gantry::v1::MoveToPositionResponse {},
+        );
+        Ok(())
+    }
+
+    fn gantry_get_lengths(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gantry::v1::GetLengthsRequest::decode(message)?;
+        let gantry = match self.robot.lock().unwrap().get_gantry_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let lengths_mm = gantry.lock().unwrap().get_lengths()?;
+        let resp = component::gantry::v1::GetLengthsResponse { lengths_mm };
+        self.encode_message(resp)
+    }
+
+    fn gantry_is_moving(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gantry::v1::IsMovingRequest::decode(message)?;
+        let gantry = match self.robot.lock().unwrap().get_gantry_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let is_moving = gantry.lock().unwrap().is_moving()?;
+        let resp = component::gantry::v1::IsMovingResponse { is_moving };
+        self.encode_message(resp)
+    }
+
+    fn gantry_stop(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gantry::v1::StopRequest::decode(message)?;
+        let gantry = match self.robot.lock().unwrap().get_gantry_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        gantry.lock().unwrap().stop_with_extra(req.extra.as_ref())?;
+        let resp = component::gantry::v1::StopResponse {};
+        self.encode_message(resp)
+    }
+
+    fn gripper_open(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gripper::v1::OpenRequest::decode(message)?;
+        let gripper = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_gripper_by_name(req.name.clone())
+        {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("gripper", &req.name);
+        self.take_over_component("gripper", &req.name);
+        self.set_operation_arguments(motion_arguments(&[("name", string_value(&req.name))]));
+        let dur = gripper.lock().unwrap().open()?;
+        self.stop_after(gripper, dur, component::gripper::v1::OpenResponse {});
+        Ok(())
+    }
+
+    /// Succeeds once the gripper closed, on something or not
+    fn gripper_grab(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gripper::v1::GrabRequest::decode(message)?;
+        let gripper = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_gripper_by_name(req.name.clone())
+        {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("gripper", &req.name);
+        self.take_over_component("gripper", &req.name);
+        self.set_operation_arguments(motion_arguments(&[("name", string_value(&req.name))]));
+        let dur = gripper.lock().unwrap().grab()?;
+        self.stop_after_then(gripper, dur, |gripper| {
+            let success = gripper.lock().unwrap().is_holding()?;
+            Ok(component::gripper::v1::GrabResponse {
+                success,
+                extra: None,
+            })
+        });
+        Ok(())
+    }
+
+    fn gripper_is_moving(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gripper::v1::IsMovingRequest::decode(message)?;
+        let gripper = match self.robot.lock().unwrap().get_gripper_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let is_moving = gripper.lock().unwrap().is_moving()?;
+        let resp = component::gripper::v1::IsMovingResponse { is_moving };
+        self.encode_message(resp)
+    }
+
+    fn gripper_stop(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::gripper::v1::StopRequest::decode(message)?;
+        let gripper = match self.robot.lock().unwrap().get_gripper_by_name(req.name) {
+            Some(g) => g,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        gripper
+            .lock()
+            .unwrap()
+            .stop_with_extra(req.extra.as_ref())?;
+        let resp = component::gripper::v1::StopResponse {};
+        self.encode_message(resp)
+    }
+
+    fn sensor_get_readings(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::sensor::v1::GetReadingsRequest::decode(message)?;
         let sensor = match self.robot.lock().unwrap().get_sensor_by_name(req.name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
 
         let readings = sensor.lock().unwrap().get_generic_readings()?;
@@ -316,24 +1583,144 @@ impl GrpcServer {
         self.encode_message(resp)
     }
 
-    fn base_move_straight(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: base_move_straight")
+    /// DoCommand is served by every component API with the messages of the generic API
+    fn do_command(&mut self, subtype: &str, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::generic::v1::DoCommandRequest::decode(message)?;
+        let result = self.robot.lock().unwrap().do_command(
+            subtype,
+            req.name,
+            req.command.unwrap_or_default(),
+        )?;
+        let resp = component::generic::v1::DoCommandResponse {
+            result: Some(result),
+        };
+        self.encode_message(resp)
+    }
+
+    fn base_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("base", message)
+    }
+
+    fn board_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("board", message)
+    }
+
+    #[cfg(feature = "camera")]
+    fn camera_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("camera", message)
+    }
+
+    fn gantry_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("gantry", message)
+    }
+
+    fn generic_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("generic", message)
+    }
+
+    fn gripper_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("gripper", message)
+    }
+
+    fn motor_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("motor", message)
     }
 
-    fn base_spin(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: base_spin")
+    fn sensor_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("sensor", message)
     }
 
-    fn base_set_velocity(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: base_set_velocity")
+    fn movement_sensor_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("movement_sensor", message)
+    }
+
+    fn servo_do_command(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        self.do_command("servo", message)
+    }
+
+    fn base_move_straight(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::base::v1::MoveStraightRequest::decode(message)?;
+        let base = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_base_by_name(req.name.clone())
+        {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("base", &req.name);
+        self.take_over_component("base", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("distance_mm", number_value(req.distance_mm as f64)),
+            ("mm_per_sec", number_value(req.mm_per_sec)),
+        ]));
+        let dur = base
+            .lock()
+            .unwrap()
+            .move_straight(req.distance_mm, req.mm_per_sec)?;
+        self.stop_after(base, dur, component::base::v1::MoveStraightResponse {});
+        Ok(())
+    }
+
+    fn base_spin(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::base::v1::SpinRequest::decode(message)?;
+        let base = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_base_by_name(req.name.clone())
+        {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("base", &req.name);
+        self.take_over_component("base", &req.name);
+        self.set_operation_arguments(motion_arguments(&[
+            ("name", string_value(&req.name)),
+            ("angle_deg", number_value(req.angle_deg)),
+            ("degs_per_sec", number_value(req.degs_per_sec)),
+        ]));
+        let dur = base.lock().unwrap().spin(req.angle_deg, req.degs_per_sec)?;
+        self.stop_after(base, dur, component::base::v1::SpinResponse {});
+        Ok(())
+    }
+
+    fn base_set_velocity(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::base::v1::SetVelocityRequest::decode(message)?;
+        let base = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_base_by_name(req.name.clone())
+        {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        self.session_actuated("base", &req.name);
+        self.take_over_component("base", &req.name);
+        base.lock().unwrap().set_velocity(
+            &req.linear.unwrap_or_default(),
+            &req.angular.unwrap_or_default(),
+        )?;
+        let resp = component::base::v1::SetVelocityResponse {};
+        self.encode_message(resp)
     }
 
-    fn base_set_power(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn base_set_power(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::base::v1::SetPowerRequest::decode(message)?;
-        let base = match self.robot.lock().unwrap().get_base_by_name(req.name) {
+        let base = match self
+            .robot
+            .lock()
+            .unwrap()
+            .get_base_by_name(req.name.clone())
+        {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
+        self.session_actuated("base", &req.name);
+        self.take_over_component("base", &req.name);
         base.lock().unwrap().set_power(
             &req.linear.unwrap_or_default(),
             &req.angular.unwrap_or_default(),
@@ -342,19 +1729,82 @@ impl GrpcServer {
         self.encode_message(resp)
     }
 
-    fn base_stop(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn base_stop(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::base::v1::StopRequest::decode(message)?;
         let base = match self.robot.lock().unwrap().get_base_by_name(req.name) {
             Some(b) => b,
-            None => return Err(anyhow::anyhow!("resource not found")),
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
         };
 
-        base.lock().unwrap().stop()?;
+        base.lock().unwrap().stop_with_extra(req.extra.as_ref())?;
         let resp = component::base::v1::StopResponse {};
         self.encode_message(resp)
     }
 
-    fn robot_status(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn base_is_moving(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = component::base::v1::IsMovingRequest::decode(message)?;
+        let base = match self.robot.lock().unwrap().get_base_by_name(req.name) {
+            Some(b) => b,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let is_moving = base.lock().unwrap().is_moving()?;
+        let resp = component::base::v1::IsMovingResponse { is_moving };
+        self.encode_message(resp)
+    }
+
+    fn robot_stop_all(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::StopAllRequest::decode(message)?;
+        self.robot.lock().unwrap().stop_all(&req.extra)?;
+        let resp = robot::v1::StopAllResponse {};
+        self.encode_message(resp)
+    }
+
+    fn resource_rpc_subtypes(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let _req = robot::v1::ResourceRpcSubtypesRequest::decode(message)?;
+        let resource_rpc_subtypes = self.robot.lock().unwrap().get_rpc_subtypes();
+        let resp = robot::v1::ResourceRpcSubtypesResponse {
+            resource_rpc_subtypes,
+        };
+        self.encode_message(resp)
+    }
+
+    fn robot_discover_components(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::DiscoverComponentsRequest::decode(message)?;
+        let discovery = self.robot.lock().unwrap().discover_components(&req.queries);
+        let resp = robot::v1::DiscoverComponentsResponse { discovery };
+        self.encode_message(resp)
+    }
+
+    fn robot_frame_system_config(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::FrameSystemConfigRequest::decode(message)?;
+        let frame_system_configs = self
+            .robot
+            .lock()
+            .unwrap()
+            .frame_system(&req.supplemental_transforms)?
+            .configs();
+        let resp = robot::v1::FrameSystemConfigResponse {
+            frame_system_configs,
+        };
+        self.encode_message(resp)
+    }
+
+    fn robot_transform_pose(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::TransformPoseRequest::decode(message)?;
+        let source = req
+            .source
+            .ok_or_else(|| GrpcError::RpcInvalidArgument("missing source pose".to_string()))?;
+        let pose = self
+            .robot
+            .lock()
+            .unwrap()
+            .frame_system(&req.supplemental_transforms)?
+            .transform_pose(&source, &req.destination)?;
+        let resp = robot::v1::TransformPoseResponse { pose: Some(pose) };
+        self.encode_message(resp)
+    }
+
+    fn robot_status(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = robot::v1::GetStatusRequest::decode(message)?;
         let status = robot::v1::GetStatusResponse {
             status: self.robot.lock().unwrap().get_status(req)?,
@@ -362,66 +1812,199 @@ impl GrpcServer {
         self.encode_message(status)
     }
 
+    fn robot_get_operations(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let _req = robot::v1::GetOperationsRequest::decode(message)?;
+        let operations = self.robot.lock().unwrap().operations().operations();
+        let resp = robot::v1::GetOperationsResponse { operations };
+        self.encode_message(resp)
+    }
+
+    fn robot_cancel_operation(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::CancelOperationRequest::decode(message)?;
+        self.robot
+            .lock()
+            .unwrap()
+            .operations_mut()
+            .cancel(&req.id)?;
+        let resp = robot::v1::CancelOperationResponse {};
+        self.encode_message(resp)
+    }
+
+    fn robot_block_for_operation(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::BlockForOperationRequest::decode(message)?;
+        let resp = robot::v1::BlockForOperationResponse {};
+        let done = self.robot.lock().unwrap().operations().wait_for(&req.id);
+        let done = match done {
+            Some(done) => done,
+            None => return self.encode_message(resp),
+        };
+        self.set_response(GrpcBody::new_streaming(move |sender| async move {
+            // nothing is ever sent, this returns once the operation finished
+            let _ = done.recv().await;
+            sender.send_message(resp).await
+        }));
+        Ok(())
+    }
+
+    fn robot_start_session(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::StartSessionRequest::decode(message)?;
+        let (id, window) = {
+            let mut robot = self.robot.lock().unwrap();
+            let sessions = robot.sessions_mut();
+            (sessions.start(&req.resume)?, sessions.heartbeat_window())
+        };
+        let resp = robot::v1::StartSessionResponse {
+            id,
+            heartbeat_window: prost_types::Duration::try_from(window).ok(),
+        };
+        self.encode_message(resp)
+    }
+
+    fn robot_send_session_heartbeat(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::SendSessionHeartbeatRequest::decode(message)?;
+        self.robot
+            .lock()
+            .unwrap()
+            .sessions_mut()
+            .heartbeat(&req.id)?;
+        let resp = robot::v1::SendSessionHeartbeatResponse {};
+        self.encode_message(resp)
+    }
+
+    fn robot_get_sessions(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let _req = robot::v1::GetSessionsRequest::decode(message)?;
+        let sessions = self
+            .robot
+            .lock()
+            .unwrap()
+            .sessions()
+            .sessions()
+            .into_iter()
+            .map(|id| robot::v1::Session {
+                id,
+                peer_connection_info: None,
+            })
+            .collect();
+        let resp = robot::v1::GetSessionsResponse { sessions };
+        self.encode_message(resp)
+    }
+
+    fn robot_stream_status(&mut self, message: &[u8]) -> Result<(), GrpcError> {
+        let req = robot::v1::StreamStatusRequest::decode(message)?;
+        // Without an interval only the resources whose status changed are sent, the robot is
+        // then polled every STREAM_STATUS_CHANGES_PERIOD
+        let every = req
+            .every
+            .and_then(|every| Duration::try_from(every).ok())
+            .filter(|every| !every.is_zero());
+        let (period, only_changes) = match every {
+            Some(every) => (every, false),
+            None => (STREAM_STATUS_CHANGES_PERIOD, true),
+        };
+        let robot = self.robot.clone();
+        self.set_response(GrpcBody::new_streaming(move |sender| async move {
+            let mut last = HashMap::new();
+            loop {
+                let mut status = robot
+                    .lock()
+                    .unwrap()
+                    .get_status(robot::v1::GetStatusRequest {
+                        resource_names: req.resource_names.clone(),
+                    })?;
+                if only_changes {
+                    status.retain(|s| last.get(&s.name) != Some(&s.status));
+                    for s in status.iter() {
+                        last.insert(s.name.clone(), s.status.clone());
+                    }
+                }
+                if !status.is_empty() || !only_changes {
+                    sender
+                        .send_message(robot::v1::StreamStatusResponse { status })
+                        .await?;
+                }
+                smol::Timer::after(period).await;
+            }
+        }));
+        Ok(())
+    }
+
     #[cfg(feature = "camera")]
-    fn camera_get_frame(&mut self, message: &[u8]) -> anyhow::Result<()> {
+    fn camera_get_frame(&mut self, message: &[u8]) -> Result<(), GrpcError> {
         let req = component::camera::v1::GetImageRequest::decode(message)?;
-        if let Some(camera) = self.robot.lock().unwrap().get_camera_by_name(req.name) {
-            // TODO: Modify `get_frame` to return a data structure that can be passed into
-            // `encode_message`, rather than re-implementing `encode_message` here. See
-            // https://viam.atlassian.net/browse/RSDK-824
-            let mut buffer = RefCell::borrow_mut(&self.buffer).split_off(0);
-            buffer.put_u8(0);
-            buffer.put_u32(0.try_into().unwrap());
-            let msg = buffer.split_off(5);
-            let msg = camera.lock().unwrap().get_frame(msg)?;
-            let len = msg.len().to_be_bytes();
-            buffer[1] = len[0];
-            buffer[2] = len[1];
-            buffer[3] = len[2];
-            buffer[4] = len[3];
-            buffer.unsplit(msg);
-            self.response.data = Some(buffer.freeze());
-            return Ok(());
-        }
-        Err(anyhow::anyhow!("resource not found"))
+        let camera = match self.robot.lock().unwrap().get_camera_by_name(req.name) {
+            Some(c) => c,
+            None => return Err(GrpcError::RpcNotFound("resource not found".to_string())),
+        };
+        let mut buffer = self.response_buffer(GRPC_CHUNK_SIZE);
+        camera.lock().unwrap().get_frame(&mut buffer)?;
+        self.response.data = buffer.into_chunks();
+        Ok(())
     }
 
     #[cfg(feature = "camera")]
-    fn camera_get_point_cloud(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: camera_get_point_cloud")
+    fn camera_get_point_cloud(&mut self, _message: &[u8]) -> Result<(), GrpcError> {
+        Err(GrpcError::RpcUnimplemented(
+            "unimplemented: camera_get_point_cloud".to_string(),
+        ))
     }
 
     #[cfg(feature = "camera")]
-    fn camera_get_properties(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: camera_get_properties")
+    fn camera_get_properties(&mut self, _message: &[u8]) -> Result<(), GrpcError> {
+        Err(GrpcError::RpcUnimplemented(
+            "unimplemented: camera_get_properties".to_string(),
+        ))
     }
 
     #[cfg(feature = "camera")]
-    fn camera_render_frame(&mut self, _message: &[u8]) -> anyhow::Result<()> {
-        anyhow::bail!("unimplemented: camera_render_frame")
+    fn camera_render_frame(&mut self, _message: &[u8]) -> Result<(), GrpcError> {
+        Err(GrpcError::RpcUnimplemented(
+            "unimplemented: camera_render_frame".to_string(),
+        ))
     }
 
-    fn resource_names(&mut self, _unused_message: &[u8]) -> anyhow::Result<()> {
+    fn resource_names(&mut self, _unused_message: &[u8]) -> Result<(), GrpcError> {
         let rr = self.robot.lock().unwrap().get_resource_names()?;
         let rr = robot::v1::ResourceNamesResponse { resources: rr };
         self.encode_message(rr)
     }
 
-    fn encode_message<M: Message>(&mut self, m: M) -> anyhow::Result<()> {
-        let mut buffer = RefCell::borrow_mut(&self.buffer).split_off(0);
-        // The buffer will have a null byte, then 4 bytes containing the big-endian length of the
-        // data (*not* including this 5-byte header), and then the data from the message itself.
-        if 5 + m.encoded_len() > buffer.capacity() {
-            return Err(anyhow::anyhow!("not enough space"));
-        }
-        buffer.put_u8(0);
-        buffer.put_u32(m.encoded_len().try_into().unwrap());
-        let mut msg = buffer.split();
-        m.encode(&mut msg)?;
-        buffer.unsplit(msg);
-        self.response.data = Some(buffer.freeze());
+    /// Buffer to encode a response into, the first chunk reuses the memory of previous
+    /// responses and is grown to `size_hint` (capped to GRPC_CHUNK_SIZE)
+    fn response_buffer(&self, size_hint: usize) -> ChunkedBuffer {
+        let mut first = RefCell::borrow_mut(&self.buffer).split_off(0);
+        first.clear();
+        first.reserve(size_hint.min(GRPC_CHUNK_SIZE));
+        ChunkedBuffer::new(first, self.max_response_size)
+    }
+
+    pub fn encode_message<M: Message>(&mut self, m: M) -> Result<(), GrpcError> {
+        let mut buffer = self.response_buffer(5 + m.encoded_len());
+        buffer.encode_message(&m)?;
+        self.response.data = buffer.into_chunks();
         Ok(())
     }
+
+    /// Set the maximum size of an encoded response, larger responses fail with
+    /// RESOURCE_EXHAUSTED
+    pub fn set_max_response_size(&mut self, size: usize) {
+        self.max_response_size = size;
+    }
+}
+
+// Each clone serves a single request, so it starts with a fresh response body
+impl Clone for GrpcServer {
+    fn clone(&self) -> Self {
+        GrpcServer {
+            response: GrpcBody::new(),
+            buffer: self.buffer.clone(),
+            robot: self.robot.clone(),
+            handlers: self.handlers.clone(),
+            max_response_size: self.max_response_size,
+            auth: self.auth.clone(),
+            session: None,
+            operation: None,
+        }
+    }
 }
 
 impl Service<Request<Body>> for GrpcServer {
@@ -439,17 +2022,28 @@ impl Service<Request<Body>> for GrpcServer {
         #[cfg(debug_assertions)]
         log::debug!("processing {:?}", req);
         Box::pin(async move {
-            let (path, body) = req.into_parts();
-            let msg = body::to_bytes(body).await.map_err(|_| MyErr)?;
-            let path = match path.uri.path_and_query() {
-                Some(path) => path.as_str(),
-                None => return Err(MyErr),
-            };
-            svc.process_request(path, msg);
+            let (parts, body) = req.into_parts();
+            if parts.uri.path() == REFLECTION_METHOD {
+                // requests are streamed, they can't be read upfront
+                svc.serve_reflection(&parts.headers, body);
+            } else {
+                let msg = body::to_bytes(body).await.map_err(|_| MyErr)?;
+                let path = match parts.uri.path_and_query() {
+                    Some(path) => path.as_str(),
+                    None => return Err(MyErr),
+                };
+                // handlers can't be interrupted once started, don't start one past its deadline
+                match parts.extensions.get::<Deadline>() {
+                    Some(Deadline(deadline)) if Instant::now() >= *deadline => {
+                        svc.response.set_error(&GrpcError::RpcDeadlineExceeded)
+                    }
+                    _ => svc.process_request(path, &parts.headers, msg),
+                }
+            }
             Response::builder()
                 .header("content-type", "application/grpc")
                 .status(200)
-                .body(svc.response.clone())
+                .body(std::mem::take(&mut svc.response))
                 .map_err(|_| MyErr {})
         })
     }
@@ -486,6 +2080,12 @@ impl MakeSvcGrpcServer {
             server: GrpcServer::new(robot),
         }
     }
+    pub fn register_handler(&mut self, path: &str, handler: GrpcHandler) {
+        self.server.register_handler(path, handler)
+    }
+    pub fn set_max_response_size(&mut self, size: usize) {
+        self.server.set_max_response_size(size)
+    }
 }
 
 impl<T> Service<T> for MakeSvcGrpcServer {
@@ -506,31 +2106,84 @@ impl<T> Service<T> for MakeSvcGrpcServer {
     }
 }
 
+/// Deadline of a request, added to its extensions by `Timeout`
+#[derive(Clone, Copy, Debug)]
+struct Deadline(Instant);
+
+/// Service enforcing deadlines on RPCs, the deadline of a request is the earliest of the one
+/// requested by the client in the `grpc-timeout` header and the server side timeout of the
+/// method. Expired requests are answered with DEADLINE_EXCEEDED.
+///
+/// The robot isn't `Send`, handlers run on the executor and what they do synchronously can't
+/// be interrupted, so the deadline covers:
+/// - reading the request, a handler isn't started once its deadline expired;
+/// - what handlers await, interrupted as soon as the deadline expires: motions waiting for
+///   their component to stop (e.g. MotorService GoFor, BaseService Spin, GripperService Grab),
+///   BlockForOperation and streamed responses (StreamStatus, reflection);
+/// - the other RPCs only once their handler returned, a late response is replaced by
+///   DEADLINE_EXCEEDED. Their drivers bound how long they block (e.g. I2C probes of
+///   DiscoverComponents time out).
+#[derive(Clone)]
 pub struct Timeout<T> {
     inner: T,
-    timeout: Duration,
+    timeout: Option<Duration>,
+    method_timeouts: Rc<Vec<(String, Duration)>>,
 }
 
 impl<T> Timeout<T> {
-    #[allow(dead_code)]
-    pub fn new(inner: T, timeout: Duration) -> Timeout<T> {
-        Timeout { inner, timeout }
+    /// Wrap `inner`, `timeout` applies to methods without a more specific timeout
+    pub fn new(inner: T, timeout: Option<Duration>) -> Timeout<T> {
+        Timeout {
+            inner,
+            timeout,
+            method_timeouts: Rc::new(Vec::new()),
+        }
     }
-}
 
-// The error returned if processing a request timed out
-#[derive(Debug)]
-pub struct Expired;
+    /// Set the server side timeout of every method whose path starts with `prefix` (for example
+    /// "/viam.component.board.v1.BoardService/"), the longest matching prefix wins
+    pub fn set_method_timeout(&mut self, prefix: &str, timeout: Duration) {
+        let method_timeouts = Rc::make_mut(&mut self.method_timeouts);
+        method_timeouts.retain(|(p, _)| p != prefix);
+        method_timeouts.push((prefix.to_owned(), timeout));
+    }
 
-impl std::fmt::Display for Expired {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "expired")
+    fn server_timeout(&self, path: &str) -> Option<Duration> {
+        self.method_timeouts
+            .iter()
+            .filter(|(prefix, _)| path.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, timeout)| *timeout)
+            .or(self.timeout)
+    }
+}
+
+/// Parse the value of a grpc-timeout header, at most 8 digits followed by a unit
+/// (see https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md)
+fn parse_grpc_timeout(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?;
+    if value.len() < 2 || value.len() > 9 {
+        return None;
+    }
+    let (amount, unit) = value.split_at(value.len() - 1);
+    if !amount.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let amount: u64 = amount.parse().ok()?;
+    match unit {
+        "H" => Some(Duration::from_secs(amount * 3600)),
+        "M" => Some(Duration::from_secs(amount * 60)),
+        "S" => Some(Duration::from_secs(amount)),
+        "m" => Some(Duration::from_millis(amount)),
+        "u" => Some(Duration::from_micros(amount)),
+        "n" => Some(Duration::from_nanos(amount)),
+        _ => None,
     }
 }
 
 impl<T> Service<Request<Body>> for Timeout<T>
 where
-    T: Service<Request<Body>>,
+    T: Service<Request<Body>, Response = Response<GrpcBody>>,
     T::Error: Into<Box<dyn Error + Send + Sync>> + 'static,
     T::Future: 'static,
 {
@@ -543,15 +2196,210 @@ where
     }
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let client_timeout = match req.headers().get("grpc-timeout") {
+            Some(value) => {
+                let timeout = parse_grpc_timeout(value);
+                if timeout.is_none() {
+                    debug!("ignoring malformed grpc-timeout {:?}", value);
+                }
+                timeout
+            }
+            None => None,
+        };
+        let timeout = match (client_timeout, self.server_timeout(req.uri().path())) {
+            (Some(client), Some(server)) => Some(client.min(server)),
+            (client, server) => client.or(server),
+        };
+        let mut req = req;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        if let Some(deadline) = deadline {
+            req.extensions_mut().insert(Deadline(deadline));
+        }
         let fut = self.inner.call(req);
-        let timeout = self.timeout;
         let f = async move {
-            if let Some(req) = fut.timeout(timeout).await {
-                return req.map_err(Into::into);
+            let deadline = match deadline {
+                Some(deadline) => deadline,
+                None => return fut.await.map_err(Into::into),
+            };
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match fut.timeout(timeout).await {
+                Some(resp) if Instant::now() < deadline => {
+                    let mut resp = resp.map_err(Into::into)?;
+                    resp.body_mut().set_deadline(deadline);
+                    return Ok(resp);
+                }
+                // the handler blocked past the deadline without giving a chance to interrupt it
+                Some(_) => info!("deadline exceeded by the handler"),
+                None => info!("timeout"),
             }
-            info!("timeout");
-            Err(Box::new(MyErr))
+            let mut body = GrpcBody::new();
+            body.set_error(&GrpcError::RpcDeadlineExceeded);
+            Response::builder()
+                .header("content-type", "application/grpc")
+                .status(200)
+                .body(body)
+                .map_err(Into::into)
         };
         Box::pin(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::grpc::reflection::v1alpha::{
+        server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+        ServerReflectionRequest,
+    };
+
+    #[test]
+    fn every_route_is_described() {
+        let reflection = Reflection::new(vec![]);
+        let paths = GrpcServer::default_handlers().into_keys();
+        for path in paths.chain([REFLECTION_METHOD.to_string()]) {
+            let symbol = path.trim_start_matches('/').replace('/', ".");
+            let req = ServerReflectionRequest {
+                host: String::new(),
+                message_request: Some(MessageRequest::FileContainingSymbol(symbol.clone())),
+            };
+            assert!(
+                matches!(
+                    reflection.respond(req).message_response,
+                    Some(MessageResponse::FileDescriptorResponse(_))
+                ),
+                "{} isn't described",
+                symbol
+            );
+        }
+    }
+
+    /// Bytes which don't repeat within a chunk
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn joined(chunks: VecDeque<Bytes>) -> Vec<u8> {
+        assert!(chunks
+            .iter()
+            .all(|c| !c.is_empty() && c.len() <= GRPC_CHUNK_SIZE));
+        chunks.iter().flat_map(|c| c.iter().copied()).collect()
+    }
+
+    fn string_message(len: usize) -> prost_types::Value {
+        prost_types::Value {
+            kind: Some(prost_types::value::Kind::StringValue("x".repeat(len))),
+        }
+    }
+
+    #[test]
+    fn chunked_buffer_splits_writes_crossing_the_chunk_size() {
+        let data = pattern(2 * GRPC_CHUNK_SIZE + 100);
+        let mut buffer = ChunkedBuffer::new(BytesMut::with_capacity(1000), 100_000);
+        buffer.put_slice(&data[..10]);
+        buffer.put_slice(&data[10..]);
+        assert_eq!(buffer.len(), data.len());
+        let chunks = buffer.into_chunks();
+        let sizes: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+        assert_eq!(sizes, [1000, GRPC_CHUNK_SIZE, GRPC_CHUNK_SIZE - 900]);
+        assert_eq!(joined(chunks), data);
+    }
+
+    #[test]
+    fn chunked_buffer_caps_a_large_first_chunk() {
+        let data = pattern(3 * GRPC_CHUNK_SIZE);
+        let mut buffer = ChunkedBuffer::new(BytesMut::with_capacity(4 * GRPC_CHUNK_SIZE), 100_000);
+        buffer.put_slice(&data);
+        let chunks = buffer.into_chunks();
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(joined(chunks), data);
+    }
+
+    #[test]
+    fn chunked_buffer_encodes_messages_larger_than_a_chunk() {
+        let m = string_message(3 * GRPC_CHUNK_SIZE);
+        let mut buffer = ChunkedBuffer::new(BytesMut::new(), GRPC_MAX_RESPONSE_SIZE);
+        buffer.encode_message(&m).unwrap();
+        let data = joined(buffer.into_chunks());
+        assert_eq!(data[0], 0);
+        assert_eq!(
+            u32::from_be_bytes(data[1..5].try_into().unwrap()) as usize,
+            m.encoded_len()
+        );
+        assert_eq!(prost_types::Value::decode(&data[5..]).unwrap(), m);
+    }
+
+    #[test]
+    fn chunked_buffer_rejects_messages_over_the_limit() {
+        let m = string_message(100);
+        let size = 5 + m.encoded_len();
+        let mut buffer = ChunkedBuffer::new(BytesMut::new(), size - 1);
+        assert!(matches!(
+            buffer.encode_message(&m),
+            Err(GrpcError::RpcResourceExhausted(_))
+        ));
+        assert!(buffer.is_empty());
+        // prost checks the room left as well
+        assert!(m.encode(&mut buffer).is_ok());
+        assert!(m.encode(&mut buffer).is_err());
+        assert_eq!(buffer.remaining_mut(), size - 1 - m.encoded_len());
+
+        let mut buffer = ChunkedBuffer::new(BytesMut::new(), size);
+        buffer.encode_message(&m).unwrap();
+        assert_eq!(buffer.remaining_mut(), 0);
+        assert!(buffer.encode_message(&string_message(0)).is_err());
+        assert_eq!(joined(buffer.into_chunks()).len(), size);
+    }
+
+    #[test]
+    fn chunked_buffer_chunk_mut_follows_advance_mut() {
+        let max_size = 2 * GRPC_CHUNK_SIZE + 500;
+        let data = pattern(max_size);
+        let mut buffer = ChunkedBuffer::new(BytesMut::with_capacity(300), max_size);
+        let mut written = 0;
+        while buffer.remaining_mut() > 0 {
+            let chunk = buffer.chunk_mut();
+            assert!(chunk.len() > 0);
+            assert!(chunk.len() <= GRPC_CHUNK_SIZE);
+            let n = chunk.len().min(700);
+            chunk[..n].copy_from_slice(&data[written..written + n]);
+            unsafe { buffer.advance_mut(n) };
+            written += n;
+            assert_eq!(buffer.len(), written);
+            assert_eq!(buffer.remaining_mut(), max_size - written);
+        }
+        assert_eq!(written, max_size);
+        assert_eq!(buffer.chunk_mut().len(), 0);
+        assert_eq!(joined(buffer.into_chunks()), data);
+    }
+
+    fn timeout(value: &str) -> Option<Duration> {
+        parse_grpc_timeout(&HeaderValue::from_str(value).unwrap())
+    }
+
+    #[test]
+    fn grpc_timeout_units() {
+        assert_eq!(timeout("2H"), Some(Duration::from_secs(7200)));
+        assert_eq!(timeout("3M"), Some(Duration::from_secs(180)));
+        assert_eq!(timeout("10S"), Some(Duration::from_secs(10)));
+        assert_eq!(timeout("250m"), Some(Duration::from_millis(250)));
+        assert_eq!(timeout("99999999u"), Some(Duration::from_micros(99999999)));
+        assert_eq!(timeout("7n"), Some(Duration::from_nanos(7)));
+    }
+
+    #[test]
+    fn invalid_grpc_timeouts_are_ignored() {
+        for value in [
+            "",
+            "S",
+            "10",
+            "10s",
+            "-1S",
+            "+1S",
+            "1.5S",
+            "123456789S",
+            " 1S",
+        ] {
+            assert_eq!(timeout(value), None, "{:?}", value);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::common::analog::AnalogReader;
+use crate::common::generic::DoCommand;
 use crate::common::sensor::GenericReadingsResult;
 use crate::common::sensor::Sensor;
 use crate::common::sensor::SensorResult;
@@ -39,6 +40,8 @@ impl SensorT<f64> for MoistureSensor {
     }
 }
 
+impl DoCommand for MoistureSensor {}
+
 impl Status for MoistureSensor {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         Ok(Some(prost_types::Struct {
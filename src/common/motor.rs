@@ -1,18 +1,62 @@
 #![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
 use crate::common::status::Status;
 use log::*;
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-pub trait Position {
-    fn position(&self) -> anyhow::Result<i32> {
-        Ok(0)
-    }
+/// Describes what a motor is able to report, returned by `Motor::get_properties`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MotorSupportedProperties {
+    pub position_reporting: bool,
 }
 
-pub trait Motor: Status {
+pub trait Motor: Status + Actuator + DoCommand {
     fn set_power(&mut self, pct: f64) -> anyhow::Result<()>;
-    fn get_position(&mut self) -> anyhow::Result<i32>;
+    /// Position of the motor in revolutions from its zero position
+    fn get_position(&mut self) -> anyhow::Result<f64>;
+    /// Spin the motor at `rpm` for a number of `revolutions`, a revolutions of 0 means spinning
+    /// until stopped. Returns the duration after which the motor should be stopped if the
+    /// motor cannot stop by itself.
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>>;
+    /// Spin the motor at `rpm` until it reaches `position_revolutions`. Returns the duration
+    /// after which the motor should be stopped if the motor cannot stop by itself.
+    fn go_to(&mut self, rpm: f64, position_revolutions: f64) -> anyhow::Result<Option<Duration>>;
+    /// Returns whether the motor is powered and the current power percentage
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)>;
+    /// Set the current position (shifted by `offset` revolutions) as the new zero position, the
+    /// current position then reads `-offset`
+    fn reset_zero_position(&mut self, offset: f64) -> anyhow::Result<()>;
+    fn get_properties(&mut self) -> MotorSupportedProperties;
+}
+
+/// Computes the power and the duration an open loop motor should be spun at to achieve `rpm`
+/// for a number of `revolutions`, given the maximum rpm of the motor.
+pub fn go_for_math(
+    max_rpm: f64,
+    rpm: f64,
+    revolutions: f64,
+) -> anyhow::Result<(f64, Option<Duration>)> {
+    if max_rpm <= 0.0 {
+        return Err(GrpcError::RpcFailedPrecondition(
+            "max rpm should be strictly positive".to_string(),
+        )
+        .into());
+    }
+    if rpm == 0.0 {
+        return Err(GrpcError::RpcInvalidArgument("rpm cannot be zero".to_string()).into());
+    }
+    let rpm = rpm.clamp(-max_rpm, max_rpm);
+    if revolutions == 0.0 {
+        return Ok((rpm / max_rpm, None));
+    }
+    let dir = rpm.signum() * revolutions.signum();
+    let pct = (rpm.abs() / max_rpm) * dir;
+    let dur = Duration::from_secs_f64((revolutions.abs() / rpm.abs()) * 60.0);
+    Ok((pct, Some(dur)))
 }
 
 pub struct FakeMotor {
@@ -41,21 +85,51 @@ where
     fn set_power(&mut self, pct: f64) -> anyhow::Result<()> {
         self.get_mut().unwrap().set_power(pct)
     }
-    fn get_position(&mut self) -> anyhow::Result<i32> {
+    fn get_position(&mut self) -> anyhow::Result<f64> {
         self.get_mut().unwrap().get_position()
     }
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().go_for(rpm, revolutions)
+    }
+    fn go_to(&mut self, rpm: f64, position_revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        self.get_mut().unwrap().go_to(rpm, position_revolutions)
+    }
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)> {
+        self.lock().unwrap().is_powered()
+    }
+    fn reset_zero_position(&mut self, offset: f64) -> anyhow::Result<()> {
+        self.get_mut().unwrap().reset_zero_position(offset)
+    }
+    fn get_properties(&mut self) -> MotorSupportedProperties {
+        self.get_mut().unwrap().get_properties()
+    }
 }
 
 impl<A> Motor for Arc<Mutex<A>>
 where
     A: ?Sized + Motor,
 {
-    fn get_position(&mut self) -> anyhow::Result<i32> {
+    fn get_position(&mut self) -> anyhow::Result<f64> {
         self.lock().unwrap().get_position()
     }
     fn set_power(&mut self, pct: f64) -> anyhow::Result<()> {
         self.lock().unwrap().set_power(pct)
     }
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        self.lock().unwrap().go_for(rpm, revolutions)
+    }
+    fn go_to(&mut self, rpm: f64, position_revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        self.lock().unwrap().go_to(rpm, position_revolutions)
+    }
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)> {
+        self.lock().unwrap().is_powered()
+    }
+    fn reset_zero_position(&mut self, offset: f64) -> anyhow::Result<()> {
+        self.lock().unwrap().reset_zero_position(offset)
+    }
+    fn get_properties(&mut self) -> MotorSupportedProperties {
+        self.lock().unwrap().get_properties()
+    }
 }
 
 impl Motor for FakeMotor {
@@ -64,17 +138,57 @@ impl Motor for FakeMotor {
         self.power = pct;
         Ok(())
     }
-    fn get_position(&mut self) -> anyhow::Result<i32> {
-        Ok(self.pos as i32)
+    fn get_position(&mut self) -> anyhow::Result<f64> {
+        Ok(self.pos)
+    }
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        let (pct, dur) = go_for_math(100.0, rpm, revolutions)?;
+        info!("go for {} revolutions at {} rpm", revolutions, rpm);
+        self.power = pct;
+        if dur.is_some() {
+            self.pos += revolutions.abs() * pct.signum();
+        }
+        Ok(dur)
+    }
+    fn go_to(&mut self, rpm: f64, position_revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        info!("go to {} at {} rpm", position_revolutions, rpm);
+        self.pos = position_revolutions;
+        self.power = 0.0;
+        Ok(None)
+    }
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)> {
+        Ok((self.power != 0.0, self.power))
+    }
+    fn reset_zero_position(&mut self, offset: f64) -> anyhow::Result<()> {
+        self.pos = -offset;
+        Ok(())
+    }
+    fn get_properties(&mut self) -> MotorSupportedProperties {
+        MotorSupportedProperties {
+            position_reporting: true,
+        }
     }
 }
+impl Actuator for FakeMotor {
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(self.power != 0.0)
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        info!("stopping motor");
+        self.power = 0.0;
+        Ok(())
+    }
+}
+
+impl DoCommand for FakeMotor {}
+
 impl Status for FakeMotor {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         let mut bt = BTreeMap::new();
         bt.insert(
             "position".to_string(),
             prost_types::Value {
-                kind: Some(prost_types::value::Kind::NumberValue(15.0)),
+                kind: Some(prost_types::value::Kind::NumberValue(self.pos)),
             },
         );
         bt.insert(
@@ -87,3 +201,72 @@ impl Status for FakeMotor {
         Ok(Some(prost_types::Struct { fields: bt }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn go_for_math_without_revolutions_runs_until_stopped() {
+        assert_eq!(go_for_math(100.0, 50.0, 0.0).unwrap(), (0.5, None));
+        assert_eq!(go_for_math(100.0, -25.0, 0.0).unwrap(), (-0.25, None));
+    }
+
+    #[test]
+    fn go_for_math_times_the_revolutions() {
+        let (pct, dur) = go_for_math(100.0, 60.0, 3.0).unwrap();
+        assert_eq!(pct, 0.6);
+        assert_eq!(dur, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn go_for_math_goes_backwards_when_rpm_or_revolutions_are_negative() {
+        assert_eq!(go_for_math(100.0, -60.0, 3.0).unwrap().0, -0.6);
+        assert_eq!(go_for_math(100.0, 60.0, -3.0).unwrap().0, -0.6);
+        assert_eq!(go_for_math(100.0, -60.0, -3.0).unwrap().0, 0.6);
+    }
+
+    #[test]
+    fn go_for_math_caps_rpm_at_max_rpm() {
+        let (pct, dur) = go_for_math(100.0, 200.0, 50.0).unwrap();
+        assert_eq!(pct, 1.0);
+        assert_eq!(dur, Some(Duration::from_secs(30)));
+    }
+
+    fn status_position(motor: &FakeMotor) -> Option<prost_types::value::Kind> {
+        motor.get_status().unwrap().unwrap().fields["position"]
+            .kind
+            .clone()
+    }
+
+    #[test]
+    fn fake_motor_reports_its_position() {
+        let mut motor = FakeMotor::new();
+        motor.go_for(60.0, -3.0).unwrap();
+        assert_eq!(motor.get_position().unwrap(), 7.0);
+        assert_eq!(
+            status_position(&motor),
+            Some(prost_types::value::Kind::NumberValue(7.0))
+        );
+        motor.reset_zero_position(1.5).unwrap();
+        assert_eq!(motor.get_position().unwrap(), -1.5);
+        assert_eq!(
+            status_position(&motor),
+            Some(prost_types::value::Kind::NumberValue(-1.5))
+        );
+    }
+
+    #[test]
+    fn go_for_math_rejects_zero_rpm_and_max_rpm() {
+        let err = go_for_math(100.0, 0.0, 1.0).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GrpcError>(),
+            Some(GrpcError::RpcInvalidArgument(_))
+        ));
+        let err = go_for_math(0.0, 10.0, 1.0).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<GrpcError>(),
+            Some(GrpcError::RpcFailedPrecondition(_))
+        ));
+    }
+}
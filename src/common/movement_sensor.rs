@@ -0,0 +1,619 @@
+#![allow(dead_code)]
+use crate::common::base::WheeledBaseGeometry;
+use crate::common::encoder::{Encoder, EncoderPositionType};
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::status::Status;
+use crate::proto::common::v1::{GeoPoint, Orientation, Vector3};
+use crate::proto::component::movementsensor;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Mean radius of the earth, used to turn distances into latitude and longitude
+static EARTH_RADIUS_MM: f64 = 6_371_000_000.0;
+
+/// Readings a movement sensor is able to provide, returned by `MovementSensor::get_properties`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MovementSensorSupportedProperties {
+    pub position_supported: bool,
+    pub linear_velocity_supported: bool,
+    pub angular_velocity_supported: bool,
+    pub linear_acceleration_supported: bool,
+    pub compass_heading_supported: bool,
+    pub orientation_supported: bool,
+}
+
+impl From<MovementSensorSupportedProperties> for movementsensor::v1::GetPropertiesResponse {
+    fn from(props: MovementSensorSupportedProperties) -> Self {
+        movementsensor::v1::GetPropertiesResponse {
+            linear_velocity_supported: props.linear_velocity_supported,
+            angular_velocity_supported: props.angular_velocity_supported,
+            orientation_supported: props.orientation_supported,
+            position_supported: props.position_supported,
+            compass_heading_supported: props.compass_heading_supported,
+            linear_acceleration_supported: props.linear_acceleration_supported,
+        }
+    }
+}
+
+/// Error returned when asking a movement sensor for a `reading` its properties don't list
+pub fn reading_not_supported(reading: &str) -> GrpcError {
+    GrpcError::RpcUnimplemented(format!(
+        "{} is not supported by this movement sensor",
+        reading
+    ))
+}
+
+/// Sensor reporting how the robot moves, implementations only override the readings their
+/// properties list as supported
+pub trait MovementSensor: Status + DoCommand {
+    fn get_properties(&self) -> MovementSensorSupportedProperties;
+    /// Coordinates and altitude (in mm)
+    fn get_position(&mut self) -> anyhow::Result<(GeoPoint, f32)> {
+        Err(reading_not_supported("position").into())
+    }
+    /// Velocity in mm/sec
+    fn get_linear_velocity(&mut self) -> anyhow::Result<Vector3> {
+        Err(reading_not_supported("linear velocity").into())
+    }
+    /// Velocity in deg/sec
+    fn get_angular_velocity(&mut self) -> anyhow::Result<Vector3> {
+        Err(reading_not_supported("angular velocity").into())
+    }
+    /// Acceleration in mm/sec^2
+    fn get_linear_acceleration(&mut self) -> anyhow::Result<Vector3> {
+        Err(reading_not_supported("linear acceleration").into())
+    }
+    /// Degrees clockwise from north
+    fn get_compass_heading(&mut self) -> anyhow::Result<f64> {
+        Err(reading_not_supported("compass heading").into())
+    }
+    fn get_orientation(&mut self) -> anyhow::Result<Orientation> {
+        Err(reading_not_supported("orientation").into())
+    }
+    /// Accuracy (in mm) of the readings, by reading
+    fn get_accuracy(&mut self) -> anyhow::Result<HashMap<String, f32>> {
+        Ok(HashMap::new())
+    }
+}
+
+impl<L> MovementSensor for Mutex<L>
+where
+    L: ?Sized + MovementSensor,
+{
+    fn get_properties(&self) -> MovementSensorSupportedProperties {
+        self.lock().unwrap().get_properties()
+    }
+    fn get_position(&mut self) -> anyhow::Result<(GeoPoint, f32)> {
+        self.get_mut().unwrap().get_position()
+    }
+    fn get_linear_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.get_mut().unwrap().get_linear_velocity()
+    }
+    fn get_angular_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.get_mut().unwrap().get_angular_velocity()
+    }
+    fn get_linear_acceleration(&mut self) -> anyhow::Result<Vector3> {
+        self.get_mut().unwrap().get_linear_acceleration()
+    }
+    fn get_compass_heading(&mut self) -> anyhow::Result<f64> {
+        self.get_mut().unwrap().get_compass_heading()
+    }
+    fn get_orientation(&mut self) -> anyhow::Result<Orientation> {
+        self.get_mut().unwrap().get_orientation()
+    }
+    fn get_accuracy(&mut self) -> anyhow::Result<HashMap<String, f32>> {
+        self.get_mut().unwrap().get_accuracy()
+    }
+}
+
+impl<A> MovementSensor for Arc<Mutex<A>>
+where
+    A: ?Sized + MovementSensor,
+{
+    fn get_properties(&self) -> MovementSensorSupportedProperties {
+        self.lock().unwrap().get_properties()
+    }
+    fn get_position(&mut self) -> anyhow::Result<(GeoPoint, f32)> {
+        self.lock().unwrap().get_position()
+    }
+    fn get_linear_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.lock().unwrap().get_linear_velocity()
+    }
+    fn get_angular_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.lock().unwrap().get_angular_velocity()
+    }
+    fn get_linear_acceleration(&mut self) -> anyhow::Result<Vector3> {
+        self.lock().unwrap().get_linear_acceleration()
+    }
+    fn get_compass_heading(&mut self) -> anyhow::Result<f64> {
+        self.lock().unwrap().get_compass_heading()
+    }
+    fn get_orientation(&mut self) -> anyhow::Result<Orientation> {
+        self.lock().unwrap().get_orientation()
+    }
+    fn get_accuracy(&mut self) -> anyhow::Result<HashMap<String, f32>> {
+        self.lock().unwrap().get_accuracy()
+    }
+}
+
+/// Returns the same readings every time, its properties tell clients which ones to ask for
+pub struct FakeMovementSensor {
+    properties: MovementSensorSupportedProperties,
+}
+
+impl FakeMovementSensor {
+    pub fn new(properties: MovementSensorSupportedProperties) -> Self {
+        FakeMovementSensor { properties }
+    }
+}
+
+/// Supports every reading
+impl Default for FakeMovementSensor {
+    fn default() -> Self {
+        Self::new(MovementSensorSupportedProperties {
+            position_supported: true,
+            linear_velocity_supported: true,
+            angular_velocity_supported: true,
+            linear_acceleration_supported: true,
+            compass_heading_supported: true,
+            orientation_supported: true,
+        })
+    }
+}
+
+impl MovementSensor for FakeMovementSensor {
+    fn get_properties(&self) -> MovementSensorSupportedProperties {
+        self.properties
+    }
+    fn get_position(&mut self) -> anyhow::Result<(GeoPoint, f32)> {
+        Ok((
+            GeoPoint {
+                latitude: 40.7,
+                longitude: -73.98,
+            },
+            50.0,
+        ))
+    }
+    fn get_linear_velocity(&mut self) -> anyhow::Result<Vector3> {
+        Ok(Vector3 {
+            x: 0.0,
+            y: 100.0,
+            z: 0.0,
+        })
+    }
+    fn get_angular_velocity(&mut self) -> anyhow::Result<Vector3> {
+        Ok(Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 10.0,
+        })
+    }
+    fn get_linear_acceleration(&mut self) -> anyhow::Result<Vector3> {
+        Ok(Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: 9806.65,
+        })
+    }
+    fn get_compass_heading(&mut self) -> anyhow::Result<f64> {
+        Ok(90.0)
+    }
+    fn get_orientation(&mut self) -> anyhow::Result<Orientation> {
+        Ok(Orientation {
+            o_x: 0.0,
+            o_y: 0.0,
+            o_z: 1.0,
+            theta: 270.0,
+        })
+    }
+    fn get_accuracy(&mut self) -> anyhow::Result<HashMap<String, f32>> {
+        Ok(HashMap::from([("position".to_string(), 1000.0)]))
+    }
+}
+
+impl DoCommand for FakeMovementSensor {}
+
+impl Status for FakeMovementSensor {
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(prost_types::Struct {
+            fields: BTreeMap::new(),
+        }))
+    }
+}
+
+/// Readings closer than that reuse the previous sample, velocities computed over a shorter
+/// time would mostly measure the jitter of the encoders
+static ODOMETRY_MIN_PERIOD: Duration = Duration::from_millis(50);
+
+fn number_value(value: f64) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::NumberValue(value)),
+    }
+}
+
+fn struct_value(fields: &[(&str, f64)]) -> prost_types::Value {
+    prost_types::Value {
+        kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), number_value(*value)))
+                .collect(),
+        })),
+    }
+}
+
+/// Tracks a differential base from the encoders of its left and right wheels, which can be
+/// shared with the motors driving them. The base starts at `origin` facing north, positions
+/// are integrated when a reading is asked for, at most every `ODOMETRY_MIN_PERIOD`, so
+/// velocities are averaged since the previous sample.
+pub struct WheeledOdometry<EL, ER> {
+    encoder_left: EL,
+    encoder_right: ER,
+    geometry: WheeledBaseGeometry,
+    ticks_per_rotation: f64,
+    origin: GeoPoint,
+    /// wheel positions (in revolutions) and time of the previous reading
+    last: Option<(f64, f64, Instant)>,
+    /// distance from the origin, in mm east and north
+    east_mm: f64,
+    north_mm: f64,
+    /// radians counterclockwise from north
+    heading: f64,
+    linear_velocity: f64,
+    angular_velocity: f64,
+}
+
+impl<EL, ER> WheeledOdometry<EL, ER>
+where
+    EL: Encoder,
+    ER: Encoder,
+{
+    /// `ticks_per_rotation` is how many ticks the encoders count when their wheel turns once
+    pub fn new(
+        encoder_left: EL,
+        encoder_right: ER,
+        geometry: WheeledBaseGeometry,
+        ticks_per_rotation: f64,
+        origin: GeoPoint,
+    ) -> Self {
+        WheeledOdometry {
+            encoder_left,
+            encoder_right,
+            geometry,
+            ticks_per_rotation,
+            origin,
+            last: None,
+            east_mm: 0.0,
+            north_mm: 0.0,
+            heading: 0.0,
+            linear_velocity: 0.0,
+            angular_velocity: 0.0,
+        }
+    }
+    fn update(&mut self) -> anyhow::Result<()> {
+        let now = Instant::now();
+        if let Some((_, _, last_time)) = self.last {
+            if now - last_time < ODOMETRY_MIN_PERIOD {
+                return Ok(());
+            }
+        }
+        let ticks_left = self.encoder_left.get_position(EncoderPositionType::Ticks)?;
+        let ticks_right = self
+            .encoder_right
+            .get_position(EncoderPositionType::Ticks)?;
+        let left = ticks_left / self.ticks_per_rotation;
+        let right = ticks_right / self.ticks_per_rotation;
+        let (last_left, last_right, last_time) = match self.last.replace((left, right, now)) {
+            Some(last) => last,
+            None => return Ok(()),
+        };
+        let dist_left = (left - last_left) * self.geometry.wheel_circumference_mm();
+        let dist_right = (right - last_right) * self.geometry.wheel_circumference_mm();
+        let dist = (dist_left + dist_right) / 2.0;
+        let turn = (dist_right - dist_left) / self.geometry.width_mm();
+        // the base moved along the heading it had halfway through the turn
+        let mid_heading = self.heading + turn / 2.0;
+        self.east_mm -= dist * mid_heading.sin();
+        self.north_mm += dist * mid_heading.cos();
+        self.heading = (self.heading + turn).rem_euclid(std::f64::consts::TAU);
+        let elapsed = (now - last_time).as_secs_f64();
+        self.linear_velocity = dist / elapsed;
+        self.angular_velocity = turn.to_degrees() / elapsed;
+        Ok(())
+    }
+    fn geo_point(&self) -> GeoPoint {
+        GeoPoint {
+            latitude: self.origin.latitude + (self.north_mm / EARTH_RADIUS_MM).to_degrees(),
+            longitude: self.origin.longitude
+                + (self.east_mm / (EARTH_RADIUS_MM * self.origin.latitude.to_radians().cos()))
+                    .to_degrees(),
+        }
+    }
+}
+
+impl<EL, ER> MovementSensor for WheeledOdometry<EL, ER>
+where
+    EL: Encoder,
+    ER: Encoder,
+{
+    fn get_properties(&self) -> MovementSensorSupportedProperties {
+        MovementSensorSupportedProperties {
+            position_supported: true,
+            linear_velocity_supported: true,
+            angular_velocity_supported: true,
+            compass_heading_supported: true,
+            orientation_supported: true,
+            ..Default::default()
+        }
+    }
+    fn get_position(&mut self) -> anyhow::Result<(GeoPoint, f32)> {
+        self.update()?;
+        Ok((self.geo_point(), 0.0))
+    }
+    fn get_linear_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.update()?;
+        Ok(Vector3 {
+            x: 0.0,
+            y: self.linear_velocity,
+            z: 0.0,
+        })
+    }
+    fn get_angular_velocity(&mut self) -> anyhow::Result<Vector3> {
+        self.update()?;
+        Ok(Vector3 {
+            x: 0.0,
+            y: 0.0,
+            z: self.angular_velocity,
+        })
+    }
+    fn get_compass_heading(&mut self) -> anyhow::Result<f64> {
+        self.update()?;
+        Ok((360.0 - self.heading.to_degrees()).rem_euclid(360.0))
+    }
+    fn get_orientation(&mut self) -> anyhow::Result<Orientation> {
+        self.update()?;
+        Ok(Orientation {
+            o_x: 0.0,
+            o_y: 0.0,
+            o_z: 1.0,
+            theta: self.heading.to_degrees(),
+        })
+    }
+}
+
+impl<EL, ER> DoCommand for WheeledOdometry<EL, ER>
+where
+    EL: Encoder,
+    ER: Encoder,
+{
+}
+
+impl<EL, ER> Status for WheeledOdometry<EL, ER>
+where
+    EL: Encoder,
+    ER: Encoder,
+{
+    /// Position and velocities as of the last sample, reading the status doesn't sample
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        let position = self.geo_point();
+        Ok(Some(prost_types::Struct {
+            fields: BTreeMap::from([
+                (
+                    "position".to_string(),
+                    struct_value(&[
+                        ("latitude", position.latitude),
+                        ("longitude", position.longitude),
+                    ]),
+                ),
+                (
+                    "linear_velocity".to_string(),
+                    struct_value(&[("x", 0.0), ("y", self.linear_velocity), ("z", 0.0)]),
+                ),
+                (
+                    "angular_velocity".to_string(),
+                    struct_value(&[("x", 0.0), ("y", 0.0), ("z", self.angular_velocity)]),
+                ),
+            ]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::encoder::FakeEncoder;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    // 100 ticks per 200 mm revolution, spinning the base by 90 degrees moves each wheel by
+    // 100 mm i.e. 50 ticks
+    const TICKS_PER_ROTATION: f64 = 100.0;
+    const WIDTH_MM: f64 = 400.0 / PI;
+
+    type TestOdometry = WheeledOdometry<Arc<Mutex<FakeEncoder>>, Arc<Mutex<FakeEncoder>>>;
+
+    struct Wheels {
+        left: Arc<Mutex<FakeEncoder>>,
+        right: Arc<Mutex<FakeEncoder>>,
+        odometry: TestOdometry,
+    }
+
+    impl Wheels {
+        fn new() -> Self {
+            let left = Arc::new(Mutex::new(FakeEncoder::new(0)));
+            let right = Arc::new(Mutex::new(FakeEncoder::new(0)));
+            let geometry = WheeledBaseGeometry::new(200.0, WIDTH_MM, 100.0).unwrap();
+            let origin = GeoPoint {
+                latitude: 40.7,
+                longitude: -73.98,
+            };
+            let mut odometry = WheeledOdometry::new(
+                left.clone(),
+                right.clone(),
+                geometry,
+                TICKS_PER_ROTATION,
+                origin,
+            );
+            // the first reading is where the wheels start from
+            odometry.update().unwrap();
+            Wheels {
+                left,
+                right,
+                odometry,
+            }
+        }
+        /// Turn the wheels by `left` and `right` ticks over one second, then sample
+        fn drive(&mut self, left: i64, right: i64) {
+            Self::turn(&self.left, left);
+            Self::turn(&self.right, right);
+            if let Some((_, _, time)) = self.odometry.last.as_mut() {
+                *time -= Duration::from_secs(1);
+            }
+            self.odometry.update().unwrap();
+        }
+        fn turn(encoder: &Arc<Mutex<FakeEncoder>>, ticks: i64) {
+            let mut encoder = encoder.lock().unwrap();
+            let position = encoder.get_position(EncoderPositionType::Ticks).unwrap();
+            encoder.set_ticks(position as i64 + ticks);
+        }
+        fn assert_at(&self, east_mm: f64, north_mm: f64, heading: f64) {
+            assert_close(self.odometry.east_mm, east_mm);
+            assert_close(self.odometry.north_mm, north_mm);
+            assert_close(self.odometry.heading, heading);
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    /// Velocities are measured over one second and a few microseconds
+    fn assert_velocity(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= expected.abs() * 0.01,
+            "{} is not about {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn odometry_drives_straight_north() {
+        let mut wheels = Wheels::new();
+        wheels.drive(500, 500);
+        wheels.assert_at(0.0, 1000.0, 0.0);
+        assert_velocity(wheels.odometry.linear_velocity, 1000.0);
+        assert_close(wheels.odometry.angular_velocity, 0.0);
+        assert_close(wheels.odometry.get_compass_heading().unwrap(), 0.0);
+
+        let (position, _) = wheels.odometry.get_position().unwrap();
+        assert_close(
+            position.latitude,
+            40.7 + (1000.0 / EARTH_RADIUS_MM).to_degrees(),
+        );
+        assert_close(position.longitude, -73.98);
+
+        wheels.drive(-250, -250);
+        wheels.assert_at(0.0, 500.0, 0.0);
+        assert_velocity(wheels.odometry.linear_velocity, -500.0);
+    }
+
+    #[test]
+    fn odometry_spins_in_place() {
+        let mut wheels = Wheels::new();
+        wheels.drive(-50, 50);
+        wheels.assert_at(0.0, 0.0, FRAC_PI_2);
+        assert_close(wheels.odometry.linear_velocity, 0.0);
+        assert_velocity(wheels.odometry.angular_velocity, 90.0);
+        // facing west
+        assert_close(wheels.odometry.get_compass_heading().unwrap(), 270.0);
+        assert_close(wheels.odometry.get_orientation().unwrap().theta, 90.0);
+    }
+
+    #[test]
+    fn odometry_goes_west_when_turned_left() {
+        let mut wheels = Wheels::new();
+        wheels.drive(-50, 50);
+        wheels.drive(500, 500);
+        wheels.assert_at(-1000.0, 0.0, FRAC_PI_2);
+        let (position, _) = wheels.odometry.get_position().unwrap();
+        assert!(position.longitude < -73.98);
+        assert_close(position.latitude, 40.7);
+    }
+
+    #[test]
+    fn odometry_heading_wraps_around() {
+        let mut wheels = Wheels::new();
+        // 90 degrees clockwise, facing east
+        wheels.drive(50, -50);
+        wheels.assert_at(0.0, 0.0, 3.0 * FRAC_PI_2);
+        assert_close(wheels.odometry.get_compass_heading().unwrap(), 90.0);
+        assert_velocity(wheels.odometry.angular_velocity, -90.0);
+        wheels.drive(500, 500);
+        wheels.assert_at(1000.0, 0.0, 3.0 * FRAC_PI_2);
+        // a full turn counterclockwise brings it back to the same heading
+        wheels.drive(-200, 200);
+        wheels.assert_at(1000.0, 0.0, 3.0 * FRAC_PI_2);
+        // then 180 degrees counterclockwise, facing west
+        wheels.drive(-100, 100);
+        wheels.assert_at(1000.0, 0.0, FRAC_PI_2);
+        assert_close(wheels.odometry.get_compass_heading().unwrap(), 270.0);
+    }
+
+    #[test]
+    fn odometry_follows_arcs_along_the_mid_heading() {
+        let mut wheels = Wheels::new();
+        // the right wheel goes 200 mm around the left one, a quarter turn left for 100 mm
+        wheels.drive(0, 100);
+        let side = 100.0 * FRAC_PI_4.sin();
+        wheels.assert_at(-side, side, FRAC_PI_2);
+        assert_velocity(wheels.odometry.linear_velocity, 100.0);
+        assert_velocity(wheels.odometry.angular_velocity, 90.0);
+
+        // smaller steps get closer to the arc of radius 200 / pi around the left wheel
+        let mut wheels = Wheels::new();
+        for _ in 0..100 {
+            wheels.drive(0, 1);
+        }
+        let radius = 200.0 / PI;
+        assert!((wheels.odometry.east_mm + radius).abs() < 0.01);
+        assert!((wheels.odometry.north_mm - radius).abs() < 0.01);
+        assert_close(wheels.odometry.heading, FRAC_PI_2);
+    }
+
+    #[test]
+    fn odometry_reuses_recent_samples() {
+        let mut wheels = Wheels::new();
+        Wheels::turn(&wheels.left, 500);
+        Wheels::turn(&wheels.right, 500);
+        // sampled right after the first reading
+        wheels.odometry.get_position().unwrap();
+        wheels.assert_at(0.0, 0.0, 0.0);
+        // the movement is accounted for by the next sample
+        wheels.drive(0, 0);
+        wheels.assert_at(0.0, 1000.0, 0.0);
+    }
+
+    #[test]
+    fn odometry_starts_from_the_first_reading() {
+        let left = Arc::new(Mutex::new(FakeEncoder::new(0)));
+        let right = Arc::new(Mutex::new(FakeEncoder::new(0)));
+        left.lock().unwrap().set_ticks(1234);
+        right.lock().unwrap().set_ticks(-77);
+        let geometry = WheeledBaseGeometry::new(200.0, WIDTH_MM, 100.0).unwrap();
+        let origin = GeoPoint {
+            latitude: 0.0,
+            longitude: 0.0,
+        };
+        let mut odometry = WheeledOdometry::new(left, right, geometry, TICKS_PER_ROTATION, origin);
+        let (position, _) = odometry.get_position().unwrap();
+        assert_eq!((position.latitude, position.longitude), (0.0, 0.0));
+        assert_eq!(odometry.get_compass_heading().unwrap(), 0.0);
+    }
+}
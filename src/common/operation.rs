@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use crate::{common::error::GrpcError, common::session::random_uuid, proto::robot};
+use smol::channel::{bounded, Receiver, Sender};
+
+/// Metadata (header) key clients can use to choose the id of the operation started by an RPC
+pub static OPID_METADATA_KEY: &str = "opid";
+
+/// Signals the cancellation of an operation to the work done on its behalf. Long running work
+/// either awaits `cancelled` or polls `is_cancelled`.
+#[derive(Clone)]
+pub struct CancellationToken {
+    // nothing is ever sent, the channel is closed to cancel
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+    superseded: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = bounded(1);
+        CancellationToken {
+            sender,
+            receiver,
+            superseded: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.sender.close();
+    }
+
+    /// Cancel because a newer operation took over the same resource, the work should then
+    /// leave the resource as is rather than stopping it
+    pub fn supersede(&self) {
+        self.superseded.store(true, Ordering::Relaxed);
+        self.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    pub fn is_superseded(&self) -> bool {
+        self.superseded.load(Ordering::Relaxed)
+    }
+
+    /// Completes once the token is cancelled
+    pub async fn cancelled(&self) {
+        let _ = self.receiver.recv().await;
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Operation {
+    method: String,
+    arguments: Option<prost_types::Struct>,
+    started: SystemTime,
+    session_id: Option<String>,
+    labels: Vec<String>,
+    token: CancellationToken,
+    // closed once the operation finished
+    done: (Sender<()>, Receiver<()>),
+}
+
+/// Registry of the operations (i.e. RPCs) in progress
+pub struct OperationManager {
+    operations: HashMap<String, Operation>,
+}
+
+impl OperationManager {
+    pub fn new() -> Self {
+        OperationManager {
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Register an operation for a call to `method`, `id` is the id requested by the client
+    /// if any. Returns the id of the operation and the token cancelling it.
+    pub fn create(
+        &mut self,
+        id: Option<&str>,
+        method: &str,
+        session_id: Option<String>,
+    ) -> Result<(String, CancellationToken), GrpcError> {
+        let id = match id {
+            Some(id) if self.operations.contains_key(id) => {
+                return Err(GrpcError::RpcAlreadyExists(format!(
+                    "operation {} already exists",
+                    id
+                )))
+            }
+            Some(id) => id.to_owned(),
+            None => random_uuid()?,
+        };
+        let token = CancellationToken::new();
+        self.operations.insert(
+            id.clone(),
+            Operation {
+                method: method.to_owned(),
+                arguments: None,
+                started: SystemTime::now(),
+                session_id,
+                labels: Vec::new(),
+                token: token.clone(),
+                done: bounded(1),
+            },
+        );
+        Ok((id, token))
+    }
+
+    /// Remove a finished operation, waking up whoever blocks on it
+    pub fn finish(&mut self, id: &str) {
+        if let Some(op) = self.operations.remove(id) {
+            op.done.0.close();
+        }
+    }
+
+    pub fn set_arguments(&mut self, id: &str, arguments: prost_types::Struct) {
+        if let Some(op) = self.operations.get_mut(id) {
+            op.arguments = Some(arguments);
+        }
+    }
+
+    /// Label the operation `id` with `label` and supersede every other operation carrying
+    /// it, e.g. to cancel a motion of a motor when a new one is requested
+    pub fn cancel_others_with_label(&mut self, id: &str, label: &str) {
+        for (other, op) in self.operations.iter_mut() {
+            if other != id && op.labels.iter().any(|l| l == label) {
+                op.token.supersede();
+                op.labels.retain(|l| l != label);
+            }
+        }
+        if let Some(op) = self.operations.get_mut(id) {
+            op.labels.push(label.to_owned());
+        }
+    }
+
+    pub fn cancel(&mut self, id: &str) -> Result<(), GrpcError> {
+        match self.operations.get(id) {
+            Some(op) => {
+                op.token.cancel();
+                Ok(())
+            }
+            None => Err(GrpcError::RpcNotFound(format!(
+                "operation {} not found",
+                id
+            ))),
+        }
+    }
+
+    /// Returns a receiver closed when the operation `id` finishes, `None` if it isn't running
+    pub fn wait_for(&self, id: &str) -> Option<Receiver<()>> {
+        self.operations.get(id).map(|op| op.done.1.clone())
+    }
+
+    pub fn operations(&self) -> Vec<robot::v1::Operation> {
+        self.operations
+            .iter()
+            .map(|(id, op)| robot::v1::Operation {
+                id: id.clone(),
+                method: op.method.clone(),
+                arguments: op.arguments.clone(),
+                started: Some(op.started.into()),
+                session_id: op.session_id.clone(),
+            })
+            .collect()
+    }
+}
+
+impl Default for OperationManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_uses_the_requested_id_once() {
+        let mut ops = OperationManager::new();
+        let (id, _) = ops.create(Some("opid"), "/Motor/GoFor", None).unwrap();
+        assert_eq!(id, "opid");
+        assert!(matches!(
+            ops.create(Some("opid"), "/Motor/GoFor", None),
+            Err(GrpcError::RpcAlreadyExists(_))
+        ));
+        assert_eq!(ops.operations().len(), 1);
+        // the id is free again once the operation finished
+        ops.finish("opid");
+        assert!(ops.create(Some("opid"), "/Motor/GoFor", None).is_ok());
+    }
+
+    #[test]
+    fn create_generates_distinct_ids() {
+        let mut ops = OperationManager::new();
+        let (first, _) = ops.create(None, "/Base/Spin", None).unwrap();
+        let (second, _) = ops
+            .create(None, "/Base/Spin", Some("sid".to_string()))
+            .unwrap();
+        assert_ne!(first, second);
+        let listed = ops.operations();
+        let op = listed.iter().find(|op| op.id == second).unwrap();
+        assert_eq!(op.method, "/Base/Spin");
+        assert_eq!(op.session_id.as_deref(), Some("sid"));
+    }
+
+    #[test]
+    fn cancel_cancels_the_token() {
+        let mut ops = OperationManager::new();
+        let (id, token) = ops.create(None, "/Motor/GoFor", None).unwrap();
+        assert!(!token.is_cancelled());
+        ops.cancel(&id).unwrap();
+        assert!(token.is_cancelled());
+        assert!(!token.is_superseded());
+        smol::block_on(token.cancelled());
+        assert!(matches!(
+            ops.cancel("unknown"),
+            Err(GrpcError::RpcNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn cancel_others_with_label_supersedes_the_older_operations() {
+        let mut ops = OperationManager::new();
+        let (first, first_token) = ops.create(None, "/Motor/GoFor", None).unwrap();
+        ops.cancel_others_with_label(&first, "motor");
+        let (other, other_token) = ops.create(None, "/Base/Spin", None).unwrap();
+        ops.cancel_others_with_label(&other, "base");
+        assert!(!first_token.is_cancelled());
+
+        let (second, second_token) = ops.create(None, "/Motor/GoTo", None).unwrap();
+        ops.cancel_others_with_label(&second, "motor");
+        assert!(first_token.is_cancelled());
+        assert!(first_token.is_superseded());
+        assert!(!other_token.is_cancelled());
+        assert!(!second_token.is_cancelled());
+
+        // labelling an operation again doesn't cancel it
+        ops.cancel_others_with_label(&second, "motor");
+        assert!(!second_token.is_cancelled());
+    }
+
+    #[test]
+    fn wait_for_returns_once_the_operation_finished() {
+        let mut ops = OperationManager::new();
+        assert!(ops.wait_for("unknown").is_none());
+        let (id, _) = ops.create(None, "/Motor/GoFor", None).unwrap();
+        let done = ops.wait_for(&id).unwrap();
+        assert!(!done.is_closed());
+        ops.finish(&id);
+        assert!(done.is_closed());
+        assert!(smol::block_on(done.recv()).is_err());
+        assert!(ops.wait_for(&id).is_none());
+        assert!(ops.operations().is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashSet;
+
+use crate::{
+    common::{error::GrpcError, grpc::GrpcStreamSender},
+    proto::{
+        google::rpc::Code,
+        grpc::reflection::v1alpha::{
+            server_reflection_request::MessageRequest, server_reflection_response::MessageResponse,
+            ErrorResponse, ExtensionNumberResponse, FileDescriptorResponse, ListServiceResponse,
+            ServerReflectionRequest, ServerReflectionResponse, ServiceResponse,
+        },
+    },
+};
+use bytes::{Buf, BytesMut};
+use hyper::{body::HttpBody, Body};
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+
+/// Path of the (bidirectional streaming) reflection RPC
+pub static REFLECTION_METHOD: &str =
+    "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo";
+
+/// Name of the reflection service itself
+pub static REFLECTION_SERVICE: &str = "grpc.reflection.v1alpha.ServerReflection";
+
+/// File descriptors of the compiled-in services and their dependencies, generated by
+/// `make descriptor-set`
+static DESCRIPTOR_SET: &[u8] = include_bytes!("../gen/descriptor_set.bin");
+
+/// Answers reflection requests, services are the ones actually served while their
+/// descriptors come from the embedded descriptor set
+pub struct Reflection {
+    services: Vec<String>,
+    files: Vec<FileDescriptorProto>,
+}
+
+impl Reflection {
+    pub fn new(services: Vec<String>) -> Self {
+        let files = match FileDescriptorSet::decode(DESCRIPTOR_SET) {
+            Ok(set) => set.file,
+            Err(e) => {
+                log::error!("invalid embedded descriptor set {:?}", e);
+                Vec::new()
+            }
+        };
+        Reflection { services, files }
+    }
+
+    pub fn respond(&self, req: ServerReflectionRequest) -> ServerReflectionResponse {
+        let response = match req.message_request.as_ref() {
+            Some(MessageRequest::ListServices(_)) => {
+                MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                })
+            }
+            Some(MessageRequest::FileByFilename(name)) => {
+                self.file_response(self.files.iter().find(|f| f.name() == name), name)
+            }
+            Some(MessageRequest::FileContainingSymbol(symbol)) => {
+                self.file_response(self.files.iter().find(|f| file_declares(f, symbol)), symbol)
+            }
+            Some(MessageRequest::FileContainingExtension(ext)) => not_found(&format!(
+                "extension {} of {}",
+                ext.extension_number, ext.containing_type
+            )),
+            Some(MessageRequest::AllExtensionNumbersOfType(name)) => {
+                MessageResponse::AllExtensionNumbersResponse(ExtensionNumberResponse {
+                    base_type_name: name.clone(),
+                    extension_number: vec![],
+                })
+            }
+            None => MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: Code::InvalidArgument as i32,
+                error_message: "empty reflection request".to_string(),
+            }),
+        };
+        ServerReflectionResponse {
+            valid_host: req.host.clone(),
+            original_request: Some(req),
+            message_response: Some(response),
+        }
+    }
+
+    /// Respond with `file` followed by its transitive dependencies
+    fn file_response(&self, file: Option<&FileDescriptorProto>, what: &str) -> MessageResponse {
+        let file = match file {
+            Some(file) => file,
+            None => return not_found(what),
+        };
+        let mut seen = HashSet::new();
+        let mut queue = vec![file];
+        let mut encoded = Vec::new();
+        while let Some(file) = queue.pop() {
+            if !seen.insert(file.name()) {
+                continue;
+            }
+            encoded.push(file.encode_to_vec());
+            for dep in file.dependency.iter() {
+                if let Some(dep) = self.files.iter().find(|f| f.name() == dep) {
+                    queue.push(dep);
+                }
+            }
+        }
+        MessageResponse::FileDescriptorResponse(FileDescriptorResponse {
+            file_descriptor_proto: encoded,
+        })
+    }
+}
+
+fn not_found(what: &str) -> MessageResponse {
+    MessageResponse::ErrorResponse(ErrorResponse {
+        error_code: Code::NotFound as i32,
+        error_message: format!("{} not found", what),
+    })
+}
+
+/// Whether `symbol` is a service, method, message or enum declared by `file`
+fn file_declares(file: &FileDescriptorProto, symbol: &str) -> bool {
+    let local = match file.package() {
+        "" => symbol,
+        package => match symbol
+            .strip_prefix(package)
+            .and_then(|s| s.strip_prefix('.'))
+        {
+            Some(local) => local,
+            None => return false,
+        },
+    };
+    file.service.iter().any(|s| {
+        local == s.name()
+            || local
+                .strip_prefix(s.name())
+                .and_then(|m| m.strip_prefix('.'))
+                .is_some_and(|m| s.method.iter().any(|method| method.name() == m))
+    }) || file.enum_type.iter().any(|e| e.name() == local)
+        || file.message_type.iter().any(|m| message_declares(m, local))
+}
+
+fn message_declares(message: &DescriptorProto, local: &str) -> bool {
+    if local == message.name() {
+        return true;
+    }
+    match local
+        .strip_prefix(message.name())
+        .and_then(|s| s.strip_prefix('.'))
+    {
+        Some(nested) => {
+            message.enum_type.iter().any(|e| e.name() == nested)
+                || message
+                    .nested_type
+                    .iter()
+                    .any(|m| message_declares(m, nested))
+        }
+        None => false,
+    }
+}
+
+/// Serve a reflection stream, every request read from `body` is answered as soon as it is
+/// complete since clients wait for the answer before sending the next one
+pub async fn serve(
+    mut body: Body,
+    reflection: Reflection,
+    sender: GrpcStreamSender,
+) -> Result<(), GrpcError> {
+    let mut buffer = BytesMut::new();
+    while let Some(data) = body.data().await {
+        let data = data.map_err(|_| GrpcError::RpcCancelled)?;
+        buffer.extend_from_slice(&data);
+        while let Some(msg) = next_message(&mut buffer)? {
+            let req = ServerReflectionRequest::decode(msg)?;
+            sender.send_message(reflection.respond(req)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Split the first complete length prefixed message off `buffer`
+fn next_message(buffer: &mut BytesMut) -> Result<Option<BytesMut>, GrpcError> {
+    if buffer.len() < 5 {
+        return Ok(None);
+    }
+    if buffer[0] != 0 {
+        return Err(GrpcError::RpcUnimplemented(
+            "compressed messages are not supported".to_string(),
+        ));
+    }
+    let len = u32::from_be_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]) as usize;
+    if buffer.len() < 5 + len {
+        return Ok(None);
+    }
+    buffer.advance(5);
+    Ok(Some(buffer.split_to(len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptor_set_is_not_empty() {
+        let set = FileDescriptorSet::decode(DESCRIPTOR_SET).unwrap();
+        assert!(!set.file.is_empty());
+    }
+
+    #[test]
+    fn messages_keep_their_proto_names() {
+        let set = FileDescriptorSet::decode(DESCRIPTOR_SET).unwrap();
+        let robot = set
+            .file
+            .iter()
+            .find(|f| f.name() == "robot/v1/robot.proto")
+            .unwrap();
+        assert!(robot
+            .message_type
+            .iter()
+            .any(|m| m.name() == "ResourceRPCSubtype"));
+    }
+
+    #[test]
+    fn files_come_with_their_dependencies() {
+        let reflection = Reflection::new(vec![]);
+        let req = ServerReflectionRequest {
+            host: String::new(),
+            message_request: Some(MessageRequest::FileContainingSymbol(
+                "viam.component.motor.v1.MotorService.GoFor".to_string(),
+            )),
+        };
+        let files = match reflection.respond(req).message_response {
+            Some(MessageResponse::FileDescriptorResponse(response)) => response
+                .file_descriptor_proto
+                .iter()
+                .map(|f| FileDescriptorProto::decode(f.as_slice()).unwrap())
+                .map(|f| f.name().to_string())
+                .collect::<Vec<_>>(),
+            other => panic!("unexpected response {:?}", other),
+        };
+        assert_eq!(files[0], "component/motor/v1/motor.proto");
+        assert!(files.iter().any(|f| f == "google/protobuf/struct.proto"));
+    }
+}
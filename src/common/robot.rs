@@ -4,16 +4,28 @@ use std::{
 };
 
 #[cfg(feature = "camera")]
-use crate::camera::Camera;
+use crate::common::camera::Camera;
 
 use crate::{
     common::base::Base,
     common::board::Board,
+    common::discovery::{self, Discover},
+    common::encoder::Encoder,
+    common::error::GrpcError,
+    common::frame::{FrameSystem, Pose},
+    common::gantry::Gantry,
+    common::generic::GenericComponent,
+    common::gripper::Gripper,
     common::motor::Motor,
+    common::movement_sensor::MovementSensor,
+    common::operation::OperationManager,
     common::sensor::Sensor,
+    common::servo::Servo,
+    common::session::SessionManager,
     common::status::Status,
     proto::{
-        common::{self, v1::ResourceName},
+        app::v1::Frame,
+        common::{self, v1::ResourceName, v1::Transform},
         robot,
     },
 };
@@ -26,17 +38,145 @@ pub enum ResourceType {
     Sensor(Arc<Mutex<dyn Sensor>>),
     #[cfg(feature = "camera")]
     Camera(Arc<Mutex<dyn Camera>>),
+    Generic(Arc<Mutex<dyn GenericComponent>>),
+    Servo(Arc<Mutex<dyn Servo>>),
+    MovementSensor(Arc<Mutex<dyn MovementSensor>>),
+    Encoder(Arc<Mutex<dyn Encoder>>),
+    Gripper(Arc<Mutex<dyn Gripper>>),
+    Gantry(Arc<Mutex<dyn Gantry>>),
+}
+impl ResourceType {
+    /// Subtype of the resource (e.g. "motor") and the service serving its API, `None` for
+    /// resources whose API isn't served
+    pub fn rpc_subtype(&self) -> Option<(&'static str, &'static str)> {
+        Some(match self {
+            ResourceType::Motor(_) => ("motor", "viam.component.motor.v1.MotorService"),
+            ResourceType::Board(_) => ("board", "viam.component.board.v1.BoardService"),
+            ResourceType::Base(_) => ("base", "viam.component.base.v1.BaseService"),
+            ResourceType::Sensor(_) => ("sensor", "viam.component.sensor.v1.SensorService"),
+            #[cfg(feature = "camera")]
+            ResourceType::Camera(_) => ("camera", "viam.component.camera.v1.CameraService"),
+            ResourceType::Generic(_) => ("generic", "viam.component.generic.v1.GenericService"),
+            ResourceType::Servo(_) => ("servo", "viam.component.servo.v1.ServoService"),
+            ResourceType::MovementSensor(_) => (
+                "movement_sensor",
+                "viam.component.movementsensor.v1.MovementSensorService",
+            ),
+            // there is no generated code for the encoder API, encoders are only used by other
+            // components and reported in the status of the robot
+            ResourceType::Encoder(_) => return None,
+            ResourceType::Gripper(_) => ("gripper", "viam.component.gripper.v1.GripperService"),
+            ResourceType::Gantry(_) => ("gantry", "viam.component.gantry.v1.GantryService"),
+        })
+    }
 }
 pub type Resource = ResourceType;
 pub type ResourceMap = HashMap<ResourceName, Resource>;
 
 pub struct LocalRobot {
     resources: ResourceMap,
+    sessions: SessionManager,
+    operations: OperationManager,
+    /// frames of the components placed in the frame system, by component name
+    frames: HashMap<String, Frame>,
+    discoveries: Vec<Box<dyn Discover>>,
 }
 
 impl LocalRobot {
     pub fn new(res: ResourceMap) -> Self {
-        LocalRobot { resources: res }
+        LocalRobot {
+            resources: res,
+            sessions: SessionManager::new(),
+            operations: OperationManager::new(),
+            frames: HashMap::new(),
+            discoveries: Vec::new(),
+        }
+    }
+    /// Register a hook run by DiscoverComponents
+    pub fn add_discovery(&mut self, hook: Box<dyn Discover>) {
+        self.discoveries.push(hook)
+    }
+    pub fn discover_components(
+        &mut self,
+        queries: &[robot::v1::DiscoveryQuery],
+    ) -> Vec<robot::v1::Discovery> {
+        discovery::discover_components(&mut self.discoveries, queries)
+    }
+    /// Place the component `name` in the frame system
+    pub fn set_frame(&mut self, name: &str, frame: Frame) -> anyhow::Result<()> {
+        if !self.resources.keys().any(|r| r.name == name) {
+            anyhow::bail!("no component named {}", name);
+        }
+        self.frames.insert(name.to_owned(), frame);
+        Ok(())
+    }
+    /// Frame system made of the frames of the components and of `supplemental` transforms
+    pub fn frame_system(&self, supplemental: &[Transform]) -> Result<FrameSystem, GrpcError> {
+        let mut fs = FrameSystem::new();
+        for (name, frame) in self.frames.iter() {
+            fs.add_frame(name, &frame.parent, Pose::from_frame(frame))?;
+        }
+        for transform in supplemental {
+            fs.add_transform(transform)?;
+        }
+        Ok(fs)
+    }
+    pub fn sessions(&self) -> &SessionManager {
+        &self.sessions
+    }
+    pub fn sessions_mut(&mut self) -> &mut SessionManager {
+        &mut self.sessions
+    }
+    pub fn operations(&self) -> &OperationManager {
+        &self.operations
+    }
+    pub fn operations_mut(&mut self) -> &mut OperationManager {
+        &mut self.operations
+    }
+    /// Stop every resource that can move, `extra` holds the parameters to stop some of them
+    /// with. Every resource is stopped even if stopping one of them fails.
+    pub fn stop_all(&mut self, extra: &[robot::v1::StopExtraParameters]) -> anyhow::Result<()> {
+        let mut failed = Vec::new();
+        for (name, res) in self.resources.iter() {
+            let params = extra
+                .iter()
+                .find(|e| e.name.as_ref() == Some(name))
+                .and_then(|e| e.params.as_ref());
+            let ret = match res {
+                ResourceType::Motor(m) => m.lock().unwrap().stop_with_extra(params),
+                ResourceType::Base(b) => b.lock().unwrap().stop_with_extra(params),
+                ResourceType::Servo(s) => s.lock().unwrap().stop_with_extra(params),
+                ResourceType::Gripper(g) => g.lock().unwrap().stop_with_extra(params),
+                ResourceType::Gantry(g) => g.lock().unwrap().stop_with_extra(params),
+                _ => continue,
+            };
+            if let Err(e) = ret {
+                error!("couldn't stop {}: {:?}", name.name, e);
+                failed.push(name.name.as_str());
+            }
+        }
+        if !failed.is_empty() {
+            anyhow::bail!("failed to stop {}", failed.join(", "));
+        }
+        Ok(())
+    }
+    /// Stop the motors, bases, servos, grippers and gantries last actuated by a session whose
+    /// heartbeat lapsed
+    pub fn stop_expired_sessions(&mut self) {
+        for name in self.sessions.expire() {
+            let ret = match self.resources.get(&name) {
+                Some(ResourceType::Motor(m)) => m.lock().unwrap().stop(),
+                Some(ResourceType::Base(b)) => b.lock().unwrap().stop(),
+                Some(ResourceType::Servo(s)) => s.lock().unwrap().stop(),
+                Some(ResourceType::Gripper(g)) => g.lock().unwrap().stop(),
+                Some(ResourceType::Gantry(g)) => g.lock().unwrap().stop(),
+                _ => continue,
+            };
+            match ret {
+                Ok(()) => info!("session expired, stopped {}", name.name),
+                Err(e) => error!("session expired, couldn't stop {}: {:?}", name.name, e),
+            }
+        }
     }
     pub fn get_status(
         &self,
@@ -74,6 +214,42 @@ impl LocalRobot {
                             status,
                         });
                     }
+                    ResourceType::Servo(s) => {
+                        let status = s.get_status()?;
+                        vec.push(robot::v1::Status {
+                            name: Some(name.clone()),
+                            status,
+                        });
+                    }
+                    ResourceType::MovementSensor(s) => {
+                        let status = s.get_status()?;
+                        vec.push(robot::v1::Status {
+                            name: Some(name.clone()),
+                            status,
+                        });
+                    }
+                    ResourceType::Encoder(e) => {
+                        let status = e.get_status()?;
+                        vec.push(robot::v1::Status {
+                            name: Some(name.clone()),
+                            status,
+                        });
+                    }
+                    ResourceType::Gripper(g) => {
+                        let status = g.get_status()?;
+                        vec.push(robot::v1::Status {
+                            name: Some(name.clone()),
+                            status,
+                        });
+                    }
+                    ResourceType::Gantry(g) => {
+                        let status = g.get_status()?;
+                        vec.push(robot::v1::Status {
+                            name: Some(name.clone()),
+                            status,
+                        });
+                    }
+                    ResourceType::Generic(_) => continue,
                     #[cfg(feature = "camera")]
                     _ => continue,
                 };
@@ -114,6 +290,42 @@ impl LocalRobot {
                                 status,
                             });
                         }
+                        ResourceType::Servo(s) => {
+                            let status = s.get_status()?;
+                            vec.push(robot::v1::Status {
+                                name: Some(name),
+                                status,
+                            });
+                        }
+                        ResourceType::MovementSensor(s) => {
+                            let status = s.get_status()?;
+                            vec.push(robot::v1::Status {
+                                name: Some(name),
+                                status,
+                            });
+                        }
+                        ResourceType::Encoder(e) => {
+                            let status = e.get_status()?;
+                            vec.push(robot::v1::Status {
+                                name: Some(name),
+                                status,
+                            });
+                        }
+                        ResourceType::Gripper(g) => {
+                            let status = g.get_status()?;
+                            vec.push(robot::v1::Status {
+                                name: Some(name),
+                                status,
+                            });
+                        }
+                        ResourceType::Gantry(g) => {
+                            let status = g.get_status()?;
+                            vec.push(robot::v1::Status {
+                                name: Some(name),
+                                status,
+                            });
+                        }
+                        ResourceType::Generic(_) => continue,
                         #[cfg(feature = "camera")]
                         _ => continue,
                     };
@@ -130,6 +342,60 @@ impl LocalRobot {
         }
         Ok(name)
     }
+    /// The component APIs used by the resources of the robot
+    pub fn get_rpc_subtypes(&self) -> Vec<robot::v1::ResourceRpcSubtype> {
+        let mut subtypes: Vec<robot::v1::ResourceRpcSubtype> = Vec::new();
+        for res in self.resources.values() {
+            let (subtype, service) = match res.rpc_subtype() {
+                Some(rpc_subtype) => rpc_subtype,
+                None => continue,
+            };
+            if subtypes.iter().any(|s| s.proto_service == service) {
+                continue;
+            }
+            subtypes.push(robot::v1::ResourceRpcSubtype {
+                subtype: Some(ResourceName {
+                    namespace: "rdk".to_string(),
+                    r#type: "component".to_string(),
+                    subtype: subtype.to_string(),
+                    name: "".to_string(),
+                }),
+                proto_service: service.to_string(),
+            });
+        }
+        subtypes
+    }
+    /// Send `command` to the resource `name` of `subtype`
+    pub fn do_command(
+        &self,
+        subtype: &str,
+        name: String,
+        command: prost_types::Struct,
+    ) -> anyhow::Result<prost_types::Struct> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: subtype.to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::Motor(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Board(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Base(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Sensor(r)) => r.lock().unwrap().do_command(command),
+            #[cfg(feature = "camera")]
+            Some(ResourceType::Camera(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Generic(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Servo(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::MovementSensor(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Encoder(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Gripper(r)) => r.lock().unwrap().do_command(command),
+            Some(ResourceType::Gantry(r)) => r.lock().unwrap().do_command(command),
+            None => {
+                Err(GrpcError::RpcNotFound(format!("{} {} not found", subtype, name.name)).into())
+            }
+        }
+    }
     pub fn get_motor_by_name(&self, name: String) -> Option<Arc<Mutex<dyn Motor>>> {
         let name = ResourceName {
             namespace: "rdk".to_string(),
@@ -196,4 +462,175 @@ impl LocalRobot {
             None => None,
         }
     }
+    pub fn get_servo_by_name(&self, name: String) -> Option<Arc<Mutex<dyn Servo>>> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "servo".to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::Servo(r)) => Some(r.clone()),
+            Some(_) => None,
+            None => None,
+        }
+    }
+    pub fn get_movement_sensor_by_name(
+        &self,
+        name: String,
+    ) -> Option<Arc<Mutex<dyn MovementSensor>>> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "movement_sensor".to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::MovementSensor(r)) => Some(r.clone()),
+            Some(_) => None,
+            None => None,
+        }
+    }
+    pub fn get_encoder_by_name(&self, name: String) -> Option<Arc<Mutex<dyn Encoder>>> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "encoder".to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::Encoder(r)) => Some(r.clone()),
+            Some(_) => None,
+            None => None,
+        }
+    }
+    pub fn get_gripper_by_name(&self, name: String) -> Option<Arc<Mutex<dyn Gripper>>> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "gripper".to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::Gripper(r)) => Some(r.clone()),
+            Some(_) => None,
+            None => None,
+        }
+    }
+    pub fn get_gantry_by_name(&self, name: String) -> Option<Arc<Mutex<dyn Gantry>>> {
+        let name = ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "gantry".to_string(),
+            name,
+        };
+        match self.resources.get(&name) {
+            Some(ResourceType::Gantry(r)) => Some(r.clone()),
+            Some(_) => None,
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::actuator::Actuator;
+    use crate::common::base::FakeBase;
+    use crate::common::generic::DoCommand;
+    use crate::common::motor::FakeMotor;
+    use crate::proto::common::v1::Vector3;
+    use std::time::Duration;
+
+    /// Base which can't be stopped
+    struct JammedBase;
+
+    impl Base for JammedBase {
+        fn set_power(&mut self, _lin: &Vector3, _ang: &Vector3) -> anyhow::Result<()> {
+            Ok(())
+        }
+        fn move_straight(
+            &mut self,
+            _distance_mm: i64,
+            _mm_per_sec: f64,
+        ) -> anyhow::Result<Option<Duration>> {
+            Ok(None)
+        }
+        fn spin(
+            &mut self,
+            _angle_deg: f64,
+            _degs_per_sec: f64,
+        ) -> anyhow::Result<Option<Duration>> {
+            Ok(None)
+        }
+        fn set_velocity(&mut self, _lin: &Vector3, _ang: &Vector3) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Actuator for JammedBase {
+        fn is_moving(&self) -> anyhow::Result<bool> {
+            Ok(true)
+        }
+        fn stop(&mut self) -> anyhow::Result<()> {
+            anyhow::bail!("jammed")
+        }
+    }
+
+    impl Status for JammedBase {
+        fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+            Ok(None)
+        }
+    }
+
+    impl DoCommand for JammedBase {}
+
+    fn name(subtype: &str, name: &str) -> ResourceName {
+        ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: subtype.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn stop_all_stops_every_actuator_even_when_one_fails() {
+        let motor = Arc::new(Mutex::new(FakeMotor::new()));
+        let base = Arc::new(Mutex::new(FakeBase::new()));
+        motor.lock().unwrap().set_power(0.5).unwrap();
+        let forward = Vector3 {
+            x: 0.0,
+            y: 1.0,
+            z: 0.0,
+        };
+        base.lock()
+            .unwrap()
+            .set_power(&forward, &Vector3::default())
+            .unwrap();
+        let mut resources = ResourceMap::new();
+        resources.insert(name("motor", "m1"), ResourceType::Motor(motor.clone()));
+        resources.insert(name("base", "b1"), ResourceType::Base(base.clone()));
+        resources.insert(
+            name("base", "jammed"),
+            ResourceType::Base(Arc::new(Mutex::new(JammedBase))),
+        );
+        let mut robot = LocalRobot::new(resources);
+
+        let err = robot.stop_all(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "failed to stop jammed");
+        assert!(!motor.lock().unwrap().is_moving().unwrap());
+        assert!(!base.lock().unwrap().is_moving().unwrap());
+    }
+
+    #[test]
+    fn stop_all_succeeds_when_everything_stopped() {
+        let motor = Arc::new(Mutex::new(FakeMotor::new()));
+        motor.lock().unwrap().set_power(-1.0).unwrap();
+        let mut resources = ResourceMap::new();
+        resources.insert(name("motor", "m1"), ResourceType::Motor(motor.clone()));
+        let mut robot = LocalRobot::new(resources);
+        robot.stop_all(&[]).unwrap();
+        assert!(!motor.lock().unwrap().is_moving().unwrap());
+    }
 }
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::common::generic::DoCommand;
 use crate::common::status::Status;
 use std::collections::BTreeMap;
 use std::collections::HashMap;
@@ -9,7 +10,7 @@ pub type GenericReadingsResult =
 
 pub type TypedReadingsResult<T> = ::std::collections::HashMap<String, T>;
 
-pub trait Sensor: Status {
+pub trait Sensor: Status + DoCommand {
     fn get_generic_readings(&self) -> anyhow::Result<GenericReadingsResult>;
 }
 
@@ -66,6 +67,8 @@ impl SensorT<f64> for FakeSensor {
     }
 }
 
+impl DoCommand for FakeSensor {}
+
 impl Status for FakeSensor {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         Ok(Some(prost_types::Struct {
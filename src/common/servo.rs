@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::status::Status;
+use log::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+pub trait Servo: Status + Actuator + DoCommand {
+    /// Move to `angle_deg`, between 0 and the max angle of the servo
+    fn move_to(&mut self, angle_deg: u32) -> anyhow::Result<()>;
+    /// Angle the servo was last moved to, hobby servos can't tell where they actually are
+    fn get_position(&self) -> anyhow::Result<u32>;
+}
+
+impl<L> Servo for Mutex<L>
+where
+    L: ?Sized + Servo,
+{
+    fn move_to(&mut self, angle_deg: u32) -> anyhow::Result<()> {
+        self.get_mut().unwrap().move_to(angle_deg)
+    }
+    fn get_position(&self) -> anyhow::Result<u32> {
+        self.lock().unwrap().get_position()
+    }
+}
+
+impl<A> Servo for Arc<Mutex<A>>
+where
+    A: ?Sized + Servo,
+{
+    fn move_to(&mut self, angle_deg: u32) -> anyhow::Result<()> {
+        self.lock().unwrap().move_to(angle_deg)
+    }
+    fn get_position(&self) -> anyhow::Result<u32> {
+        self.lock().unwrap().get_position()
+    }
+}
+
+/// Pulses a servo expects: a pulse of `min_pulse_us` moves it to 0 degree, one of
+/// `max_pulse_us` to `max_angle_deg`, repeated `frequency_hz` times a second
+#[derive(Clone, Copy, Debug)]
+pub struct ServoPulseConfig {
+    min_pulse_us: u32,
+    max_pulse_us: u32,
+    max_angle_deg: u32,
+    frequency_hz: u32,
+}
+
+impl ServoPulseConfig {
+    pub fn new(
+        min_pulse_us: u32,
+        max_pulse_us: u32,
+        max_angle_deg: u32,
+        frequency_hz: u32,
+    ) -> anyhow::Result<Self> {
+        if min_pulse_us >= max_pulse_us {
+            return Err(GrpcError::RpcFailedPrecondition(format!(
+                "min pulse {}us should be shorter than max pulse {}us",
+                min_pulse_us, max_pulse_us
+            ))
+            .into());
+        }
+        if max_angle_deg == 0 || frequency_hz == 0 {
+            return Err(GrpcError::RpcFailedPrecondition(
+                "max angle and frequency should be strictly positive".to_string(),
+            )
+            .into());
+        }
+        if max_pulse_us as u64 * frequency_hz as u64 > 1_000_000 {
+            return Err(GrpcError::RpcFailedPrecondition(format!(
+                "max pulse {}us is longer than the period at {}Hz",
+                max_pulse_us, frequency_hz
+            ))
+            .into());
+        }
+        Ok(ServoPulseConfig {
+            min_pulse_us,
+            max_pulse_us,
+            max_angle_deg,
+            frequency_hz,
+        })
+    }
+    pub fn max_angle_deg(&self) -> u32 {
+        self.max_angle_deg
+    }
+    pub fn frequency_hz(&self) -> u32 {
+        self.frequency_hz
+    }
+    /// Width of the pulse moving the servo to `angle_deg`
+    pub fn pulse_us(&self, angle_deg: u32) -> anyhow::Result<u32> {
+        if angle_deg > self.max_angle_deg {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "angle {} is above the max angle {}",
+                angle_deg, self.max_angle_deg
+            ))
+            .into());
+        }
+        let range = (self.max_pulse_us - self.min_pulse_us) as u64;
+        Ok(self.min_pulse_us + (range * angle_deg as u64 / self.max_angle_deg as u64) as u32)
+    }
+    /// Duty (out of `max_duty`) of the PWM signal moving the servo to `angle_deg`
+    pub fn duty(&self, angle_deg: u32, max_duty: u32) -> anyhow::Result<u32> {
+        let pulse_us = self.pulse_us(angle_deg)? as u64;
+        Ok((pulse_us * self.frequency_hz as u64 * max_duty as u64 / 1_000_000) as u32)
+    }
+}
+
+/// Most hobby servos turn by 180 degrees for pulses between 0.5ms and 2.5ms sent at 50Hz
+impl Default for ServoPulseConfig {
+    fn default() -> Self {
+        ServoPulseConfig {
+            min_pulse_us: 500,
+            max_pulse_us: 2500,
+            max_angle_deg: 180,
+            frequency_hz: 50,
+        }
+    }
+}
+
+/// Status of a servo as described by `viam.component.servo.v1.Status`
+pub(crate) fn servo_status(position_deg: u32, is_moving: bool) -> prost_types::Struct {
+    prost_types::Struct {
+        fields: BTreeMap::from([
+            (
+                "position_deg".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::NumberValue(position_deg as f64)),
+                },
+            ),
+            (
+                "is_moving".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::BoolValue(is_moving)),
+                },
+            ),
+        ]),
+    }
+}
+
+pub struct FakeServo {
+    position: u32,
+}
+
+impl FakeServo {
+    pub fn new() -> Self {
+        FakeServo { position: 0 }
+    }
+}
+
+impl Default for FakeServo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Servo for FakeServo {
+    fn move_to(&mut self, angle_deg: u32) -> anyhow::Result<()> {
+        info!("moving servo to {}", angle_deg);
+        self.position = angle_deg;
+        Ok(())
+    }
+    fn get_position(&self) -> anyhow::Result<u32> {
+        Ok(self.position)
+    }
+}
+
+impl Actuator for FakeServo {
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        info!("stopping servo");
+        Ok(())
+    }
+}
+
+impl DoCommand for FakeServo {}
+
+impl Status for FakeServo {
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(servo_status(self.position, false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulses_span_the_angles() {
+        let config = ServoPulseConfig::default();
+        assert_eq!(config.pulse_us(0).unwrap(), 500);
+        assert_eq!(config.pulse_us(90).unwrap(), 1500);
+        assert_eq!(config.pulse_us(180).unwrap(), 2500);
+        assert!(config.pulse_us(181).is_err());
+    }
+
+    #[test]
+    fn duty_is_the_pulse_over_the_period() {
+        let config = ServoPulseConfig::default();
+        // 1.5ms out of a 20ms period
+        assert_eq!(config.duty(90, 20000).unwrap(), 1500);
+        assert_eq!(config.duty(180, 8192).unwrap(), 1024);
+    }
+
+    #[test]
+    fn pulse_config_is_checked() {
+        assert!(ServoPulseConfig::new(2500, 500, 180, 50).is_err());
+        assert!(ServoPulseConfig::new(500, 2500, 0, 50).is_err());
+        assert!(ServoPulseConfig::new(500, 2500, 180, 0).is_err());
+        // a 2.5ms pulse doesn't fit in the 2ms period at 500Hz
+        assert!(ServoPulseConfig::new(500, 2500, 180, 500).is_err());
+        assert!(ServoPulseConfig::new(1000, 2000, 270, 330).is_ok());
+    }
+
+    #[test]
+    fn fake_servo_reports_where_it_was_moved() {
+        let mut servo = FakeServo::new();
+        servo.move_to(45).unwrap();
+        assert_eq!(servo.get_position().unwrap(), 45);
+        assert!(!servo.is_moving().unwrap());
+        assert_eq!(servo.get_status().unwrap(), Some(servo_status(45, false)));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{common::error::GrpcError, common::robot::LocalRobot, proto::common::v1::ResourceName};
+
+/// Metadata (header) key carrying the id of the session an RPC is part of
+pub static SESSION_METADATA_KEY: &str = "viam-sid";
+
+/// Default time a client has to send a heartbeat before its session expires
+static HEARTBEAT_WINDOW: Duration = Duration::from_secs(2);
+
+/// How often `safety_monitor` looks for expired sessions
+static SESSION_CHECK_PERIOD: Duration = Duration::from_millis(100);
+
+/// Keeps track of client sessions and of the resources they actuate. When a client stops
+/// sending heartbeats its session expires, resources it was the last one to actuate are then
+/// returned by `expire` so they can be stopped.
+pub struct SessionManager {
+    /// time of the last heartbeat of every live session
+    sessions: HashMap<String, Instant>,
+    /// session which last actuated a resource
+    owners: HashMap<ResourceName, String>,
+    heartbeat_window: Duration,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        SessionManager {
+            sessions: HashMap::new(),
+            owners: HashMap::new(),
+            heartbeat_window: HEARTBEAT_WINDOW,
+        }
+    }
+
+    pub fn heartbeat_window(&self) -> Duration {
+        self.heartbeat_window
+    }
+
+    pub fn set_heartbeat_window(&mut self, window: Duration) {
+        self.heartbeat_window = window
+    }
+
+    /// Start a session, or resume the session `resume` if it is still alive. Returns the id of
+    /// the session.
+    pub fn start(&mut self, resume: &str) -> Result<String, GrpcError> {
+        let now = Instant::now();
+        if let Some(last) = self.sessions.get_mut(resume) {
+            *last = now;
+            return Ok(resume.to_owned());
+        }
+        let id = random_uuid()?;
+        self.sessions.insert(id.clone(), now);
+        Ok(id)
+    }
+
+    /// Keep the session `id` alive, fails with SESSION_EXPIRED if it doesn't exist (anymore)
+    pub fn heartbeat(&mut self, id: &str) -> Result<(), GrpcError> {
+        match self.sessions.get_mut(id) {
+            Some(last) => {
+                *last = Instant::now();
+                Ok(())
+            }
+            None => Err(GrpcError::RpcInvalidArgument("SESSION_EXPIRED".to_string())),
+        }
+    }
+
+    /// Ids of the live sessions
+    pub fn sessions(&self) -> Vec<String> {
+        self.sessions.keys().cloned().collect()
+    }
+
+    /// Record that the session `id` actuated the resource `name`
+    pub fn associate(&mut self, id: &str, name: ResourceName) {
+        if self.sessions.contains_key(id) {
+            self.owners.insert(name, id.to_owned());
+        }
+    }
+
+    /// Remove the sessions which didn't send a heartbeat within the heartbeat window, returns
+    /// the resources they were the last to actuate
+    pub fn expire(&mut self) -> Vec<ResourceName> {
+        let window = self.heartbeat_window;
+        let now = Instant::now();
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, last| now.saturating_duration_since(*last) <= window);
+        if self.sessions.len() == before {
+            return Vec::new();
+        }
+        let sessions = &self.sessions;
+        let mut expired = Vec::new();
+        self.owners.retain(|name, owner| {
+            if sessions.contains_key(owner) {
+                return true;
+            }
+            expired.push(name.clone());
+            false
+        });
+        expired
+    }
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Generate a random version 4 UUID, ids have to be unguessable since knowing the id of a
+/// session is enough to keep it alive
+pub(crate) fn random_uuid() -> Result<String, GrpcError> {
+    let mut bytes = [0_u8; 16];
+    fill_random(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+#[cfg(feature = "esp32")]
+fn fill_random(buf: &mut [u8]) -> Result<(), GrpcError> {
+    // hardware RNG, cryptographically secure while the radio is on, which it is when serving
+    unsafe { esp_idf_sys::esp_fill_random(buf.as_mut_ptr() as *mut _, buf.len() as _) };
+    Ok(())
+}
+
+#[cfg(not(feature = "esp32"))]
+fn fill_random(buf: &mut [u8]) -> Result<(), GrpcError> {
+    getrandom::getrandom(buf)
+        .map_err(|e| GrpcError::RpcInternal(format!("cannot generate an id: {}", e)))
+}
+
+/// Periodically stop the resources of expired sessions, runs forever and is meant to be spawned
+/// on the executor serving RPCs
+pub async fn safety_monitor(robot: Arc<Mutex<LocalRobot>>) {
+    loop {
+        smol::Timer::after(SESSION_CHECK_PERIOD).await;
+        robot.lock().unwrap().stop_expired_sessions();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    fn resource(name: &str) -> ResourceName {
+        ResourceName {
+            namespace: "rdk".to_string(),
+            r#type: "component".to_string(),
+            subtype: "motor".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn manager(window: Duration) -> SessionManager {
+        let mut sessions = SessionManager::new();
+        sessions.set_heartbeat_window(window);
+        sessions
+    }
+
+    #[test]
+    fn ids_are_random_uuids() {
+        let mut sessions = SessionManager::new();
+        let first = sessions.start("").unwrap();
+        let second = sessions.start("").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first.len(), 36);
+        assert_eq!(first.chars().nth(14), Some('4'));
+        assert!(matches!(first.chars().nth(19), Some('8' | '9' | 'a' | 'b')));
+    }
+
+    #[test]
+    fn live_sessions_can_be_resumed() {
+        let mut sessions = SessionManager::new();
+        let id = sessions.start("").unwrap();
+        assert_eq!(sessions.start(&id).unwrap(), id);
+        assert_ne!(sessions.start("unknown").unwrap(), "unknown");
+    }
+
+    #[test]
+    fn heartbeat_keeps_the_session_alive() {
+        let mut sessions = manager(Duration::from_millis(100));
+        let id = sessions.start("").unwrap();
+        sessions.associate(&id, resource("motor"));
+        for _ in 0..3 {
+            sleep(Duration::from_millis(60));
+            sessions.heartbeat(&id).unwrap();
+        }
+        assert!(sessions.expire().is_empty());
+        assert_eq!(sessions.sessions(), vec![id]);
+    }
+
+    #[test]
+    fn heartbeat_of_an_unknown_session_fails() {
+        let mut sessions = SessionManager::new();
+        assert_eq!(
+            sessions.heartbeat("unknown"),
+            Err(GrpcError::RpcInvalidArgument("SESSION_EXPIRED".to_string()))
+        );
+    }
+
+    #[test]
+    fn expire_returns_the_resources_of_the_expired_sessions() {
+        let mut sessions = manager(Duration::from_millis(50));
+        let expired = sessions.start("").unwrap();
+        let alive = sessions.start("").unwrap();
+        sessions.associate(&expired, resource("left"));
+        sessions.associate(&alive, resource("right"));
+        sleep(Duration::from_millis(100));
+        sessions.heartbeat(&alive).unwrap();
+
+        assert_eq!(sessions.expire(), vec![resource("left")]);
+        assert_eq!(sessions.sessions(), vec![alive.clone()]);
+        assert!(sessions.heartbeat(&expired).is_err());
+        // the resources are only returned once
+        assert!(sessions.expire().is_empty());
+        sessions.heartbeat(&alive).unwrap();
+    }
+
+    #[test]
+    fn expire_keeps_the_resources_taken_over_by_a_live_session() {
+        let mut sessions = manager(Duration::from_millis(50));
+        let expired = sessions.start("").unwrap();
+        let alive = sessions.start("").unwrap();
+        sessions.associate(&expired, resource("motor"));
+        sessions.associate(&alive, resource("motor"));
+        sleep(Duration::from_millis(100));
+        sessions.heartbeat(&alive).unwrap();
+
+        assert!(sessions.expire().is_empty());
+        assert_eq!(sessions.sessions(), vec![alive]);
+    }
+
+    #[test]
+    fn expired_sessions_cannot_actuate() {
+        let mut sessions = manager(Duration::from_millis(50));
+        let id = sessions.start("").unwrap();
+        sleep(Duration::from_millis(100));
+        assert!(sessions.expire().is_empty());
+        sessions.associate(&id, resource("motor"));
+        assert!(sessions.expire().is_empty());
+        assert_ne!(sessions.start(&id).unwrap(), id);
+    }
+}
@@ -1,12 +1,17 @@
 #![allow(dead_code)]
-use crate::common::base::Base;
+use crate::common::actuator::Actuator;
+use crate::common::base::{Base, WheeledBaseGeometry};
+use crate::common::generic::DoCommand;
 use crate::common::motor::Motor;
 use crate::common::status::Status;
 use crate::proto::common::v1::Vector3;
 use std::collections::BTreeMap;
+use std::time::Duration;
+
 pub struct Esp32WheelBase<ML, MR> {
     motor_right: MR,
     motor_left: ML,
+    geometry: WheeledBaseGeometry,
 }
 
 impl<ML, MR> Esp32WheelBase<ML, MR>
@@ -14,11 +19,44 @@ where
     ML: Motor,
     MR: Motor,
 {
-    pub fn new(motor_left: ML, motor_right: MR) -> Self {
+    pub fn new(motor_left: ML, motor_right: MR, geometry: WheeledBaseGeometry) -> Self {
         Esp32WheelBase {
             motor_right,
             motor_left,
+            geometry,
+        }
+    }
+    /// Spin each wheel at its rpm for its number of revolutions, motors reporting their
+    /// position are stopped by `poll_moving` once they got there, the others need to be stopped
+    /// after the longest duration
+    fn go_for(&mut self, left: (f64, f64), right: (f64, f64)) -> anyhow::Result<Option<Duration>> {
+        // zero revolutions would spin the motors until stopped
+        if left.1 == 0.0 && right.1 == 0.0 {
+            self.stop()?;
+            return Ok(None);
         }
+        let dur_left = self.motor_left.go_for(left.0, left.1)?;
+        let dur_right = self.motor_right.go_for(right.0, right.1)?;
+        Ok(dur_left.max(dur_right))
+    }
+    /// Spin a motor at `rpm` until stopped, or stop it
+    fn spin_motor<M: Motor>(motor: &mut M, rpm: f64) -> anyhow::Result<()> {
+        if rpm == 0.0 {
+            return motor.stop();
+        }
+        motor.go_for(rpm, 0.0)?;
+        Ok(())
+    }
+    /// Whether a wheel still moves, a wheel which reached its number of revolutions is stopped
+    /// right away instead of overshooting while the other one is still going
+    fn poll_wheel<M: Motor>(motor: &mut M) -> anyhow::Result<bool> {
+        if motor.is_moving()? {
+            return Ok(true);
+        }
+        if motor.is_powered()?.0 {
+            motor.stop()?;
+        }
+        Ok(false)
     }
     #[allow(clippy::only_used_in_recursion)]
     fn differential_drive(&self, forward: f64, left: f64) -> (f64, f64) {
@@ -34,6 +72,13 @@ where
         (l.max(-1.0).min(1.0), r.max(-1.0).min(1.0))
     }
 }
+impl<ML, MR> DoCommand for Esp32WheelBase<ML, MR>
+where
+    ML: Motor,
+    MR: Motor,
+{
+}
+
 impl<ML, MR> Status for Esp32WheelBase<ML, MR>
 where
     ML: Motor,
@@ -44,7 +89,7 @@ where
         bt.insert(
             "is_moving".to_string(),
             prost_types::Value {
-                kind: Some(prost_types::value::Kind::BoolValue(false)),
+                kind: Some(prost_types::value::Kind::BoolValue(self.is_moving()?)),
             },
         );
         Ok(Some(prost_types::Struct { fields: bt }))
@@ -62,9 +107,42 @@ where
         self.motor_right.set_power(r)?;
         Ok(())
     }
+    fn move_straight(
+        &mut self,
+        distance_mm: i64,
+        mm_per_sec: f64,
+    ) -> anyhow::Result<Option<Duration>> {
+        let (rpm, revolutions) = self.geometry.straight(distance_mm, mm_per_sec)?;
+        self.go_for((rpm, revolutions), (rpm, revolutions))
+    }
+    fn spin(&mut self, angle_deg: f64, degs_per_sec: f64) -> anyhow::Result<Option<Duration>> {
+        let (left, right) = self.geometry.spin(angle_deg, degs_per_sec)?;
+        self.go_for(left, right)
+    }
+    fn set_velocity(&mut self, lin: &Vector3, ang: &Vector3) -> anyhow::Result<()> {
+        let (left, right) = self.geometry.velocity(lin.y, ang.z);
+        Self::spin_motor(&mut self.motor_left, left)?;
+        Self::spin_motor(&mut self.motor_right, right)
+    }
+}
+
+impl<ML, MR> Actuator for Esp32WheelBase<ML, MR>
+where
+    ML: Motor,
+    MR: Motor,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(self.motor_left.is_moving()? || self.motor_right.is_moving()?)
+    }
+    fn poll_moving(&mut self) -> anyhow::Result<bool> {
+        // both wheels are polled, the one done first has to be stopped
+        let left = Self::poll_wheel(&mut self.motor_left)?;
+        let right = Self::poll_wheel(&mut self.motor_right)?;
+        Ok(left || right)
+    }
     fn stop(&mut self) -> anyhow::Result<()> {
-        self.motor_left.set_power(0.0)?;
-        self.motor_right.set_power(0.0)?;
+        self.motor_left.stop()?;
+        self.motor_right.stop()?;
         Ok(())
     }
 }
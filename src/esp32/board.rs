@@ -1,53 +1,171 @@
 #![allow(dead_code)]
-use super::pin::PinExt;
+use super::pin::{Esp32GpioPin, PinMode};
+use super::pwm::Esp32PwmPin;
 use crate::common::analog::AnalogReader;
-use crate::common::board::Board;
+use crate::common::board::{digital_interrupts_status, parse_pin, Board, DigitalInterrupt};
+use crate::common::discovery::{Discover, I2cProbe};
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
 use crate::common::status::Status;
 use crate::proto::common;
 use core::cell::RefCell;
-use embedded_hal::digital::v2::StatefulOutputPin;
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::Pull;
+use esp_idf_hal::i2c::I2cDriver;
 use std::collections::{BTreeMap, HashMap};
 use std::rc::Rc;
-pub struct EspBoard<Pins> {
-    pins: Vec<Pins>,
+use std::time::Duration;
+
+/// GPIOs wired to ADC1, ADC2 can't be used while WiFi is running
+static ADC1_PINS: [i32; 8] = [32, 33, 34, 35, 36, 37, 38, 39];
+
+/// How long a device has to acknowledge its address during an I2C scan
+static I2C_PROBE_TIMEOUT: Duration = Duration::from_millis(10);
+
+pub struct EspBoard {
+    pins: Vec<Esp32GpioPin>,
     analogs: Vec<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>>,
+    interrupts: Vec<Rc<RefCell<dyn DigitalInterrupt>>>,
+    pwms: Vec<Esp32PwmPin>,
 }
 
-impl<Pins> EspBoard<Pins>
-where
-    Pins: StatefulOutputPin + PinExt,
-{
+impl EspBoard {
     pub fn new(
-        pins: Vec<Pins>,
+        pins: Vec<Esp32GpioPin>,
         analogs: Vec<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>>,
+        pwms: Vec<Esp32PwmPin>,
     ) -> Self {
-        EspBoard { pins, analogs }
+        EspBoard {
+            pins,
+            analogs,
+            interrupts: Vec::new(),
+            pwms,
+        }
+    }
+    pub fn add_digital_interrupt(&mut self, interrupt: Rc<RefCell<dyn DigitalInterrupt>>) {
+        self.interrupts.push(interrupt)
+    }
+    /// Switch `pin` between input and output while the board is running
+    pub fn set_pin_mode(&mut self, pin: i32, mode: PinMode, pull: Pull) -> anyhow::Result<()> {
+        self.gpio_pin_mut(pin)?.set_mode(mode, pull)
+    }
+    fn gpio_pin(&self, pin: i32) -> anyhow::Result<&Esp32GpioPin> {
+        self.pins
+            .iter()
+            .find(|p| p.pin() == pin)
+            .ok_or_else(|| GrpcError::RpcNotFound(format!("pin {} is not a gpio pin", pin)).into())
+    }
+    fn gpio_pin_mut(&mut self, pin: i32) -> anyhow::Result<&mut Esp32GpioPin> {
+        self.pins
+            .iter_mut()
+            .find(|p| p.pin() == pin)
+            .ok_or_else(|| GrpcError::RpcNotFound(format!("pin {} is not a gpio pin", pin)).into())
+    }
+    fn pwm_pin(&self, pin: i32) -> anyhow::Result<&Esp32PwmPin> {
+        self.pwms
+            .iter()
+            .find(|p| p.pin() == pin)
+            .ok_or_else(|| GrpcError::RpcNotFound(format!("pin {} is not a pwm pin", pin)).into())
+    }
+    fn pwm_pin_mut(&mut self, pin: i32) -> anyhow::Result<&mut Esp32PwmPin> {
+        self.pwms
+            .iter_mut()
+            .find(|p| p.pin() == pin)
+            .ok_or_else(|| GrpcError::RpcNotFound(format!("pin {} is not a pwm pin", pin)).into())
+    }
+    /// Discovery hook suggesting an analog reader for every ADC1 pin the board doesn't use,
+    /// `reserved` are pins used elsewhere (e.g. by analog readers or a camera)
+    pub fn analog_discovery(&self, reserved: &[i32]) -> EspAnalogDiscovery {
+        let free = ADC1_PINS
+            .iter()
+            .copied()
+            .filter(|p| {
+                !reserved.contains(p)
+                    && !self.pins.iter().any(|x| x.pin() == *p)
+                    && !self.pwms.iter().any(|x| x.pin() == *p)
+            })
+            .collect();
+        EspAnalogDiscovery { free }
     }
 }
 
-impl<Pins> Board for EspBoard<Pins>
-where
-    Pins: StatefulOutputPin + PinExt,
-{
-    fn set_gpio_pin_level(&mut self, pin: i32, is_high: bool) -> anyhow::Result<()> {
-        if let Some(pin) = self.pins.iter_mut().find(|x| x.pin() == pin) {
-            if is_high {
-                pin.set_high()
-                    .map_err(|_| anyhow::anyhow!("error setting pin {} high", pin.pin()))?;
-            } else {
-                pin.set_low()
-                    .map_err(|_| anyhow::anyhow!("error setting pin {} low", pin.pin()))?;
-            }
+pub struct EspAnalogDiscovery {
+    free: Vec<i32>,
+}
+
+impl Discover for EspAnalogDiscovery {
+    fn subtype(&self) -> &str {
+        "board"
+    }
+    fn model(&self) -> &str {
+        "esp32"
+    }
+    fn discover(&mut self) -> anyhow::Result<Vec<prost_types::Struct>> {
+        let analogs = self
+            .free
+            .iter()
+            .map(|pin| prost_types::Value {
+                kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+                    fields: BTreeMap::from([
+                        (
+                            "name".to_string(),
+                            prost_types::Value {
+                                kind: Some(prost_types::value::Kind::StringValue(format!(
+                                    "A{}",
+                                    pin
+                                ))),
+                            },
+                        ),
+                        (
+                            "pin".to_string(),
+                            prost_types::Value {
+                                kind: Some(prost_types::value::Kind::StringValue(pin.to_string())),
+                            },
+                        ),
+                    ]),
+                })),
+            })
+            .collect();
+        Ok(vec![prost_types::Struct {
+            fields: BTreeMap::from([(
+                "analogs".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::ListValue(
+                        prost_types::ListValue { values: analogs },
+                    )),
+                },
+            )]),
+        }])
+    }
+}
+
+impl<'d> I2cProbe for I2cDriver<'d> {
+    fn probe(&mut self, address: u8) -> anyhow::Result<bool> {
+        // an empty write only checks that a device acknowledges the address
+        match self.write(address, &[], TickType::from(I2C_PROBE_TIMEOUT).0) {
+            Ok(()) => Ok(true),
+            Err(e) if e.code() == esp_idf_sys::ESP_FAIL => Ok(false),
+            Err(e) => Err(anyhow::anyhow!(
+                "error probing i2c address {}: {}",
+                address,
+                e
+            )),
         }
-        Ok(())
     }
-    fn get_gpio_level(&self, pin: i32) -> anyhow::Result<bool> {
-        if let Some(pin) = self.pins.iter().find(|x| x.pin() == pin) {
-            return pin
-                .is_set_high()
-                .map_err(|_| anyhow::anyhow!("error getting pin {}", pin.pin()));
+}
+
+impl Board for EspBoard {
+    fn resolve_pin(&self, pin: &str) -> anyhow::Result<i32> {
+        match self.pins.iter().find(|p| p.alias() == Some(pin)) {
+            Some(p) => Ok(p.pin()),
+            None => parse_pin(pin),
         }
-        anyhow::bail!("pin {} not found", pin)
+    }
+    fn set_gpio_pin_level(&mut self, pin: i32, is_high: bool) -> anyhow::Result<()> {
+        self.gpio_pin_mut(pin)?.set_level(is_high)
+    }
+    fn get_gpio_level(&self, pin: i32) -> anyhow::Result<bool> {
+        self.gpio_pin(pin)?.is_high()
     }
     fn get_board_status(&self) -> anyhow::Result<common::v1::BoardStatus> {
         let mut b = common::v1::BoardStatus {
@@ -63,6 +181,15 @@ where
                 },
             );
         });
+        self.interrupts.iter().for_each(|i| {
+            let borrowed = i.borrow();
+            b.digital_interrupts.insert(
+                borrowed.name(),
+                common::v1::DigitalInterruptStatus {
+                    value: borrowed.value().unwrap_or(0),
+                },
+            );
+        });
         Ok(b)
     }
     fn get_analog_reader_by_name(
@@ -71,14 +198,40 @@ where
     ) -> anyhow::Result<Rc<RefCell<dyn AnalogReader<u16, Error = anyhow::Error>>>> {
         match self.analogs.iter().find(|a| a.borrow().name() == name) {
             Some(reader) => Ok(reader.clone()),
-            None => Err(anyhow::anyhow!("couldn't find analog reader {}", name)),
+            None => {
+                Err(GrpcError::RpcNotFound(format!("couldn't find analog reader {}", name)).into())
+            }
         }
     }
+    fn get_digital_interrupt_by_name(
+        &self,
+        name: String,
+    ) -> anyhow::Result<Rc<RefCell<dyn DigitalInterrupt>>> {
+        match self.interrupts.iter().find(|i| i.borrow().name() == name) {
+            Some(interrupt) => Ok(interrupt.clone()),
+            None => Err(GrpcError::RpcNotFound(format!(
+                "couldn't find digital interrupt {}",
+                name
+            ))
+            .into()),
+        }
+    }
+    fn get_pwm_duty(&self, pin: i32) -> anyhow::Result<f64> {
+        Ok(self.pwm_pin(pin)?.duty_cycle())
+    }
+    fn set_pwm_duty(&mut self, pin: i32, duty_cycle_pct: f64) -> anyhow::Result<()> {
+        self.pwm_pin_mut(pin)?.set_duty_cycle(duty_cycle_pct)
+    }
+    fn get_pwm_frequency(&self, pin: i32) -> anyhow::Result<u64> {
+        Ok(self.pwm_pin(pin)?.frequency())
+    }
+    fn set_pwm_frequency(&mut self, pin: i32, frequency_hz: u64) -> anyhow::Result<()> {
+        self.pwm_pin_mut(pin)?.set_frequency(frequency_hz)
+    }
 }
-impl<Pins> Status for EspBoard<Pins>
-where
-    Pins: StatefulOutputPin + PinExt,
-{
+impl DoCommand for EspBoard {}
+
+impl Status for EspBoard {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         let mut bt = BTreeMap::new();
         let mut analogs = BTreeMap::new();
@@ -110,6 +263,17 @@ where
                 },
             );
         }
+        let interrupts = digital_interrupts_status(&self.interrupts);
+        if !interrupts.is_empty() {
+            bt.insert(
+                "digital_interrupts".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::StructValue(prost_types::Struct {
+                        fields: interrupts,
+                    })),
+                },
+            );
+        }
         Ok(Some(prost_types::Struct { fields: bt }))
     }
 }
@@ -2,14 +2,17 @@
 use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::common::camera::Camera;
+use crate::common::discovery::Discover;
+use crate::common::generic::DoCommand;
+use crate::common::grpc::ChunkedBuffer;
 use crate::proto::component::camera;
-use bytes::{Bytes, BytesMut};
+use bytes::Bytes;
 use esp_idf_svc::systime::EspSystemTime;
 use esp_idf_sys::camera_config_t;
 use esp_idf_sys::camera_config_t__bindgen_ty_1;
 use esp_idf_sys::camera_config_t__bindgen_ty_2;
 use log::*;
-use prost::Message;
 
 pub struct Esp32Camera {
     config: camera_config_t,
@@ -86,26 +89,55 @@ impl Esp32Camera {
         }
     }
 }
+/// Reports the camera sensor detected by the driver, the camera has to be set up first
+pub struct Esp32CameraDiscovery;
+
+impl Discover for Esp32CameraDiscovery {
+    fn subtype(&self) -> &str {
+        "camera"
+    }
+    fn model(&self) -> &str {
+        "esp32"
+    }
+    fn discover(&mut self) -> anyhow::Result<Vec<prost_types::Struct>> {
+        let sensor = unsafe { esp_idf_sys::esp_camera_sensor_get() };
+        if sensor.is_null() {
+            return Ok(vec![]);
+        }
+        let pid = unsafe { (*sensor).id.PID };
+        Ok(vec![prost_types::Struct {
+            fields: std::collections::BTreeMap::from([(
+                "sensor_pid".to_string(),
+                prost_types::Value {
+                    kind: Some(prost_types::value::Kind::NumberValue(pid as f64)),
+                },
+            )]),
+        }])
+    }
+}
+
+impl DoCommand for Esp32Camera {}
+
 impl Camera for Esp32Camera {
-    fn get_frame(&mut self, mut buffer: BytesMut) -> anyhow::Result<BytesMut> {
+    fn get_frame(&mut self, buffer: &mut ChunkedBuffer) -> anyhow::Result<()> {
         if let Some(ptr) = self.get_cam_frame() {
             let buf = unsafe {
                 let buf = (*ptr).buf;
                 let len = (*ptr).len as usize;
                 core::slice::from_raw_parts(buf, len)
             };
-            if buf.len() > buffer.capacity() {
-                self.return_cam_frame(Some(ptr));
-                return Err(anyhow::anyhow!("oops too big"));
-            }
-            let bytes = Bytes::from(buf);
+            // the frame is encoded straight from the frame buffer, which is given back to the
+            // driver right after
+            let bytes = Bytes::from_static(buf);
             let msg = camera::v1::GetImageResponse {
                 mime_type: "image/jpeg".to_string(),
                 image: bytes,
             };
-            msg.encode(&mut buffer).unwrap();
+            let ret = buffer.encode_message(&msg);
+            drop(msg);
             self.return_cam_frame(Some(ptr));
-            return Ok(buffer);
+            ret?;
+            return Ok(());
         }
         Err(anyhow::anyhow!("cannot get frame"))
     }
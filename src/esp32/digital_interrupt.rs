@@ -0,0 +1,129 @@
+#![allow(dead_code)]
+use crate::common::board::{BasicDigitalInterrupt, DigitalInterrupt, DigitalInterruptCallback};
+use esp_idf_hal::task::{notify, wait_notification};
+use esp_idf_sys as espsys;
+use espsys::{esp, EspError, ESP_ERR_INVALID_STATE, ESP_OK};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+// state shared by the interrupt handler and the task dispatching the edges to the callbacks
+struct IsrContext {
+    pin: i32,
+    inner: BasicDigitalInterrupt,
+    // task to notify of new edges, null until it started
+    dispatcher: AtomicPtr<espsys::c_types::c_void>,
+    closed: AtomicBool,
+}
+
+/// Digital interrupt counting the edges of a GPIO from its interrupt handler, callbacks are
+/// called from a dedicated task woken up by the handler
+pub struct Esp32DigitalInterrupt {
+    name: String,
+    context: Arc<IsrContext>,
+    dispatcher: Option<JoinHandle<()>>,
+}
+
+impl Esp32DigitalInterrupt {
+    /// Configure `pin` as an input interrupting on both edges, with `timestamps` the time of
+    /// every edge is recorded
+    pub fn new(name: String, pin: i32, timestamps: bool) -> anyhow::Result<Self> {
+        let conf = espsys::gpio_config_t {
+            pin_bit_mask: 1_u64 << pin,
+            mode: espsys::gpio_mode_t_GPIO_MODE_INPUT,
+            pull_up_en: espsys::gpio_pullup_t_GPIO_PULLUP_DISABLE,
+            pull_down_en: espsys::gpio_pulldown_t_GPIO_PULLDOWN_DISABLE,
+            intr_type: espsys::gpio_int_type_t_GPIO_INTR_ANYEDGE,
+        };
+        esp!(unsafe { espsys::gpio_config(&conf) })?;
+        unsafe {
+            match espsys::gpio_install_isr_service(0) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap().into()),
+            }
+        }
+        let context = Arc::new(IsrContext {
+            pin,
+            inner: BasicDigitalInterrupt::new(timestamps),
+            dispatcher: AtomicPtr::new(std::ptr::null_mut()),
+            closed: AtomicBool::new(false),
+        });
+        let dispatched = context.clone();
+        let dispatcher = std::thread::Builder::new()
+            .stack_size(4096)
+            .spawn(move || Self::dispatch(dispatched))?;
+        esp!(unsafe {
+            espsys::gpio_isr_handler_add(
+                pin,
+                Some(Self::irq_handler),
+                Arc::as_ptr(&context) as *mut IsrContext as *mut _,
+            )
+        })?;
+        Ok(Esp32DigitalInterrupt {
+            name,
+            context,
+            dispatcher: Some(dispatcher),
+        })
+    }
+    pub fn pin(&self) -> i32 {
+        self.context.pin
+    }
+    /// Body of the dispatcher task, passes the edges to the callbacks every time the interrupt
+    /// handler notifies it until the interrupt is dropped
+    fn dispatch(context: Arc<IsrContext>) {
+        let task = unsafe { espsys::xTaskGetCurrentTaskHandle() };
+        // sequentially consistent with `drop`, so one of them sees what the other did
+        context.dispatcher.store(task as *mut _, Ordering::SeqCst);
+        while !context.closed.load(Ordering::SeqCst) {
+            let _ = wait_notification(None);
+            context.inner.dispatch();
+        }
+    }
+    /// Only records the edge and wakes up the dispatcher, which calls the callbacks
+    #[link_section = ".iram1.digital_interrupt"]
+    unsafe extern "C" fn irq_handler(arg: *mut esp_idf_sys::c_types::c_void) {
+        let context: &IsrContext = &*(arg as *const IsrContext);
+        let high = espsys::gpio_get_level(context.pin) != 0;
+        let nanos = if context.inner.records_timestamps() {
+            espsys::esp_timer_get_time() as u64 * 1000
+        } else {
+            0
+        };
+        context.inner.tick(high, nanos);
+        let task = context.dispatcher.load(Ordering::Acquire);
+        if !task.is_null() {
+            notify(task as _, 1);
+        }
+    }
+}
+
+impl DigitalInterrupt for Esp32DigitalInterrupt {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+    fn value(&self) -> anyhow::Result<i64> {
+        Ok(self.context.inner.value())
+    }
+    fn add_callback(&mut self, callback: DigitalInterruptCallback) -> anyhow::Result<()> {
+        self.context.inner.add_callback(callback);
+        Ok(())
+    }
+}
+
+impl Drop for Esp32DigitalInterrupt {
+    fn drop(&mut self) {
+        unsafe {
+            espsys::gpio_isr_handler_remove(self.context.pin);
+        }
+        self.context.closed.store(true, Ordering::SeqCst);
+        if let Some(dispatcher) = self.dispatcher.take() {
+            // the dispatcher may not have published its handle yet, it then sees `closed`
+            // before waiting
+            let task = self.context.dispatcher.load(Ordering::SeqCst);
+            if !task.is_null() {
+                unsafe { notify(task as _, 1) };
+            }
+            let _ = dispatcher.join();
+        }
+    }
+}
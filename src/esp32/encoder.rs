@@ -0,0 +1,343 @@
+#![allow(dead_code)]
+use esp_idf_sys as espsys;
+use espsys::c_types::{c_short, c_ulong};
+use espsys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_DECREASE as pcnt_count_dec;
+use espsys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_INCREASE as pcnt_count_inc;
+use espsys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_INVERSE as pcnt_mode_reverse;
+use espsys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_KEEP as pcnt_mode_keep;
+use espsys::pcnt_channel_t_PCNT_CHANNEL_0 as pcnt_channel_0;
+use espsys::pcnt_channel_t_PCNT_CHANNEL_1 as pcnt_channel_1;
+use espsys::pcnt_evt_type_t_PCNT_EVT_H_LIM as pcnt_evt_h_lim;
+use espsys::pcnt_evt_type_t_PCNT_EVT_L_LIM as pcnt_evt_l_lim;
+use espsys::{pcnt_channel_edge_action_t, pcnt_channel_t, pcnt_config_t};
+use espsys::{pcnt_unit_t, pcnt_unit_t_PCNT_UNIT_MAX};
+
+use super::pin::PinExt;
+use crate::common::encoder::{
+    encoder_status, position_type_not_supported, Encoder, EncoderPositionType,
+    EncoderSupportedProperties,
+};
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::status::Status;
+use embedded_hal::digital::v2::InputPin;
+use espsys::{esp, EspError, ESP_ERR_INVALID_STATE, ESP_OK};
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+// state shared with the interrupt handler, boxed so its address stays valid
+struct IsrContext {
+    unit: pcnt_unit_t,
+    /// number of times the counter reached its high limit minus the times it reached the low one
+    acc: AtomicI32,
+}
+
+/// PCNT units in use, one bit per unit
+static CLAIMED_UNITS: AtomicU8 = AtomicU8::new(0);
+
+/// PCNT unit counting the edges of an encoder. The hardware counter is 16 bits, it is cleared
+/// every time it reaches +/- `counter_limit` and the interrupt handler keeps track of how many
+/// times that happened.
+struct PcntUnit {
+    context: Box<IsrContext>,
+    counter_limit: i16,
+}
+
+impl PcntUnit {
+    fn new(unit: pcnt_unit_t, counter_limit: i16) -> anyhow::Result<Self> {
+        if unit >= pcnt_unit_t_PCNT_UNIT_MAX {
+            return Err(GrpcError::RpcInvalidArgument(format!(
+                "PCNT unit {} doesn't exist, there are {} of them",
+                unit, pcnt_unit_t_PCNT_UNIT_MAX
+            ))
+            .into());
+        }
+        if counter_limit <= 0 {
+            return Err(GrpcError::RpcInvalidArgument(
+                "counter limit should be strictly positive".to_string(),
+            )
+            .into());
+        }
+        let bit = 1_u8 << unit;
+        if CLAIMED_UNITS.fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return Err(GrpcError::RpcFailedPrecondition(format!(
+                "PCNT unit {} is already used by another encoder",
+                unit
+            ))
+            .into());
+        }
+        Ok(PcntUnit {
+            context: Box::new(IsrContext {
+                unit,
+                acc: AtomicI32::new(0),
+            }),
+            counter_limit,
+        })
+    }
+    fn unit(&self) -> pcnt_unit_t {
+        self.context.unit
+    }
+    /// Count on `channel` the edges of `pulse_pin`, the level of `ctrl_pin` (when used) tells
+    /// which way to count
+    fn configure_channel(
+        &self,
+        channel: pcnt_channel_t,
+        pulse_pin: i32,
+        ctrl_pin: i32,
+        pos_mode: pcnt_channel_edge_action_t,
+        neg_mode: pcnt_channel_edge_action_t,
+    ) -> anyhow::Result<()> {
+        let config = pcnt_config_t {
+            pulse_gpio_num: pulse_pin,
+            ctrl_gpio_num: ctrl_pin,
+            pos_mode,
+            neg_mode,
+            lctrl_mode: pcnt_mode_reverse,
+            hctrl_mode: pcnt_mode_keep,
+            counter_h_lim: self.counter_limit,
+            counter_l_lim: -self.counter_limit,
+            channel,
+            unit: self.unit(),
+        };
+        unsafe {
+            match espsys::pcnt_unit_config(&config as *const pcnt_config_t) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap().into()),
+            }
+        }
+        Ok(())
+    }
+    /// Change which edges of the pulse pin of `channel` are counted and how
+    fn set_edge_modes(
+        &self,
+        channel: pcnt_channel_t,
+        pos_mode: pcnt_channel_edge_action_t,
+        neg_mode: pcnt_channel_edge_action_t,
+    ) -> anyhow::Result<()> {
+        esp!(unsafe {
+            espsys::pcnt_set_mode(
+                self.unit(),
+                channel,
+                pos_mode,
+                neg_mode,
+                pcnt_mode_keep,
+                pcnt_mode_reverse,
+            )
+        })?;
+        Ok(())
+    }
+    /// Register the interrupt handler and start counting from 0, channels have to be
+    /// configured beforehand
+    fn start(&mut self) -> anyhow::Result<()> {
+        unsafe {
+            match espsys::pcnt_isr_service_install(0) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap().into()),
+            }
+        }
+        esp!(unsafe {
+            espsys::pcnt_isr_handler_add(
+                self.unit(),
+                Some(Self::irq_handler),
+                self.context.as_mut() as *mut IsrContext as *mut _,
+            )
+        })?;
+        unsafe {
+            match espsys::pcnt_event_enable(self.unit(), pcnt_evt_h_lim) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap().into()),
+            }
+            match espsys::pcnt_event_enable(self.unit(), pcnt_evt_l_lim) {
+                ESP_OK | ESP_ERR_INVALID_STATE => {}
+                err => return Err(EspError::from(err).unwrap().into()),
+            }
+        }
+        self.clear()
+    }
+    fn clear(&self) -> anyhow::Result<()> {
+        esp!(unsafe { espsys::pcnt_counter_pause(self.unit()) })?;
+        self.context.acc.store(0, Ordering::Relaxed);
+        esp!(unsafe { espsys::pcnt_counter_clear(self.unit()) })?;
+        esp!(unsafe { espsys::pcnt_counter_resume(self.unit()) })?;
+        Ok(())
+    }
+    fn count(&self) -> anyhow::Result<i64> {
+        let mut ctr: i16 = 0;
+        esp!(unsafe { espsys::pcnt_get_counter_value(self.unit(), &mut ctr as *mut c_short) })?;
+        let laps = self.context.acc.load(Ordering::Relaxed) as i64;
+        Ok(laps * self.counter_limit as i64 + i64::from(ctr))
+    }
+    #[inline(always)]
+    #[link_section = ".iram1.pcnt_srv"]
+    unsafe extern "C" fn irq_handler(arg: *mut esp_idf_sys::c_types::c_void) {
+        let context: &IsrContext = &*(arg as *const IsrContext);
+        let mut status = 0;
+        espsys::pcnt_get_event_status(context.unit, &mut status as *mut c_ulong);
+        if status & pcnt_evt_h_lim != 0 {
+            context.acc.fetch_add(1, Ordering::Relaxed);
+        }
+        if status & pcnt_evt_l_lim != 0 {
+            context.acc.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for PcntUnit {
+    fn drop(&mut self) {
+        unsafe {
+            espsys::pcnt_counter_pause(self.unit());
+            espsys::pcnt_isr_handler_remove(self.unit());
+        }
+        CLAIMED_UNITS.fetch_and(!(1_u8 << self.unit()), Ordering::AcqRel);
+    }
+}
+
+fn ticks_position(pcnt: &PcntUnit, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+    match position_type {
+        EncoderPositionType::Ticks => Ok(pcnt.count()? as f64),
+        _ => Err(position_type_not_supported(position_type).into()),
+    }
+}
+
+/// Incremental encoder with two phases in quadrature, every edge of either phase is counted
+/// and the other phase tells the direction. Each encoder needs its own PCNT `unit`, creating
+/// it fails if another encoder already uses it.
+pub struct Esp32QuadratureEncoder<A, B> {
+    pcnt: PcntUnit,
+    a: A,
+    b: B,
+}
+
+impl<A, B> Esp32QuadratureEncoder<A, B>
+where
+    A: InputPin + PinExt,
+    B: InputPin + PinExt,
+{
+    pub fn new(unit: pcnt_unit_t, a: A, b: B, counter_limit: i16) -> anyhow::Result<Self> {
+        let mut pcnt = PcntUnit::new(unit, counter_limit)?;
+        pcnt.configure_channel(
+            pcnt_channel_0,
+            a.pin(),
+            b.pin(),
+            pcnt_count_inc,
+            pcnt_count_dec,
+        )?;
+        pcnt.configure_channel(
+            pcnt_channel_1,
+            b.pin(),
+            a.pin(),
+            pcnt_count_dec,
+            pcnt_count_inc,
+        )?;
+        pcnt.start()?;
+        Ok(Esp32QuadratureEncoder { pcnt, a, b })
+    }
+}
+
+impl<A, B> Encoder for Esp32QuadratureEncoder<A, B>
+where
+    A: InputPin + PinExt,
+    B: InputPin + PinExt,
+{
+    fn get_properties(&self) -> EncoderSupportedProperties {
+        EncoderSupportedProperties {
+            ticks_count_supported: true,
+            angle_degrees_supported: false,
+        }
+    }
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+        ticks_position(&self.pcnt, position_type)
+    }
+    fn reset_position(&mut self) -> anyhow::Result<()> {
+        self.pcnt.clear()
+    }
+}
+
+impl<A, B> DoCommand for Esp32QuadratureEncoder<A, B>
+where
+    A: InputPin + PinExt,
+    B: InputPin + PinExt,
+{
+}
+
+impl<A, B> Status for Esp32QuadratureEncoder<A, B>
+where
+    A: InputPin + PinExt,
+    B: InputPin + PinExt,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(encoder_status(self.pcnt.count()? as f64)))
+    }
+}
+
+/// Incremental encoder with a single phase, both edges are counted. It can't sense which way
+/// it turns and counts in the direction last set with `Encoder::set_direction`. Each encoder
+/// needs its own PCNT `unit`, creating it fails if another encoder already uses it.
+pub struct Esp32SinglePhaseEncoder<A> {
+    pcnt: PcntUnit,
+    a: A,
+    forwards: bool,
+}
+
+impl<A> Esp32SinglePhaseEncoder<A>
+where
+    A: InputPin + PinExt,
+{
+    pub fn new(unit: pcnt_unit_t, a: A, counter_limit: i16) -> anyhow::Result<Self> {
+        let mut pcnt = PcntUnit::new(unit, counter_limit)?;
+        pcnt.configure_channel(
+            pcnt_channel_0,
+            a.pin(),
+            espsys::PCNT_PIN_NOT_USED,
+            pcnt_count_inc,
+            pcnt_count_inc,
+        )?;
+        pcnt.start()?;
+        Ok(Esp32SinglePhaseEncoder {
+            pcnt,
+            a,
+            forwards: true,
+        })
+    }
+}
+
+impl<A> Encoder for Esp32SinglePhaseEncoder<A>
+where
+    A: InputPin + PinExt,
+{
+    fn get_properties(&self) -> EncoderSupportedProperties {
+        EncoderSupportedProperties {
+            ticks_count_supported: true,
+            angle_degrees_supported: false,
+        }
+    }
+    fn get_position(&self, position_type: EncoderPositionType) -> anyhow::Result<f64> {
+        ticks_position(&self.pcnt, position_type)
+    }
+    fn reset_position(&mut self) -> anyhow::Result<()> {
+        self.pcnt.clear()
+    }
+    fn set_direction(&mut self, forwards: bool) -> anyhow::Result<()> {
+        if forwards == self.forwards {
+            return Ok(());
+        }
+        let mode = if forwards {
+            pcnt_count_inc
+        } else {
+            pcnt_count_dec
+        };
+        self.pcnt.set_edge_modes(pcnt_channel_0, mode, mode)?;
+        self.forwards = forwards;
+        Ok(())
+    }
+}
+
+impl<A> DoCommand for Esp32SinglePhaseEncoder<A> where A: InputPin + PinExt {}
+
+impl<A> Status for Esp32SinglePhaseEncoder<A>
+where
+    A: InputPin + PinExt,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(encoder_status(self.pcnt.count()? as f64)))
+    }
+}
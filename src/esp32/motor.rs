@@ -1,188 +1,33 @@
 #![allow(dead_code)]
-use esp_idf_sys as espsys;
-use espsys::c_types::{c_short, c_ulong};
-use espsys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_DECREASE as pcnt_count_dec;
-use espsys::pcnt_channel_edge_action_t_PCNT_CHANNEL_EDGE_ACTION_INCREASE as pcnt_count_inc;
-use espsys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_INVERSE as pcnt_mode_reverse;
-use espsys::pcnt_channel_level_action_t_PCNT_CHANNEL_LEVEL_ACTION_KEEP as pcnt_mode_keep;
-use espsys::pcnt_channel_t_PCNT_CHANNEL_0 as pcnt_channel_0;
-use espsys::pcnt_channel_t_PCNT_CHANNEL_1 as pcnt_channel_1;
-use espsys::pcnt_config_t;
-use espsys::pcnt_evt_type_t_PCNT_EVT_H_LIM as pcnt_evt_h_lim;
-use espsys::pcnt_evt_type_t_PCNT_EVT_L_LIM as pcnt_evt_l_lim;
-
 use super::pin::PinExt;
+use crate::common::actuator::Actuator;
+use crate::common::encoder::{Encoder, EncoderPositionType};
+use crate::common::error::GrpcError;
+use crate::common::generic::DoCommand;
+use crate::common::motor::go_for_math;
 use crate::common::motor::Motor;
-use crate::common::motor::Position;
+use crate::common::motor::MotorSupportedProperties;
 use crate::common::status::Status;
-use espsys::{esp, EspError, ESP_ERR_INVALID_STATE, ESP_OK};
 use log::*;
-use std::sync::atomic::{AtomicI32, Ordering};
-use std::sync::Arc;
+use std::time::Duration;
 
-use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal::digital::v2::OutputPin;
 use embedded_hal::PwmPin;
 
-pub struct Esp32Encoder<A, B> {
-    acc: Arc<AtomicI32>,
-    config: pcnt_config_t,
-    a: A,
-    b: B,
-    unit: u32,
-}
-
-impl<A, B> Esp32Encoder<A, B>
-where
-    A: InputPin + PinExt,
-    B: InputPin + PinExt,
-{
-    pub fn new(a: A, b: B) -> Self {
-        Esp32Encoder {
-            acc: Arc::new(AtomicI32::new(0)),
-            config: pcnt_config_t {
-                pulse_gpio_num: a.pin(),
-                ctrl_gpio_num: b.pin(),
-                pos_mode: pcnt_count_inc,
-                neg_mode: pcnt_count_dec,
-                lctrl_mode: pcnt_mode_reverse,
-                hctrl_mode: pcnt_mode_keep,
-                counter_h_lim: 100,
-                counter_l_lim: -100,
-                channel: pcnt_channel_0,
-                unit: 0,
-            },
-            a,
-            b,
-            unit: 0,
-        }
-    }
-    pub fn start(&self) -> anyhow::Result<()> {
-        unsafe {
-            match esp_idf_sys::pcnt_counter_resume(self.config.unit) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-        Ok(())
-    }
-    pub fn stop(&self) -> anyhow::Result<()> {
-        unsafe {
-            match esp_idf_sys::pcnt_counter_pause(self.config.unit) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-        Ok(())
-    }
-    pub fn reset(&self) -> anyhow::Result<()> {
-        self.stop()?;
-        unsafe {
-            match esp_idf_sys::pcnt_counter_clear(self.config.unit) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-        self.start()?;
-        Ok(())
-    }
-    pub fn get_counter_value(&self) -> anyhow::Result<i32> {
-        let mut ctr: i16 = 0;
-        unsafe {
-            match esp_idf_sys::pcnt_get_counter_value(self.config.unit, &mut ctr as *mut c_short) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-        let tot = self.acc.load(Ordering::Relaxed) * 100 + i32::from(ctr);
-        Ok(tot)
-    }
-    pub fn setup_pcnt(&mut self) -> anyhow::Result<()> {
-        unsafe {
-            match esp_idf_sys::pcnt_unit_config(&self.config as *const pcnt_config_t) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-        self.config.pulse_gpio_num = self.b.pin();
-        self.config.ctrl_gpio_num = self.a.pin();
-        self.config.channel = pcnt_channel_1;
-        self.config.pos_mode = pcnt_count_dec;
-        self.config.neg_mode = pcnt_count_inc;
-        unsafe {
-            match esp_idf_sys::pcnt_unit_config(&self.config as *const pcnt_config_t) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-
-        unsafe {
-            match esp_idf_sys::pcnt_counter_pause(self.config.unit) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-            match esp_idf_sys::pcnt_counter_clear(self.config.unit) {
-                ESP_OK => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-
-        unsafe {
-            match esp_idf_sys::pcnt_isr_service_install(0) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-
-        esp!(unsafe {
-            esp_idf_sys::pcnt_isr_handler_add(
-                self.config.unit,
-                Some(Self::irq_handler),
-                self as *mut Self as *mut _,
-            )
-        })?;
-
-        unsafe {
-            match esp_idf_sys::pcnt_event_enable(self.config.unit, pcnt_evt_h_lim) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-            match esp_idf_sys::pcnt_event_enable(self.config.unit, pcnt_evt_l_lim) {
-                ESP_OK | ESP_ERR_INVALID_STATE => {}
-                err => return Err(EspError::from(err).unwrap().into()),
-            }
-        }
-
-        Ok(())
-    }
-    #[inline(always)]
-    #[link_section = ".iram1.pcnt_srv"]
-    unsafe extern "C" fn irq_handler(arg: *mut esp_idf_sys::c_types::c_void) {
-        let arg: &mut Esp32Encoder<A, B> = &mut *(arg as *mut _);
-        let mut status = 0;
-        esp_idf_sys::pcnt_get_event_status(arg.unit, &mut status as *mut c_ulong);
-        if status & pcnt_evt_h_lim != 0 {
-            arg.acc.fetch_add(1, Ordering::Relaxed);
-        }
-        if status & pcnt_evt_l_lim != 0 {
-            arg.acc.fetch_sub(1, Ordering::Relaxed);
-        }
-    }
-}
-impl<A, B> Position for Esp32Encoder<A, B>
-where
-    A: InputPin + PinExt,
-    B: InputPin + PinExt,
-{
-    fn position(&self) -> anyhow::Result<i32> {
-        self.get_counter_value()
-    }
-}
-
+/// Motor whose position is read from an encoder, the encoder can be shared with other
+/// components (e.g. `Arc<Mutex<dyn Encoder>>`), the motor never resets it
 pub struct MotorEncodedEsp32<Enc, A, B, PWM> {
     a: A,
     b: B,
     pwm: PWM,
     enc: Enc,
+    power: f64,
+    max_rpm: f64,
+    ticks_per_rotation: i32,
+    /// ticks added to the position of the encoder to get the position of the motor
+    offset: i32,
+    /// Position to reach when going for a number of revolutions
+    target: Option<i32>,
 }
 
 impl<Enc, A, B, PWM> MotorEncodedEsp32<Enc, A, B, PWM>
@@ -190,10 +35,27 @@ where
     A: OutputPin + PinExt,
     B: OutputPin + PinExt,
     PWM: PwmPin<Duty = u32>,
-    Enc: Position,
+    Enc: Encoder,
 {
-    pub fn new(a: A, b: B, pwm: PWM, enc: Enc) -> Self {
-        MotorEncodedEsp32 { a, b, pwm, enc }
+    pub fn new(a: A, b: B, pwm: PWM, enc: Enc, max_rpm: f64, ticks_per_rotation: i32) -> Self {
+        MotorEncodedEsp32 {
+            a,
+            b,
+            pwm,
+            enc,
+            power: 0.0,
+            max_rpm,
+            ticks_per_rotation,
+            offset: 0,
+            target: None,
+        }
+    }
+    /// Position of the motor in ticks
+    fn position(&self) -> anyhow::Result<i32> {
+        Ok(self.enc.get_position(EncoderPositionType::Ticks)? as i32 + self.offset)
+    }
+    fn revolutions(&self) -> anyhow::Result<f64> {
+        Ok(self.position()? as f64 / self.ticks_per_rotation as f64)
     }
 }
 impl<Enc, A, B, PWM> Motor for MotorEncodedEsp32<Enc, A, B, PWM>
@@ -201,11 +63,11 @@ where
     A: OutputPin + PinExt,
     B: OutputPin + PinExt,
     PWM: PwmPin<Duty = u32>,
-    Enc: Position,
+    Enc: Encoder,
 {
     fn set_power(&mut self, pct: f64) -> anyhow::Result<()> {
         if !(-1.0..=1.0).contains(&pct) {
-            anyhow::bail!("power outside limit")
+            return Err(GrpcError::RpcInvalidArgument("power outside limit".to_string()).into());
         }
         let max_duty = self.pwm.get_max_duty();
         if pct < 0.0 {
@@ -223,6 +85,9 @@ where
                 .set_high()
                 .map_err(|_| anyhow::anyhow!("error setting B pin"))?;
         }
+        if pct != 0.0 {
+            self.enc.set_direction(pct > 0.0)?;
+        }
         info!(
             "Setting pwr {} translate to {} out of {}",
             &pct,
@@ -231,24 +96,94 @@ where
         );
         self.pwm
             .set_duty(((max_duty as f64) * pct.abs()).floor() as u32);
+        self.power = pct;
+        self.target = None;
         Ok(())
     }
-    fn get_position(&mut self) -> anyhow::Result<i32> {
-        self.enc.position()
+    fn get_position(&mut self) -> anyhow::Result<f64> {
+        self.revolutions()
+    }
+    /// The encoder tells when the motor got there, it stops moving once it did
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        let (pct, _) = go_for_math(self.max_rpm, rpm, revolutions)?;
+        let start = self.position()?;
+        self.set_power(pct)?;
+        if revolutions != 0.0 {
+            let ticks = (revolutions.abs() * self.ticks_per_rotation as f64).round() as i32;
+            self.target = Some(start + ticks * pct.signum() as i32);
+        }
+        Ok(None)
+    }
+    fn go_to(&mut self, rpm: f64, position_revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        let revolutions = position_revolutions - self.get_position()?;
+        if revolutions == 0.0 {
+            return Ok(None);
+        }
+        self.go_for(rpm.abs(), revolutions)
+    }
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)> {
+        Ok((self.power != 0.0, self.power))
+    }
+    fn reset_zero_position(&mut self, offset: f64) -> anyhow::Result<()> {
+        let ticks = self.enc.get_position(EncoderPositionType::Ticks)? as i32;
+        // the current position then reads -offset
+        self.offset = -(offset * self.ticks_per_rotation as f64).round() as i32 - ticks;
+        Ok(())
+    }
+    fn get_properties(&mut self) -> MotorSupportedProperties {
+        MotorSupportedProperties {
+            position_reporting: true,
+        }
+    }
+}
+impl<Enc, A, B, PWM> Actuator for MotorEncodedEsp32<Enc, A, B, PWM>
+where
+    A: OutputPin + PinExt,
+    B: OutputPin + PinExt,
+    PWM: PwmPin<Duty = u32>,
+    Enc: Encoder,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        if self.power == 0.0 {
+            return Ok(false);
+        }
+        let target = match self.target {
+            Some(target) => target,
+            None => return Ok(true),
+        };
+        let position = self.position()?;
+        Ok(if self.power > 0.0 {
+            position < target
+        } else {
+            position > target
+        })
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.set_power(0.0)
     }
 }
+
 use std::collections::BTreeMap;
 
+impl<Enc, A, B, PWM> DoCommand for MotorEncodedEsp32<Enc, A, B, PWM>
+where
+    A: OutputPin + PinExt,
+    B: OutputPin + PinExt,
+    PWM: PwmPin<Duty = u32>,
+    Enc: Encoder,
+{
+}
+
 impl<Enc, A, B, PWM> Status for MotorEncodedEsp32<Enc, A, B, PWM>
 where
     A: OutputPin + PinExt,
     B: OutputPin + PinExt,
     PWM: PwmPin<Duty = u32>,
-    Enc: Position,
+    Enc: Encoder,
 {
     fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
         let mut bt = BTreeMap::new();
-        let pos = self.enc.position()? as f64;
+        let pos = self.revolutions()?;
         bt.insert(
             "position".to_string(),
             prost_types::Value {
@@ -263,6 +198,8 @@ pub struct MotorEsp32<A, B, PWM> {
     a: A,
     b: B,
     pwm: PWM,
+    power: f64,
+    max_rpm: f64,
 }
 
 impl<A, B, PWM> MotorEsp32<A, B, PWM>
@@ -271,8 +208,14 @@ where
     B: OutputPin + PinExt,
     PWM: PwmPin<Duty = u32>,
 {
-    pub fn new(a: A, b: B, pwm: PWM) -> Self {
-        MotorEsp32 { a, b, pwm }
+    pub fn new(a: A, b: B, pwm: PWM, max_rpm: f64) -> Self {
+        MotorEsp32 {
+            a,
+            b,
+            pwm,
+            power: 0.0,
+            max_rpm,
+        }
     }
 }
 impl<A, B, PWM> Motor for MotorEsp32<A, B, PWM>
@@ -283,7 +226,7 @@ where
 {
     fn set_power(&mut self, pct: f64) -> anyhow::Result<()> {
         if !(-1.0..=1.0).contains(&pct) {
-            anyhow::bail!("power outside limit")
+            return Err(GrpcError::RpcInvalidArgument("power outside limit".to_string()).into());
         }
         let max_duty = self.pwm.get_max_duty();
         if pct < 0.0 {
@@ -303,13 +246,61 @@ where
         }
         self.pwm
             .set_duty(((max_duty as f64) * pct.abs()).floor() as u32);
+        self.power = pct;
         Ok(())
     }
-    fn get_position(&mut self) -> anyhow::Result<i32> {
-        Ok(0)
+    fn get_position(&mut self) -> anyhow::Result<f64> {
+        Ok(0.0)
+    }
+    fn go_for(&mut self, rpm: f64, revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        let (pct, dur) = go_for_math(self.max_rpm, rpm, revolutions)?;
+        self.set_power(pct)?;
+        Ok(dur)
+    }
+    fn go_to(&mut self, _rpm: f64, _position_revolutions: f64) -> anyhow::Result<Option<Duration>> {
+        Err(GrpcError::RpcUnimplemented(
+            "go_to requires a motor with position reporting".to_string(),
+        )
+        .into())
+    }
+    fn is_powered(&self) -> anyhow::Result<(bool, f64)> {
+        Ok((self.power != 0.0, self.power))
+    }
+    fn reset_zero_position(&mut self, _offset: f64) -> anyhow::Result<()> {
+        Err(GrpcError::RpcUnimplemented(
+            "reset_zero_position requires a motor with position reporting".to_string(),
+        )
+        .into())
+    }
+    fn get_properties(&mut self) -> MotorSupportedProperties {
+        MotorSupportedProperties {
+            position_reporting: false,
+        }
+    }
+}
+
+impl<A, B, PWM> Actuator for MotorEsp32<A, B, PWM>
+where
+    A: OutputPin + PinExt,
+    B: OutputPin + PinExt,
+    PWM: PwmPin<Duty = u32>,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(self.power != 0.0)
+    }
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.set_power(0.0)
     }
 }
 
+impl<A, B, PWM> DoCommand for MotorEsp32<A, B, PWM>
+where
+    A: OutputPin + PinExt,
+    B: OutputPin + PinExt,
+    PWM: PwmPin<Duty = u32>,
+{
+}
+
 impl<A, B, PWM> Status for MotorEsp32<A, B, PWM>
 where
     A: OutputPin + PinExt,
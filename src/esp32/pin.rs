@@ -1,14 +1,150 @@
-use esp_idf_hal::gpio::{OutputMode, Pin, PinDriver};
+use crate::common::error::GrpcError;
+use esp_idf_hal::gpio::{AnyIOPin, AnyInputPin, Input, InputOutput, Level, Pin, PinDriver, Pull};
 
 pub trait PinExt {
     fn pin(&self) -> i32;
 }
 
-impl<'d, T: Pin, MODE> PinExt for PinDriver<'d, T, MODE>
-where
-    MODE: OutputMode,
-{
+impl<'d, T: Pin, MODE> PinExt for PinDriver<'d, T, MODE> {
     fn pin(&self) -> i32 {
         self.pin()
     }
 }
+
+/// Direction a board pin is configured in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinMode {
+    Input,
+    Output,
+}
+
+enum GpioDriver {
+    Input(PinDriver<'static, AnyIOPin, Input>),
+    /// GPIOs 34 to 39 can only be inputs and have no pull resistors
+    InputOnly(PinDriver<'static, AnyInputPin, Input>),
+    /// Output pins are also inputs so the level they drive can be read back
+    Output(PinDriver<'static, AnyIOPin, InputOutput>),
+}
+
+/// GPIO managed by the board, its mode can be changed at runtime and it can be given an alias
+/// (e.g. "led") requests can use instead of its number
+pub struct Esp32GpioPin {
+    pin: i32,
+    alias: Option<String>,
+    pull: Pull,
+    // only empty if changing the mode failed half way
+    driver: Option<GpioDriver>,
+}
+
+impl Esp32GpioPin {
+    pub fn input(pin: AnyIOPin, pull: Pull) -> anyhow::Result<Self> {
+        let pin_number = pin.pin();
+        let mut driver = PinDriver::input(pin)?;
+        driver.set_pull(pull)?;
+        Ok(Esp32GpioPin {
+            pin: pin_number,
+            alias: None,
+            pull,
+            driver: Some(GpioDriver::Input(driver)),
+        })
+    }
+    pub fn input_only(pin: AnyInputPin) -> anyhow::Result<Self> {
+        let pin_number = pin.pin();
+        Ok(Esp32GpioPin {
+            pin: pin_number,
+            alias: None,
+            pull: Pull::Floating,
+            driver: Some(GpioDriver::InputOnly(PinDriver::input(pin)?)),
+        })
+    }
+    pub fn output(pin: AnyIOPin) -> anyhow::Result<Self> {
+        let pin_number = pin.pin();
+        Ok(Esp32GpioPin {
+            pin: pin_number,
+            alias: None,
+            pull: Pull::Floating,
+            driver: Some(GpioDriver::Output(PinDriver::input_output(pin)?)),
+        })
+    }
+    pub fn with_alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_string());
+        self
+    }
+    pub fn pin(&self) -> i32 {
+        self.pin
+    }
+    pub fn alias(&self) -> Option<&str> {
+        self.alias.as_deref()
+    }
+    pub fn pull(&self) -> Pull {
+        self.pull
+    }
+    pub fn mode(&self) -> anyhow::Result<PinMode> {
+        match self.driver()? {
+            GpioDriver::Input(_) | GpioDriver::InputOnly(_) => Ok(PinMode::Input),
+            GpioDriver::Output(_) => Ok(PinMode::Output),
+        }
+    }
+    pub fn is_high(&self) -> anyhow::Result<bool> {
+        Ok(match self.driver()? {
+            GpioDriver::Input(d) => d.is_high(),
+            GpioDriver::InputOnly(d) => d.is_high(),
+            GpioDriver::Output(d) => d.is_high(),
+        })
+    }
+    pub fn set_level(&mut self, is_high: bool) -> anyhow::Result<()> {
+        let pin = self.pin;
+        match self.driver.as_mut() {
+            Some(GpioDriver::Output(d)) => {
+                let level = if is_high { Level::High } else { Level::Low };
+                d.set_level(level)
+                    .map_err(|e| anyhow::anyhow!("error setting level of pin {}: {}", pin, e))
+            }
+            Some(_) => Err(GrpcError::RpcFailedPrecondition(format!(
+                "pin {} is an input, it can't be set",
+                pin
+            ))
+            .into()),
+            None => Err(self.unusable()),
+        }
+    }
+    /// Switch the pin to `mode`, the pull resistor only applies to inputs
+    pub fn set_mode(&mut self, mode: PinMode, pull: Pull) -> anyhow::Result<()> {
+        if let Some(GpioDriver::InputOnly(_)) = self.driver {
+            if mode == PinMode::Output || pull != Pull::Floating {
+                return Err(GrpcError::RpcFailedPrecondition(format!(
+                    "pin {} is input only and has no pull resistors",
+                    self.pin
+                ))
+                .into());
+            }
+            return Ok(());
+        }
+        let driver = self.driver.take().ok_or_else(|| self.unusable())?;
+        self.driver = Some(match (driver, mode) {
+            (GpioDriver::Input(mut d), PinMode::Input) => {
+                d.set_pull(pull)?;
+                GpioDriver::Input(d)
+            }
+            (GpioDriver::Input(d), PinMode::Output) => GpioDriver::Output(d.into_input_output()?),
+            (GpioDriver::Output(d), PinMode::Input) => {
+                let mut d = d.into_input()?;
+                d.set_pull(pull)?;
+                GpioDriver::Input(d)
+            }
+            (driver, _) => driver,
+        });
+        self.pull = pull;
+        Ok(())
+    }
+    fn driver(&self) -> anyhow::Result<&GpioDriver> {
+        self.driver.as_ref().ok_or_else(|| self.unusable())
+    }
+    fn unusable(&self) -> anyhow::Error {
+        GrpcError::RpcInternal(format!(
+            "pin {} is unusable after failing to change its mode",
+            self.pin
+        ))
+        .into()
+    }
+}
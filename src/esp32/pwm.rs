@@ -0,0 +1,65 @@
+#![allow(dead_code)]
+use esp_idf_hal::ledc::LedcDriver;
+use esp_idf_sys as espsys;
+use espsys::{esp, ledc_mode_t_LEDC_LOW_SPEED_MODE, ledc_timer_t};
+
+/// Board pin driven by a LEDC channel. The frequency belongs to the LEDC timer, changing it
+/// changes the frequency of every channel using the same timer.
+pub struct Esp32PwmPin {
+    pin: i32,
+    driver: LedcDriver<'static>,
+    timer: ledc_timer_t,
+    frequency_hz: u64,
+}
+
+impl Esp32PwmPin {
+    /// `timer` is the LEDC timer `driver` was built with, running at `frequency_hz`
+    pub fn new(
+        pin: i32,
+        driver: LedcDriver<'static>,
+        timer: ledc_timer_t,
+        frequency_hz: u64,
+    ) -> Self {
+        Esp32PwmPin {
+            pin,
+            driver,
+            timer,
+            frequency_hz,
+        }
+    }
+    pub fn pin(&self) -> i32 {
+        self.pin
+    }
+    pub fn duty_cycle(&self) -> f64 {
+        self.driver.get_duty() as f64 / self.driver.get_max_duty() as f64
+    }
+    /// `duty_cycle_pct` is between 0 and 1
+    pub fn set_duty_cycle(&mut self, duty_cycle_pct: f64) -> anyhow::Result<()> {
+        let duty = (duty_cycle_pct * self.driver.get_max_duty() as f64).round() as u32;
+        self.driver
+            .set_duty(duty)
+            .map_err(|e| anyhow::anyhow!("error setting duty cycle of pin {}: {}", self.pin, e))
+    }
+    pub fn frequency(&self) -> u64 {
+        self.frequency_hz
+    }
+    pub fn set_frequency(&mut self, frequency_hz: u64) -> anyhow::Result<()> {
+        if frequency_hz == self.frequency_hz {
+            return Ok(());
+        }
+        let freq = u32::try_from(frequency_hz)
+            .map_err(|_| anyhow::anyhow!("frequency {}Hz is too high", frequency_hz))?;
+        let ret =
+            unsafe { espsys::ledc_set_freq(ledc_mode_t_LEDC_LOW_SPEED_MODE, self.timer, freq) };
+        esp!(ret).map_err(|e| {
+            anyhow::anyhow!(
+                "error setting frequency of pin {} to {}Hz: {}",
+                self.pin,
+                frequency_hz,
+                e
+            )
+        })?;
+        self.frequency_hz = frequency_hz;
+        Ok(())
+    }
+}
@@ -5,7 +5,10 @@ use std::{
     time::Duration,
 };
 
-use crate::common::grpc::GrpcServer;
+use crate::common::auth::Authenticator;
+use crate::common::grpc::{GrpcHandler, GrpcServer, Timeout};
+use crate::common::session::safety_monitor;
+use crate::proto::app::v1::LocationSecret;
 
 use super::super::common::robot::LocalRobot;
 use esp_idf_hal::task::{notify, wait_notification};
@@ -13,6 +16,7 @@ use esp_idf_svc::mdns::EspMdns;
 use esp_idf_sys::{vTaskDelay, xTaskGetCurrentTaskHandle, TaskHandle_t};
 use futures_lite::future::block_on;
 use hyper::server::conn::Http;
+use smol::lock::Semaphore;
 
 use super::{
     exec::Esp32Executor,
@@ -28,6 +32,7 @@ pub struct CloudConfig<'a> {
     robot_id: &'a str,
     robot_secret: &'a str,
     robot_tls_config: Option<Esp32TlsServerConfig>,
+    location_secrets: Vec<LocationSecret>,
 }
 
 impl<'a> CloudConfig<'a> {
@@ -45,25 +50,75 @@ impl<'a> CloudConfig<'a> {
             robot_id,
             robot_secret,
             robot_tls_config: None,
+            location_secrets: Vec::new(),
         }
     }
     pub fn set_tls_config(&mut self, tls_cfg: Esp32TlsServerConfig) {
         self.robot_tls_config = Some(tls_cfg)
     }
+    /// Set the location secrets clients can authenticate with besides the robot secret
+    pub fn set_location_secrets(&mut self, location_secrets: Vec<LocationSecret>) {
+        self.location_secrets = location_secrets
+    }
 }
 
+/// Default number of clients served at the same time
+static MAX_CONNECTIONS: usize = 2;
+/// Default number of concurrent HTTP/2 streams (i.e. RPCs) per client
+static MAX_CONCURRENT_STREAMS: u32 = 4;
+
 pub struct Esp32Server<'a> {
     robot: Arc<Mutex<LocalRobot>>,
     cloud_cfg: CloudConfig<'a>,
+    srv: GrpcServer,
+    max_connections: usize,
+    max_concurrent_streams: u32,
+    timeout: Option<Duration>,
+    method_timeouts: Vec<(String, Duration)>,
 }
 
 impl<'a> Esp32Server<'a> {
     pub fn new(robot: LocalRobot, cloud_cfg: CloudConfig<'a>) -> Self {
+        let robot = Arc::new(Mutex::new(robot));
+        let mut srv = GrpcServer::new(robot.clone());
+        let location_secrets = cloud_cfg
+            .location_secrets
+            .iter()
+            .map(|s| s.secret.clone())
+            .collect();
+        srv.set_authenticator(Authenticator::new(cloud_cfg.robot_secret, location_secrets));
         Esp32Server {
-            robot: Arc::new(Mutex::new(robot)),
+            srv,
+            robot,
             cloud_cfg,
+            max_connections: MAX_CONNECTIONS,
+            max_concurrent_streams: MAX_CONCURRENT_STREAMS,
+            timeout: None,
+            method_timeouts: Vec::new(),
         }
     }
+    /// Set how many clients can be connected at the same time, further clients wait for a
+    /// connection to close before being accepted
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections.max(1)
+    }
+    /// Set how many RPCs a single client can have in flight
+    pub fn set_max_concurrent_streams(&mut self, max_concurrent_streams: u32) {
+        self.max_concurrent_streams = max_concurrent_streams.max(1)
+    }
+    /// Set the server side timeout of RPCs, `None` lets them run until the client's deadline
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout
+    }
+    /// Set the server side timeout of the RPCs whose path starts with `prefix`, see
+    /// `Timeout::set_method_timeout`
+    pub fn set_method_timeout(&mut self, prefix: &str, timeout: Duration) {
+        self.method_timeouts.push((prefix.to_owned(), timeout))
+    }
+    /// Serve an additional RPC, see `GrpcServer::register_handler`
+    pub fn register_handler(&mut self, path: &str, handler: GrpcHandler) {
+        self.srv.register_handler(path, handler)
+    }
     pub fn start(&self, ip: Ipv4Addr) -> anyhow::Result<()> {
         let mut client_cfg = {
             RobotClientConfig::new(
@@ -115,7 +170,10 @@ impl<'a> Esp32Server<'a> {
         let address: SocketAddr = "0.0.0.0:4545".parse().unwrap();
         let mut listener = Esp32Listener::new(address.into(), Some(tls))?;
         let exec = Esp32Executor::new();
-        let srv = GrpcServer::new(self.robot.clone());
+        let mut srv = Timeout::new(self.srv.clone(), self.timeout);
+        for (prefix, timeout) in &self.method_timeouts {
+            srv.set_method_timeout(prefix, *timeout);
+        }
         if let Some(hnd) = client_handle {
             if unsafe { notify(hnd, 1) } {
                 log::info!("successfully notified client task");
@@ -128,18 +186,37 @@ impl<'a> Esp32Server<'a> {
         } else {
             log::error!("no handle")
         }
-        loop {
-            let stream = listener.accept()?;
-            block_on(exec.run(async {
-                let err = Http::new()
-                    .with_executor(exec.clone())
-                    .http2_max_concurrent_streams(1)
-                    .serve_connection(stream, srv.clone())
-                    .await;
-                if err.is_err() {
-                    log::error!("server error {}", err.err().unwrap());
-                }
-            }));
-        }
+        let slots = Arc::new(Semaphore::new(self.max_connections));
+        let max_concurrent_streams = self.max_concurrent_streams;
+        exec.spawn(safety_monitor(self.robot.clone())).detach();
+        block_on(exec.run(async {
+            loop {
+                let slot = slots.acquire_arc().await;
+                let conn = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        log::error!("failed to accept connection {:?}", e);
+                        continue;
+                    }
+                };
+                let mut http = Http::new().with_executor(exec.clone());
+                http.http2_max_concurrent_streams(max_concurrent_streams);
+                let srv = srv.clone();
+                exec.spawn(async move {
+                    let stream = match conn.handshake().await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            log::error!("TLS handshake failed {:?}", e);
+                            return;
+                        }
+                    };
+                    if let Err(e) = http.serve_connection(stream, srv).await {
+                        log::error!("server error {}", e);
+                    }
+                    drop(slot);
+                })
+                .detach();
+            }
+        }))
     }
 }
@@ -0,0 +1,81 @@
+#![allow(dead_code)]
+use crate::common::actuator::Actuator;
+use crate::common::generic::DoCommand;
+use crate::common::servo::{servo_status, Servo, ServoPulseConfig};
+use crate::common::status::Status;
+use embedded_hal::PwmPin;
+use std::time::{Duration, Instant};
+
+/// Hobby servos don't report their position, they are assumed to be moving for as long as
+/// turning at this speed takes (about 0.15s per 60 degrees)
+static ASSUMED_SPEED_DEG_PER_SEC: f64 = 400.0;
+
+/// Servo driven by a PWM signal (e.g. a LEDC channel), the PWM has to run at the frequency of
+/// the pulse configuration. No pulses are sent until the servo is first moved.
+pub struct Esp32Servo<PWM> {
+    pwm: PWM,
+    config: ServoPulseConfig,
+    position: u32,
+    moving_until: Option<Instant>,
+}
+
+impl<PWM> Esp32Servo<PWM>
+where
+    PWM: PwmPin<Duty = u32>,
+{
+    pub fn new(mut pwm: PWM, config: ServoPulseConfig) -> Self {
+        pwm.set_duty(0);
+        Esp32Servo {
+            pwm,
+            config,
+            position: 0,
+            moving_until: None,
+        }
+    }
+}
+
+impl<PWM> Servo for Esp32Servo<PWM>
+where
+    PWM: PwmPin<Duty = u32>,
+{
+    fn move_to(&mut self, angle_deg: u32) -> anyhow::Result<()> {
+        let duty = self.config.duty(angle_deg, self.pwm.get_max_duty())?;
+        self.pwm.set_duty(duty);
+        self.pwm.enable();
+        let travel = self.position.abs_diff(angle_deg) as f64 / ASSUMED_SPEED_DEG_PER_SEC;
+        self.moving_until = Some(Instant::now() + Duration::from_secs_f64(travel));
+        self.position = angle_deg;
+        Ok(())
+    }
+    fn get_position(&self) -> anyhow::Result<u32> {
+        Ok(self.position)
+    }
+}
+
+impl<PWM> Actuator for Esp32Servo<PWM>
+where
+    PWM: PwmPin<Duty = u32>,
+{
+    fn is_moving(&self) -> anyhow::Result<bool> {
+        Ok(self
+            .moving_until
+            .is_some_and(|until| Instant::now() < until))
+    }
+    /// Stop sending pulses, the servo stops holding its position
+    fn stop(&mut self) -> anyhow::Result<()> {
+        self.pwm.set_duty(0);
+        self.moving_until = None;
+        Ok(())
+    }
+}
+
+impl<PWM> DoCommand for Esp32Servo<PWM> where PWM: PwmPin<Duty = u32> {}
+
+impl<PWM> Status for Esp32Servo<PWM>
+where
+    PWM: PwmPin<Duty = u32>,
+{
+    fn get_status(&self) -> anyhow::Result<Option<prost_types::Struct>> {
+        Ok(Some(servo_status(self.position, self.is_moving()?)))
+    }
+}
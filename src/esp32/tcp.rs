@@ -1,8 +1,11 @@
 use crate::esp32::tls::{Esp32Tls, Esp32TlsStream};
 use futures_lite::io;
 use log::*;
+use smol::Async;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::cell::RefCell;
 use std::io::{Read, Write};
+use std::rc::Rc;
 use std::{
     marker::PhantomData,
     net::{Shutdown, TcpListener, TcpStream},
@@ -12,11 +15,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 /// Struct to listen for incoming TCP connections
 pub struct Esp32Listener {
-    listener: TcpListener,
+    listener: Async<TcpListener>,
     #[allow(dead_code)]
     addr: SockAddr,
     _marker: PhantomData<*const ()>,
-    tls: Option<Box<Esp32Tls>>,
+    tls: Option<Rc<RefCell<Esp32Tls>>>,
 }
 
 impl Esp32Listener {
@@ -28,27 +31,46 @@ impl Esp32Listener {
         socket.bind(&addr)?;
         socket.listen(128)?;
         Ok(Self {
-            listener: socket.into(),
+            listener: Async::new(socket.into())?,
             addr,
             _marker: PhantomData,
-            tls,
+            tls: tls.map(|tls| Rc::new(RefCell::new(*tls))),
         })
     }
 
-    /// Accept the next incoming connection. Will block until a connection is established
-    pub fn accept(&mut self) -> anyhow::Result<Esp32Stream> {
-        let (conn, _) = self.listener.accept()?;
+    /// Accept the next incoming connection, its TLS handshake (if any) is left to
+    /// `Esp32Connection::handshake` so it doesn't hold up the next accept
+    pub async fn accept(&mut self) -> anyhow::Result<Esp32Connection> {
+        let (conn, peer) = self.listener.accept().await?;
+        info!("Connected to {:?}", peer);
+        let conn = conn.into_inner()?;
         conn.set_nonblocking(true).expect("cannot set nodelay");
-        let stream = match &mut self.tls {
+        Ok(Esp32Connection {
+            conn,
+            tls: self.tls.clone(),
+        })
+    }
+}
+
+/// Connection accepted by an `Esp32Listener`, not yet usable before its handshake
+pub struct Esp32Connection {
+    conn: TcpStream,
+    tls: Option<Rc<RefCell<Esp32Tls>>>,
+}
+
+impl Esp32Connection {
+    /// Run the TLS handshake. esp-tls completes it before returning, the executor waits for it
+    /// but the accept loop and other connections aren't affected by a failed handshake.
+    pub async fn handshake(self) -> anyhow::Result<Esp32Stream> {
+        Ok(match self.tls {
             Some(tls) => {
                 info!("opening TLS ctx");
-                let stream = tls.open_ssl_context(Some(conn))?;
+                let stream = tls.borrow_mut().open_ssl_context(Some(self.conn))?;
                 info!("handshake done");
                 Esp32Stream::TLSStream(Box::new(stream))
             }
-            None => Esp32Stream::LocalPlain(conn),
-        };
-        Ok(stream)
+            None => Esp32Stream::LocalPlain(self.conn),
+        })
     }
 }
 
@@ -58,9 +80,22 @@ impl hyper::server::accept::Accept for Esp32Listener {
     type Error = io::Error;
     fn poll_accept(
         self: std::pin::Pin<&mut Self>,
-        _: &mut Context,
+        cx: &mut Context,
     ) -> std::task::Poll<Option<Result<Self::Conn, Self::Error>>> {
-        let (stream, peer) = self.listener.accept()?;
+        let (stream, peer) = loop {
+            match self.listener.poll_readable(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+            // another task may have taken the connection, poll_readable registers the waker
+            // for the next one
+            match self.listener.get_ref().accept() {
+                Ok(conn) => break conn,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        };
         info!("Connected to {:?}", peer);
         stream.set_nonblocking(true).expect("cannot set nodelay");
         let stream = Esp32Stream::LocalPlain(stream);
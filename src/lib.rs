@@ -1,13 +1,27 @@
 pub mod common {
+    pub mod actuator;
     pub mod analog;
+    pub mod auth;
     pub mod base;
     pub mod board;
     pub mod camera;
+    pub mod discovery;
+    pub mod encoder;
+    pub mod error;
+    pub mod frame;
+    pub mod gantry;
+    pub mod generic;
+    pub mod gripper;
     pub mod grpc;
     pub mod moisture_sensor;
     pub mod motor;
+    pub mod movement_sensor;
+    pub mod operation;
+    pub mod reflection;
     pub mod robot;
     pub mod sensor;
+    pub mod servo;
+    pub mod session;
     pub mod status;
 }
 
@@ -18,11 +32,15 @@ pub mod esp32 {
     pub mod board;
     #[cfg(feature = "camera")]
     pub mod camera;
+    pub mod digital_interrupt;
+    pub mod encoder;
     pub mod exec;
     pub mod motor;
     pub mod pin;
+    pub mod pwm;
     pub mod robot_client;
     pub mod server;
+    pub mod servo;
     pub mod tcp;
     pub mod tls;
 }
@@ -58,6 +76,23 @@ pub mod proto {
         }
     }
 
+    pub mod google {
+        pub mod rpc {
+            #![allow(clippy::derive_partial_eq_without_eq)]
+            #![allow(clippy::doc_overindented_list_items)]
+            include!("gen/google.rpc.rs");
+        }
+    }
+
+    pub mod grpc {
+        pub mod reflection {
+            pub mod v1alpha {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/grpc.reflection.v1alpha.rs");
+            }
+        }
+    }
+
     pub mod robot {
         pub mod v1 {
             #![allow(clippy::derive_partial_eq_without_eq)]
@@ -77,6 +112,12 @@ pub mod proto {
                 include!("gen/viam.component.motor.v1.rs");
             }
         }
+        pub mod movementsensor {
+            pub mod v1 {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/viam.component.movementsensor.v1.rs");
+            }
+        }
         pub mod camera {
             pub mod v1 {
                 #![allow(clippy::derive_partial_eq_without_eq)]
@@ -95,5 +136,29 @@ pub mod proto {
                 include!("gen/viam.component.sensor.v1.rs");
             }
         }
+        pub mod servo {
+            pub mod v1 {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/viam.component.servo.v1.rs");
+            }
+        }
+        pub mod gantry {
+            pub mod v1 {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/viam.component.gantry.v1.rs");
+            }
+        }
+        pub mod generic {
+            pub mod v1 {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/viam.component.generic.v1.rs");
+            }
+        }
+        pub mod gripper {
+            pub mod v1 {
+                #![allow(clippy::derive_partial_eq_without_eq)]
+                include!("gen/viam.component.gripper.v1.rs");
+            }
+        }
     }
 }
@@ -1,8 +1,10 @@
 use crate::native::tls::{NativeTls, NativeTlsStream};
 use futures_lite::io;
 use log::*;
+use smol::Async;
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use std::io::{Read, Write};
+use std::rc::Rc;
 use std::{
     marker::PhantomData,
     net::{Shutdown, TcpListener, TcpStream},
@@ -12,11 +14,11 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 /// Struct to listen for incoming TCP connections
 pub struct NativeListener {
-    listener: TcpListener,
+    listener: Async<TcpListener>,
     #[allow(dead_code)]
     addr: SockAddr,
     _marker: PhantomData<*const ()>,
-    tls: Option<Box<NativeTls>>,
+    tls: Option<Rc<NativeTls>>,
 }
 
 impl NativeListener {
@@ -28,27 +30,40 @@ impl NativeListener {
         socket.bind(&addr)?;
         socket.listen(128)?;
         Ok(Self {
-            listener: socket.into(),
+            listener: Async::new(socket.into())?,
             addr,
             _marker: PhantomData,
-            tls,
+            tls: tls.map(Rc::from),
         })
     }
 
-    /// Accept the next incoming connection. Will block until a connection is established
-    pub fn accept(&mut self) -> anyhow::Result<NativeStream> {
-        let (conn, _) = self.listener.accept()?;
+    /// Accept the next incoming connection, its TLS handshake (if any) is left to
+    /// `NativeConnection::handshake` so it doesn't hold up the next accept
+    pub async fn accept(&mut self) -> anyhow::Result<NativeConnection> {
+        let (conn, peer) = self.listener.accept().await?;
+        info!("Connected to {:?}", peer);
+        let conn = conn.into_inner()?;
         conn.set_nonblocking(true).expect("cannot set nodelay");
-        let stream = match &mut self.tls {
-            Some(tls) => {
-                info!("opening TLS ctx");
-                let stream = tls.open_ssl_context(Some(conn))?;
-                info!("handshake done");
-                NativeStream::TLSStream(Box::new(stream))
-            }
-            None => NativeStream::LocalPlain(conn),
-        };
-        Ok(stream)
+        Ok(NativeConnection {
+            conn,
+            tls: self.tls.clone(),
+        })
+    }
+}
+
+/// Connection accepted by a `NativeListener`, not yet usable before its handshake
+pub struct NativeConnection {
+    conn: TcpStream,
+    tls: Option<Rc<NativeTls>>,
+}
+
+impl NativeConnection {
+    /// Run the TLS handshake, awaiting the socket so other connections are served meanwhile
+    pub async fn handshake(self) -> anyhow::Result<NativeStream> {
+        Ok(match self.tls {
+            Some(tls) => NativeStream::TLSStream(Box::new(tls.accept(self.conn).await?)),
+            None => NativeStream::LocalPlain(self.conn),
+        })
     }
 }
 
@@ -58,9 +73,22 @@ impl hyper::server::accept::Accept for NativeListener {
     type Error = io::Error;
     fn poll_accept(
         self: std::pin::Pin<&mut Self>,
-        _: &mut Context,
+        cx: &mut Context,
     ) -> std::task::Poll<Option<Result<Self::Conn, Self::Error>>> {
-        let (stream, peer) = self.listener.accept()?;
+        let (stream, peer) = loop {
+            match self.listener.poll_readable(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+            // another task may have taken the connection, poll_readable registers the waker
+            // for the next one
+            match self.listener.get_ref().accept() {
+                Ok(conn) => break conn,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Some(Err(e))),
+            }
+        };
         info!("Connected to {:?}", peer);
         stream.set_nonblocking(true).expect("cannot set nodelay");
         let stream = NativeStream::LocalPlain(stream);
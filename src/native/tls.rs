@@ -8,6 +8,7 @@ use rustls::{
     ClientConfig, ClientConnection, OwnedTrustAnchor, RootCertStore, ServerConfig,
     ServerConnection, StreamOwned,
 };
+use smol::Async;
 
 /// structure to store tls configuration
 pub struct NativeTls {
@@ -35,6 +36,31 @@ impl NativeTlsServerConfig {
     pub fn new(srv_cert: Vec<u8>, srv_key: Vec<u8>) -> Self {
         NativeTlsServerConfig { srv_cert, srv_key }
     }
+    /// rustls configuration of a server presenting the certificate and key
+    fn server_config(&self) -> anyhow::Result<Arc<ServerConfig>> {
+        let cert_chain = rustls_pemfile::certs(&mut BufReader::new(self.srv_cert.as_slice()))
+            .unwrap()
+            .iter()
+            .map(|c| rustls::Certificate(c.clone()))
+            .collect();
+        let cert_key = match rustls_pemfile::read_one(&mut BufReader::new(self.srv_key.as_slice()))
+            .expect("cannot parse private key pem file")
+        {
+            Some(rustls_pemfile::Item::RSAKey(key)) => rustls::PrivateKey(key),
+            Some(rustls_pemfile::Item::PKCS8Key(key)) => rustls::PrivateKey(key),
+            Some(rustls_pemfile::Item::ECKey(key)) => rustls::PrivateKey(key),
+            None => return Err(anyhow::anyhow!("private key couldn't be parsed")),
+            _ => return Err(anyhow::anyhow!("unexpected private key type")),
+        };
+        let mut cfg = ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS12])?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, cert_key)?;
+        cfg.alpn_protocols = vec!["h2".as_bytes().to_vec()];
+        Ok(Arc::new(cfg))
+    }
 }
 
 impl NativeTls {
@@ -54,6 +80,35 @@ impl NativeTls {
     pub fn open_ssl_context(&self, socket: Option<TcpStream>) -> anyhow::Result<NativeTlsStream> {
         NativeTlsStream::new(socket, &self.server_config)
     }
+
+    /// Accept a TLS connection from a client on `socket`. The handshake waits for the socket to
+    /// be ready instead of blocking, other tasks of the executor keep running meanwhile.
+    pub async fn accept(&self, socket: TcpStream) -> anyhow::Result<NativeTlsStream> {
+        let tls_cfg = self
+            .server_config
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("TLS isn't configured as a server"))?;
+        let mut conn = ServerConnection::new(tls_cfg.server_config()?)?;
+        let mut socket = Async::new(socket)?;
+        while conn.is_handshaking() {
+            match conn.complete_io(socket.get_mut()) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    if conn.wants_write() {
+                        socket.writable().await?;
+                    } else {
+                        socket.readable().await?;
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        let stream = StreamOwned::new(conn, socket.into_inner()?);
+        Ok(NativeTlsStream {
+            socket: None,
+            stream: Box::new(NativeTlsStreamRole::Server(stream)),
+        })
+    }
 }
 
 /// Esp32TlsStream represents a properly established TLS connection to a server or a client. It can be use bye Esp32TCPStream since it
@@ -65,30 +120,7 @@ impl NativeTlsStream {
         tls_cfg: &Option<NativeTlsServerConfig>,
     ) -> anyhow::Result<Self> {
         let (stream, socket) = if let Some(tls_cfg) = tls_cfg {
-            let cert_chain =
-                rustls_pemfile::certs(&mut BufReader::new(tls_cfg.srv_cert.as_slice()))
-                    .unwrap()
-                    .iter()
-                    .map(|c| rustls::Certificate(c.clone()))
-                    .collect();
-            let cert_key =
-                match rustls_pemfile::read_one(&mut BufReader::new(tls_cfg.srv_key.as_slice()))
-                    .expect("cannot parse private key pem file")
-                {
-                    Some(rustls_pemfile::Item::RSAKey(key)) => rustls::PrivateKey(key),
-                    Some(rustls_pemfile::Item::PKCS8Key(key)) => rustls::PrivateKey(key),
-                    Some(rustls_pemfile::Item::ECKey(key)) => rustls::PrivateKey(key),
-                    None => return Err(anyhow::anyhow!("private key couldn't be parsed")),
-                    _ => return Err(anyhow::anyhow!("unexpected private key type")),
-                };
-            let mut cfg = ServerConfig::builder()
-                .with_safe_default_cipher_suites()
-                .with_safe_default_kx_groups()
-                .with_protocol_versions(&[&rustls::version::TLS12])?
-                .with_no_client_auth()
-                .with_single_cert(cert_chain, cert_key)?;
-            cfg.alpn_protocols = vec!["h2".as_bytes().to_vec()];
-            let mut conn = ServerConnection::new(Arc::new(cfg))?;
+            let mut conn = ServerConnection::new(tls_cfg.server_config()?)?;
             let mut socket = socket.unwrap();
             socket.set_nonblocking(false)?;
             let _r = conn.complete_io::<TcpStream>(&mut socket).unwrap();